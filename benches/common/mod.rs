@@ -0,0 +1,49 @@
+// Helpers shared by the benchmarks, which are plain timing loops run with `cargo bench`. Under
+// `cargo test --benches` they're run once each as a smoke test.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use spz_rs::rng::{RandomSource, SplitMix64};
+use spz_rs::PackedGaussians;
+
+/// Whether the benchmark is run by `cargo bench` rather than as a smoke test
+pub fn benchmarking() -> bool {
+    std::env::args().any(|arg| arg == "--bench")
+}
+
+/// Mean time of a call to `f`, over enough calls to be stable when benchmarking
+pub fn time<T, F: FnMut() -> T>(mut f: F) -> Duration {
+    let repeats = if benchmarking() { 20 } else { 1 };
+    let start = Instant::now();
+    for _ in 0..repeats {
+        black_box(f());
+    }
+    start.elapsed() / repeats
+}
+
+/// Number of splats to decode, a million when benchmarking
+pub fn num_points() -> usize {
+    if benchmarking() { 1_000_000 } else { 1_000 }
+}
+
+/// Cloud of splats with random bytes in every field, so every decode path is exercised
+pub fn random_cloud(num_points: usize, sh_degree: usize) -> PackedGaussians {
+    let mut rng = SplitMix64::new(0x5053_474e);
+    let mut bytes = |count: usize| -> Vec<u8> { (0..count).map(|_| rng.next_u64() as u8).collect() };
+    PackedGaussians {
+        num_points,
+        sh_degree,
+        fractional_bits: 12,
+        antialiased: false,
+        positions: bytes(num_points * 9),
+        scales: bytes(num_points * 3),
+        rotations: bytes(num_points * 3),
+        alphas: bytes(num_points),
+        colors: bytes(num_points * 3),
+        sh: bytes(num_points * ((sh_degree + 1) * (sh_degree + 1) - 1) * 3),
+        attributes: Default::default(),
+        position_encoding: Default::default(),
+        coordinate_system: Default::default(),
+    }
+}
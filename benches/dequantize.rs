@@ -0,0 +1,66 @@
+// Times the scalar dequantization functions in a loop against the batch kernels which replace
+// them in unpack_all, for each field of a cloud of random splats:
+//
+//     cargo bench --bench dequantize
+
+mod common;
+
+use std::time::Duration;
+
+use common::{num_points, random_cloud, time};
+use spz_rs::codec;
+
+fn report(name: &str, values: usize, scalar: Duration, batch: Duration) {
+    let per_value = |d: Duration| d.as_secs_f64() * 1e9 / values as f64;
+    println!("{:14} {:>8.2}ns {:>8.2}ns {:>6.1}x", name, per_value(scalar), per_value(batch),
+        scalar.as_secs_f64() / batch.as_secs_f64());
+}
+
+// Generic rather than taking function pointers so the scalar decoder is inlined, as it is in a
+// hand written loop
+fn compare(name: &str, bytes: &[u8], scalar: impl Fn(u8) -> f32, batch: impl Fn(&[u8], &mut [f32])) {
+    let mut out = vec![0.0; bytes.len()];
+    let scalar = time(|| out.iter_mut().zip(bytes).for_each(|(x, &b)| *x = scalar(b)));
+    let batch = time(|| batch(bytes, &mut out));
+    report(name, bytes.len(), scalar, batch);
+}
+
+fn main() {
+    let cloud = random_cloud(num_points(), 3);
+    println!("{} splats, time per decoded value", cloud.num_points);
+    println!("{:14} {:>10} {:>10} {:>7}", "field", "scalar", "batch", "speedup");
+
+    compare("scales", &cloud.scales, codec::unquantize_scale, codec::unquantize_scale_batch);
+    compare("alphas", &cloud.alphas, codec::unquantize_alpha, codec::unquantize_alpha_batch);
+    compare("alphas (det.)", &cloud.alphas, codec::unquantize_alpha_deterministic, codec::unquantize_alpha_deterministic_batch);
+    compare("colors", &cloud.colors, codec::unquantize_color, codec::unquantize_color_batch);
+    compare("sh", &cloud.sh, codec::unquantize_sh, codec::unquantize_sh_batch);
+
+    let mut out = vec![0.0; cloud.num_points * 4];
+    let scalar = time(|| {
+        for (q, b) in out.chunks_exact_mut(4).zip(cloud.rotations.chunks_exact(3)) {
+            q.copy_from_slice(&codec::decode_quat3(b));
+        }
+    });
+    let batch = time(|| codec::decode_quat3_batch(&cloud.rotations, &mut out));
+    report("rotations", out.len(), scalar, batch);
+
+    let mut out = vec![0.0; cloud.num_points * 3];
+    let scalar = time(|| {
+        for (x, b) in out.iter_mut().zip(cloud.positions.chunks_exact(3)) {
+            *x = codec::decode_fixed24(b, 12);
+        }
+    });
+    let batch = time(|| codec::decode_fixed24_batch(&cloud.positions, 12, &mut out));
+    report("fixed24", out.len(), scalar, batch);
+
+    // The position bytes are as good a source of random halves as any
+    let mut out = vec![0.0; cloud.positions.len() / 2];
+    let scalar = time(|| {
+        for (x, b) in out.iter_mut().zip(cloud.positions.chunks_exact(2)) {
+            *x = codec::half_to_f32(u16::from_le_bytes([b[0], b[1]]));
+        }
+    });
+    let batch = time(|| codec::half_to_f32_batch(&cloud.positions, &mut out));
+    report("half_to_f32", out.len(), scalar, batch);
+}
@@ -0,0 +1,21 @@
+// Times decoding a cloud of random splats one splat at a time and with unpack_all. Run it with and
+// without `--features parallel` to compare single and multi threaded decoding:
+//
+//     cargo bench --bench unpack
+//     cargo bench --bench unpack --features parallel
+
+mod common;
+
+use common::{num_points, random_cloud, time};
+
+fn main() {
+    let cloud = random_cloud(num_points(), 3);
+    println!("{} splats at SH degree {}", cloud.num_points, cloud.sh_degree);
+
+    let per_splat = time(|| (0..cloud.num_points).map(|i| cloud.unpack(i)).collect::<Vec<_>>());
+    println!("unpack:     {:?}", per_splat);
+
+    let bulk = time(|| cloud.unpack_all());
+    let mode = if cfg!(feature = "parallel") { "parallel" } else { "single threaded" };
+    println!("unpack_all: {:?} ({}, {:.1}x)", bulk, mode, per_splat.as_secs_f64() / bulk.as_secs_f64());
+}
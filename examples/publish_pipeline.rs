@@ -0,0 +1,43 @@
+// Publishes a folder of .spz or .ply captures as a tiled, multi resolution dataset ready to be
+// served to a web viewer, along with a JSONL log of everything that was done.
+//
+// Usage: publish_pipeline INPUT_DIR OUTPUT_DIR [TILE_SIZE]
+
+use std::env;
+use std::io;
+use std::process;
+
+use spz_rs::events::EventLog;
+use spz_rs::publish::{publish_folder, PublishOptions};
+
+fn main() -> Result<(), io::Error> {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 3 {
+        eprintln!("Error: No folders provided. Usage {} INPUT_DIR OUTPUT_DIR [TILE_SIZE]", args[0]);
+        process::exit(-1);
+    }
+
+    let mut options = PublishOptions::default();
+    if let Some(tile_size) = args.get(3) {
+        options.tile_size = tile_size.parse().unwrap_or_else(|_| {
+            eprintln!("Error: Tile size should be a number");
+            process::exit(-1);
+        });
+    }
+
+    std::fs::create_dir_all(&args[2])?;
+    let mut log = EventLog::open(&format!("{}/publish_log.jsonl", args[2]))?;
+    let manifest = publish_folder(&args[1], &args[2], &options, Some(&mut log))?;
+
+    for asset in &manifest.assets {
+        println!("{}", asset.name);
+        for (i, lod) in asset.lods.iter().enumerate() {
+            let num_points: usize = lod.tiles.iter().map(|t| t.num_points).sum();
+            println!("  LOD {}: {} tiles, {} splats", i, lod.tiles.len(), num_points);
+        }
+    }
+    println!("Wrote manifest to {}/{}", args[2], spz_rs::publish::MANIFEST_FILENAME);
+
+    Ok(())
+}
@@ -3,8 +3,6 @@ use std::env;
 use std::io;
 use std::process;
 
-use spz_rs;
-
 fn main() -> Result<(), io::Error> {
     let args: Vec<String> = env::args().collect();
 
@@ -0,0 +1,62 @@
+// Times loading a file and decoding all of its splats, one at a time and in bulk, and compares
+// the scalar and batch decoders for each field. Run it with and without `--features parallel` to
+// compare single and multi threaded decoding.
+//
+// Usage: unpack_benchmark FILENAME [REPEATS]
+
+use std::env;
+use std::io;
+use std::process;
+use std::time::{Duration, Instant};
+
+use spz_rs::codec;
+
+fn time<T, F: FnMut() -> T>(repeats: u32, mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..repeats {
+        std::hint::black_box(f());
+    }
+    start.elapsed() / repeats
+}
+
+// Generic rather than taking function pointers so the scalar decoder is inlined, as it is in a
+// hand written loop
+fn compare(repeats: u32, name: &str, bytes: &[u8], scalar: impl Fn(u8) -> f32, batch: impl Fn(&[u8], &mut [f32])) {
+    let mut out = vec![0.0; bytes.len()];
+    let scalar = time(repeats, || out.iter_mut().zip(bytes).for_each(|(x, &b)| *x = scalar(b)));
+    let batch = time(repeats, || batch(bytes, &mut out));
+    println!("{:10} {:>12?} {:>12?}", name, scalar, batch);
+}
+
+fn main() -> Result<(), io::Error> {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 2 {
+        eprintln!("Error: No filename provided. Usage {} FILENAME [REPEATS]", args[0]);
+        process::exit(-1);
+    }
+
+    let filename = &args[1];
+    let repeats = args.get(2).and_then(|r| r.parse().ok()).unwrap_or(5);
+
+    let contents = std::fs::read(filename)?;
+    let load = time(repeats, || spz_rs::load_packed_gaussians_from_spz_buffer(&contents[..]));
+    let packed_gaussians = spz_rs::load_packed_gaussians_from_spz_buffer(&contents[..])?;
+    println!("File contains {} gaussians", packed_gaussians.num_points);
+    println!("Load:       {:?}", load);
+
+    let per_splat = time(repeats, || (0..packed_gaussians.num_points).map(|i| packed_gaussians.unpack(i)).collect::<Vec<_>>());
+    println!("unpack:     {:?}", per_splat);
+
+    let bulk = time(repeats, || packed_gaussians.unpack_all());
+    println!("unpack_all: {:?} ({})", bulk, if cfg!(feature = "parallel") { "parallel" } else { "single threaded" });
+
+    println!();
+    println!("{:10} {:>12} {:>12}", "field", "scalar", "batch");
+    compare(repeats, "scales", &packed_gaussians.scales, codec::unquantize_scale, codec::unquantize_scale_batch);
+    compare(repeats, "alphas", &packed_gaussians.alphas, codec::unquantize_alpha, codec::unquantize_alpha_batch);
+    compare(repeats, "colors", &packed_gaussians.colors, codec::unquantize_color, codec::unquantize_color_batch);
+    compare(repeats, "sh", &packed_gaussians.sh, codec::unquantize_sh, codec::unquantize_sh_batch);
+
+    Ok(())
+}
@@ -0,0 +1,23 @@
+// Loads arbitrary bytes as a gzipped .spz file, through both the blocking and the push based
+// decoders. Neither may panic, and the decompression limit keeps a small input from expanding
+// into a large cloud. Anything which loads has to decode too.
+//
+//     cargo +nightly fuzz run load
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use spz_rs::incremental::SpzDecoder;
+use spz_rs::{load_packed_gaussians_from_bytes_with, LoadOptions};
+
+fuzz_target!(|data: &[u8]| {
+    let options = LoadOptions::default().max_decompressed_bytes(1 << 24);
+    if let Ok(cloud) = load_packed_gaussians_from_bytes_with(data, &options) {
+        let _ = cloud.unpack_all();
+    }
+
+    // Chunk boundaries are where the push based decoder keeps its state, so split the input
+    let mut decoder = SpzDecoder::new_with(&options);
+    let split = data.first().map_or(0, |&b| b as usize).min(data.len());
+    let _ = decoder.push(&data[..split]).and_then(|()| decoder.push(&data[split..])).and_then(|()| decoder.finish());
+});
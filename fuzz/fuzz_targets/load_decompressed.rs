@@ -0,0 +1,24 @@
+// Loads arbitrary bytes as a decompressed .spz payload, both copied into a cloud and borrowed
+// through a view, which skips gzip so the fuzzer reaches the header and field handling
+// directly.
+//
+//     cargo +nightly fuzz run load_decompressed
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use spz_rs::view::PackedGaussiansView;
+use spz_rs::{load_packed_gaussians_from_decompressed_buffer_with, LoadOptions, UnpackOptions};
+
+fuzz_target!(|data: &[u8]| {
+    let options = LoadOptions::default().max_decompressed_bytes(1 << 24);
+    if let Ok(cloud) = load_packed_gaussians_from_decompressed_buffer_with(data, &options) {
+        let _ = cloud.unpack_all_with(&UnpackOptions::default().deterministic(true));
+        let _ = cloud.compute_bounds();
+    }
+    if let Ok(view) = PackedGaussiansView::from_decompressed_bytes(data) {
+        for i in 0..view.num_points.min(16) {
+            let _ = view.unpack(i);
+        }
+    }
+});
@@ -0,0 +1,54 @@
+/* C interface to spz_rs, built with
+ *
+ *     cargo rustc --release --lib --features capi --crate-type staticlib --crate-type cdylib
+ *
+ * Link against libspz_rs.a or the shared library from target/release. Kept in step with
+ * src/capi.rs, which checks every declaration here in its tests. */
+
+#ifndef SPZ_RS_H
+#define SPZ_RS_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque handle to a loaded cloud, released with spz_free */
+typedef struct SpzCloud SpzCloud;
+
+/* Message for the last failure on this thread, or NULL. Valid until the next failing call. */
+const char *spz_last_error(void);
+
+/* Load a .spz file from disk or memory, returning NULL on failure */
+SpzCloud *spz_load_file(const char *path);
+SpzCloud *spz_load_bytes(const uint8_t *data, size_t len);
+
+/* Save a cloud as a .spz file, returning 0 on success */
+int32_t spz_save_file(const SpzCloud *cloud, const char *path);
+
+/* Release a cloud. NULL is ignored. */
+void spz_free(SpzCloud *cloud);
+
+size_t spz_get_num_points(const SpzCloud *cloud);
+size_t spz_get_sh_degree(const SpzCloud *cloud);
+bool spz_is_antialiased(const SpzCloud *cloud);
+
+/* Copy a decoded attribute into out. Each returns the number of floats the attribute needs and
+ * only writes them if capacity is at least that many, so pass NULL first to size the array. */
+size_t spz_unpack_all_positions(const SpzCloud *cloud, float *out, size_t capacity);
+size_t spz_unpack_all_rotations(const SpzCloud *cloud, float *out, size_t capacity);
+size_t spz_unpack_all_scales(const SpzCloud *cloud, float *out, size_t capacity);
+size_t spz_unpack_all_colors(const SpzCloud *cloud, float *out, size_t capacity);
+size_t spz_unpack_all_alphas(const SpzCloud *cloud, float *out, size_t capacity);
+size_t spz_unpack_all_sh(const SpzCloud *cloud, float *out, size_t capacity);
+/* Six floats per splat, xx, xy, xz, yy, yz, zz */
+size_t spz_unpack_all_covariances(const SpzCloud *cloud, float *out, size_t capacity);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,201 @@
+// Custom per-splat attribute channels. These are carried alongside a cloud in memory and follow
+// the splats through operations which reorder or subset them, but aren't part of the .spz format
+// so they're not written out or read back in with the cloud.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::PackedGaussians;
+
+/// Name of the channel holding per-splat capture timestamps, in seconds
+pub const TIMESTAMP_ATTRIBUTE: &str = "timestamp";
+
+/// Name of the channel holding per-splat material ids, used by hybrid renderers to route splats
+/// to different passes
+pub const MATERIAL_ATTRIBUTE: &str = "material";
+
+/// Material id assumed for splats in clouds without a material channel
+pub const DEFAULT_MATERIAL: u32 = 0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttributeData {
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+    U32(Vec<u32>),
+}
+
+impl AttributeData {
+    pub fn len(&self) -> usize {
+        match self {
+            AttributeData::F32(v) => v.len(),
+            AttributeData::F64(v) => v.len(),
+            AttributeData::U32(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn select(&self, indices: &[usize]) -> AttributeData {
+        match self {
+            AttributeData::F32(v) => AttributeData::F32(indices.iter().map(|&i| v[i]).collect()),
+            AttributeData::F64(v) => AttributeData::F64(indices.iter().map(|&i| v[i]).collect()),
+            AttributeData::U32(v) => AttributeData::U32(indices.iter().map(|&i| v[i]).collect()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CustomAttributes {
+    channels: Vec<(String, AttributeData)>,
+}
+
+impl CustomAttributes {
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.channels.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AttributeData> {
+        self.channels.iter().find(|(n, _)| n == name).map(|(_, data)| data)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut AttributeData> {
+        self.channels.iter_mut().find(|(n, _)| n == name).map(|(_, data)| data)
+    }
+
+    /// Adds a channel, replacing any existing channel with the same name
+    pub fn insert(&mut self, name: &str, data: AttributeData) {
+        match self.get_mut(name) {
+            Some(existing) => *existing = data,
+            None => self.channels.push((name.to_string(), data)),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<AttributeData> {
+        let index = self.channels.iter().position(|(n, _)| n == name)?;
+        Some(self.channels.remove(index).1)
+    }
+
+    pub fn select(&self, indices: &[usize]) -> CustomAttributes {
+        CustomAttributes {
+            channels: self.channels.iter()
+                .map(|(name, data)| (name.clone(), data.select(indices)))
+                .collect(),
+        }
+    }
+
+    /// Channels of several clouds laid end to end. Only channels which every cloud has, with the
+    /// same type, are kept.
+    pub(crate) fn concat(parts: &[&CustomAttributes]) -> CustomAttributes {
+        let Some((first, rest)) = parts.split_first() else {
+            return CustomAttributes::default();
+        };
+        let mut channels = Vec::new();
+        for (name, data) in &first.channels {
+            let mut combined = data.clone();
+            let complete = rest.iter().all(|part| match (&mut combined, part.get(name)) {
+                (AttributeData::F32(a), Some(AttributeData::F32(b))) => { a.extend_from_slice(b); true }
+                (AttributeData::F64(a), Some(AttributeData::F64(b))) => { a.extend_from_slice(b); true }
+                (AttributeData::U32(a), Some(AttributeData::U32(b))) => { a.extend_from_slice(b); true }
+                _ => false,
+            });
+            if complete {
+                channels.push((name.clone(), combined));
+            }
+        }
+        CustomAttributes { channels }
+    }
+
+    /// Appends a zero to every channel, for a splat added without attribute values. Zero is also
+    /// [`DEFAULT_MATERIAL`].
+    pub(crate) fn push_zeros(&mut self) {
+        for (_, data) in &mut self.channels {
+            match data {
+                AttributeData::F32(v) => v.push(0.0),
+                AttributeData::F64(v) => v.push(0.0),
+                AttributeData::U32(v) => v.push(0),
+            }
+        }
+    }
+}
+
+impl PackedGaussians {
+    /// Attaches a custom attribute channel, which must have one value per splat
+    pub fn set_attribute(&mut self, name: &str, data: AttributeData) -> Result<(), io::Error> {
+        if data.len() != self.num_points {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("Attribute '{}' has {} values but the cloud has {} splats", name, data.len(), self.num_points)));
+        }
+        self.attributes.insert(name, data);
+        Ok(())
+    }
+
+    pub fn set_timestamps(&mut self, timestamps: Vec<f64>) -> Result<(), io::Error> {
+        self.set_attribute(TIMESTAMP_ATTRIBUTE, AttributeData::F64(timestamps))
+    }
+
+    pub fn timestamps(&self) -> Option<&[f64]> {
+        match self.attributes.get(TIMESTAMP_ATTRIBUTE) {
+            Some(AttributeData::F64(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Earliest and latest capture timestamps
+    pub fn time_range(&self) -> Option<(f64, f64)> {
+        let timestamps = self.timestamps()?;
+        if timestamps.is_empty() {
+            return None;
+        }
+        Some(timestamps.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &t| (min.min(t), max.max(t))))
+    }
+
+    /// Returns the splats captured in the half open window [start, end). A cloud without a
+    /// timestamp channel is treated as static and returned whole.
+    pub fn filter_time_range(&self, start: f64, end: f64) -> PackedGaussians {
+        match self.timestamps() {
+            Some(timestamps) => {
+                let indices: Vec<usize> = timestamps.iter().enumerate()
+                    .filter(|(_, &t)| t >= start && t < end)
+                    .map(|(i, _)| i)
+                    .collect();
+                self.select(&indices)
+            }
+            None => self.clone(),
+        }
+    }
+
+    pub fn set_material_ids(&mut self, material_ids: Vec<u32>) -> Result<(), io::Error> {
+        self.set_attribute(MATERIAL_ATTRIBUTE, AttributeData::U32(material_ids))
+    }
+
+    pub fn material_ids(&self) -> Option<&[u32]> {
+        match self.attributes.get(MATERIAL_ATTRIBUTE) {
+            Some(AttributeData::U32(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Splits the cloud into one cloud per material id, in increasing id order. Splats keep their
+    /// relative order within each partition. A cloud without a material channel is returned
+    /// whole as [`DEFAULT_MATERIAL`].
+    pub fn partition_by_material(&self) -> Vec<(u32, PackedGaussians)> {
+        let material_ids = match self.material_ids() {
+            Some(ids) => ids,
+            None => return vec![(DEFAULT_MATERIAL, self.clone())],
+        };
+
+        let mut partitions: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+        for (i, &id) in material_ids.iter().enumerate() {
+            partitions.entry(id).or_default().push(i);
+        }
+        partitions.into_iter()
+            .map(|(id, indices)| (id, self.select(&indices)))
+            .collect()
+    }
+}
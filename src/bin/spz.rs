@@ -0,0 +1,378 @@
+// Command line tool for inspecting, fixing and batch processing .spz files
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use spz_rs::concat::{merge_files, MergeOptions};
+use spz_rs::diagnostics::{Diagnosis, Fix};
+use spz_rs::dump::{save_dump_to_file, Dump, DumpOptions};
+use spz_rs::events::EventLog;
+use spz_rs::ops::{OpRegistry, OpStep};
+use spz_rs::ply::{export_to_ply_file, load_gaussians_from_ply_file};
+use spz_rs::{LoadOptions, PackedGaussians};
+
+fn usage() -> ! {
+    eprintln!("Usage: spz doctor FILE [--clamp-needles] [--remove-floaters] [--remove-transparent] [--reset-zero-rotations] [--z-up-to-y-up] [--drop-sh] [--output FILE] [--log FILE]");
+    eprintln!("       spz apply FILE --op NAME[:KEY=VALUE,...]... --output FILE [--log FILE]");
+    eprintln!("       spz dump FILE [--count N] [--output FILE]");
+    eprintln!("       spz info FILE");
+    eprintln!("       spz convert INPUT OUTPUT");
+    eprintln!("       spz validate FILE...");
+    eprintln!("       spz compare REFERENCE FILE");
+    eprintln!("       spz prune FILE [--max-alpha A] [--max-scale S] [--min X,Y,Z] [--max X,Y,Z] --output FILE");
+    eprintln!("       spz merge FILE... [--sh-degree N] [--fractional-bits N] --output FILE");
+    eprintln!("       spz ops");
+    eprintln!("       spz self-test");
+    process::exit(2);
+}
+
+fn is_ply(filename: &str) -> bool {
+    Path::new(filename).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ply"))
+}
+
+/// Loads a .spz or .ply file, going by the extension
+fn load(filename: &str) -> Result<PackedGaussians, std::io::Error> {
+    if is_ply(filename) {
+        load_gaussians_from_ply_file(filename)
+    } else {
+        Ok(spz_rs::load_packed_gaussians_from_file(filename)?)
+    }
+}
+
+fn save(gaussians: &PackedGaussians, filename: &str) -> Result<(), std::io::Error> {
+    if is_ply(filename) {
+        export_to_ply_file(gaussians, filename)
+    } else {
+        Ok(spz_rs::save_packed_gaussians_to_file(gaussians, filename)?)
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(value: Option<&String>) -> T {
+    value.and_then(|value| value.parse().ok()).unwrap_or_else(|| usage())
+}
+
+fn parse_point(value: Option<&String>) -> [f32; 3] {
+    let values: Vec<f32> = value
+        .map(|value| value.split(',').map(|x| x.trim().parse().unwrap_or_else(|_| usage())).collect())
+        .unwrap_or_else(|| usage());
+    values.try_into().unwrap_or_else(|_| usage())
+}
+
+fn print_report(filename: &str, diagnosis: &Diagnosis) {
+    println!("{}: {} splats, SH degree {}", filename, diagnosis.num_points, diagnosis.sh_degree);
+    if let Some((min, max)) = diagnosis.bounds {
+        println!("  bounds: [{:.3}, {:.3}, {:.3}] to [{:.3}, {:.3}, {:.3}]",
+            min[0], min[1], min[2], max[0], max[1], max[2]);
+    }
+    let s = &diagnosis.scale_stats;
+    println!("  splat size: min {:.4}, median {:.4}, p99 {:.4}, max {:.4}", s.min, s.median, s.p99, s.max);
+    let z = &diagnosis.sizes;
+    println!("  payload: {} bytes (positions {}, alphas {}, colors {}, scales {}, rotations {}, sh {})",
+        z.total(), z.positions, z.alphas, z.colors, z.scales, z.rotations, z.sh);
+
+    if diagnosis.findings.is_empty() {
+        println!("No problems found");
+        return;
+    }
+    println!();
+    for finding in &diagnosis.findings {
+        println!("{}: {}", finding.severity, finding.message);
+        if let Some(suggestion) = &finding.suggestion {
+            println!("  {}", suggestion);
+        }
+        if let Some(fix) = finding.fix {
+            println!("  fix with {}", fix.flag());
+        }
+    }
+}
+
+fn doctor(args: &[String]) -> Result<i32, std::io::Error> {
+    let mut filename = None;
+    let mut output = None;
+    let mut log = None;
+    let mut fixes = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--output" {
+            output = Some(args.next().unwrap_or_else(|| usage()).clone());
+        } else if arg == "--log" {
+            log = Some(EventLog::open(args.next().unwrap_or_else(|| usage()))?);
+        } else if let Some(fix) = Fix::from_flag(arg) {
+            fixes.push(fix);
+        } else if arg.starts_with("--") || filename.is_some() {
+            usage();
+        } else {
+            filename = Some(arg.clone());
+        }
+    }
+    let filename = filename.unwrap_or_else(|| usage());
+    if !fixes.is_empty() && output.is_none() {
+        eprintln!("Error: fixes need an --output file to write to");
+        return Ok(2);
+    }
+
+    let mut gaussians = match &mut log {
+        Some(log) => log.load_file(&filename)?,
+        None => load(&filename)?,
+    };
+    let diagnosis = gaussians.diagnose();
+    print_report(&filename, &diagnosis);
+    if diagnosis.has_errors() {
+        return Ok(1);
+    }
+
+    if let Some(output) = output {
+        println!();
+        for fix in fixes {
+            let count = gaussians.apply_fix(fix);
+            println!("{}: {} splats", fix.flag(), count);
+            if let Some(log) = &mut log {
+                log.operation_applied(fix.flag().trim_start_matches("--"), count)?;
+            }
+        }
+        match &mut log {
+            Some(log) => log.save_file(&gaussians, &output)?,
+            None => save(&gaussians, &output)?,
+        }
+        println!("Wrote {} splats to {}", gaussians.num_points, output);
+    }
+    Ok(0)
+}
+
+fn apply(args: &[String], registry: &OpRegistry) -> Result<i32, std::io::Error> {
+    let mut filename = None;
+    let mut output = None;
+    let mut log = None;
+    let mut steps = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--op" {
+            steps.push(OpStep::parse(args.next().unwrap_or_else(|| usage()))?);
+        } else if arg == "--output" {
+            output = Some(args.next().unwrap_or_else(|| usage()).clone());
+        } else if arg == "--log" {
+            log = Some(EventLog::open(args.next().unwrap_or_else(|| usage()))?);
+        } else if arg.starts_with("--") || filename.is_some() {
+            usage();
+        } else {
+            filename = Some(arg.clone());
+        }
+    }
+    let filename = filename.unwrap_or_else(|| usage());
+    let output = output.unwrap_or_else(|| usage());
+
+    let mut gaussians = match &mut log {
+        Some(log) => log.load_file(&filename)?,
+        None => load(&filename)?,
+    };
+    registry.run(&mut gaussians, &steps, log.as_mut())?;
+    match &mut log {
+        Some(log) => log.save_file(&gaussians, &output)?,
+        None => save(&gaussians, &output)?,
+    }
+    println!("Wrote {} splats to {}", gaussians.num_points, output);
+    Ok(0)
+}
+
+fn dump(args: &[String]) -> Result<i32, std::io::Error> {
+    let mut filename = None;
+    let mut output = None;
+    let mut options = DumpOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--count" {
+            let count = args.next().and_then(|count| count.parse().ok()).unwrap_or_else(|| usage());
+            options = options.max_splats(count);
+        } else if arg == "--output" {
+            output = Some(args.next().unwrap_or_else(|| usage()).clone());
+        } else if arg.starts_with("--") || filename.is_some() {
+            usage();
+        } else {
+            filename = Some(arg.clone());
+        }
+    }
+    let filename = filename.unwrap_or_else(|| usage());
+
+    let gaussians = spz_rs::load_packed_gaussians_from_file(&filename)?;
+    let dump = Dump::new(&gaussians, &options);
+    match output {
+        Some(output) => save_dump_to_file(&dump, &output)?,
+        None => println!("{}", dump.to_json()),
+    }
+    Ok(0)
+}
+
+fn info(args: &[String]) -> Result<i32, std::io::Error> {
+    let [filename] = args else { usage() };
+    let gaussians = load(filename)?;
+    let header = gaussians.header();
+    println!("{}", filename);
+    println!("  version: {}", header.version);
+    println!("  splats: {}", gaussians.num_points);
+    println!("  SH degree: {}", gaussians.sh_degree);
+    if gaussians.uses_float16() {
+        println!("  positions: float16");
+    } else {
+        println!("  positions: fixed point, {} fractional bits", gaussians.fractional_bits);
+    }
+    println!("  antialiased: {}", gaussians.antialiased);
+    println!("  coordinate system: {:?}", gaussians.coordinate_system);
+    if let Some((min, max)) = gaussians.compute_bounds() {
+        println!("  bounds: [{:.3}, {:.3}, {:.3}] to [{:.3}, {:.3}, {:.3}]",
+            min[0], min[1], min[2], max[0], max[1], max[2]);
+    }
+    if let (Some(c), Some(radius)) = (gaussians.centroid(), gaussians.bounding_radius()) {
+        println!("  centroid: [{:.3}, {:.3}, {:.3}], radius {:.3}", c[0], c[1], c[2], radius);
+    }
+    let z = gaussians.section_sizes();
+    println!("  payload: {} bytes (positions {}, alphas {}, colors {}, scales {}, rotations {}, sh {})",
+        z.total(), z.positions, z.alphas, z.colors, z.scales, z.rotations, z.sh);
+    Ok(0)
+}
+
+fn convert(args: &[String]) -> Result<i32, std::io::Error> {
+    let [input, output] = args else { usage() };
+    let gaussians = load(input)?;
+    save(&gaussians, output)?;
+    println!("Wrote {} splats to {}", gaussians.num_points, output);
+    Ok(0)
+}
+
+fn validate(args: &[String]) -> Result<i32, std::io::Error> {
+    if args.is_empty() {
+        usage();
+    }
+    let options = LoadOptions::default().strict(true);
+    let mut failed = 0;
+    for filename in args {
+        let result = if is_ply(filename) {
+            load_gaussians_from_ply_file(filename).and_then(|gaussians| Ok(gaussians.validate()?))
+        } else {
+            spz_rs::load_packed_gaussians_from_file_with(filename, &options).map(|_| ()).map_err(Into::into)
+        };
+        match result {
+            Ok(()) => println!("{}: ok", filename),
+            Err(error) => {
+                println!("{}: {}", filename, error);
+                failed += 1;
+            }
+        }
+    }
+    Ok(if failed == 0 { 0 } else { 1 })
+}
+
+fn compare(args: &[String]) -> Result<i32, std::io::Error> {
+    let [reference, filename] = args else { usage() };
+    let comparison = load(reference)?.compare(&load(filename)?)?;
+    println!("{} against {}: {}", filename, reference, comparison);
+    Ok(0)
+}
+
+fn prune(args: &[String]) -> Result<i32, std::io::Error> {
+    let mut filename = None;
+    let mut output = None;
+    let mut max_alpha = None;
+    let mut max_scale = None;
+    let mut min = [f32::NEG_INFINITY; 3];
+    let mut max = [f32::INFINITY; 3];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--max-alpha" {
+            max_alpha = Some(parse_value::<f32>(args.next()));
+        } else if arg == "--max-scale" {
+            max_scale = Some(parse_value::<f32>(args.next()));
+        } else if arg == "--min" {
+            min = parse_point(args.next());
+        } else if arg == "--max" {
+            max = parse_point(args.next());
+        } else if arg == "--output" {
+            output = Some(args.next().unwrap_or_else(|| usage()).clone());
+        } else if arg.starts_with("--") || filename.is_some() {
+            usage();
+        } else {
+            filename = Some(arg.clone());
+        }
+    }
+    let filename = filename.unwrap_or_else(|| usage());
+    let output = output.unwrap_or_else(|| usage());
+
+    let mut gaussians = load(&filename)?;
+    if let Some(max_alpha) = max_alpha {
+        println!("--max-alpha: removed {} splats", gaussians.prune_transparent(max_alpha));
+    }
+    if let Some(max_scale) = max_scale {
+        println!("--max-scale: removed {} splats", gaussians.prune_large(max_scale));
+    }
+    if min.iter().chain(&max).any(|x| x.is_finite()) {
+        println!("--min/--max: removed {} splats", gaussians.prune_outside(min, max));
+    }
+    save(&gaussians, &output)?;
+    println!("Wrote {} splats to {}", gaussians.num_points, output);
+    Ok(0)
+}
+
+fn merge(args: &[String]) -> Result<i32, std::io::Error> {
+    let mut filenames = Vec::new();
+    let mut output = None;
+    let mut options = MergeOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--sh-degree" {
+            options = options.sh_degree(parse_value(args.next()));
+        } else if arg == "--fractional-bits" {
+            options = options.fractional_bits(parse_value(args.next()));
+        } else if arg == "--output" {
+            output = Some(args.next().unwrap_or_else(|| usage()).clone());
+        } else if arg.starts_with("--") {
+            usage();
+        } else {
+            filenames.push(arg.as_str());
+        }
+    }
+    let output = output.unwrap_or_else(|| usage());
+    if filenames.is_empty() {
+        usage();
+    }
+
+    let gaussians = merge_files(&filenames, &options)?;
+    save(&gaussians, &output)?;
+    println!("Wrote {} splats from {} files to {}", gaussians.num_points, filenames.len(), output);
+    Ok(0)
+}
+
+fn list_ops(registry: &OpRegistry) -> i32 {
+    for op in registry.ops() {
+        println!("{:20} {}", op.name(), op.description());
+    }
+    0
+}
+
+fn self_test() -> i32 {
+    let report = spz_rs::selftest::self_test();
+    for failure in &report.failures {
+        println!("FAILED {} {}: expected {:?}, got {:?}", failure.check, failure.input, failure.expected, failure.actual);
+    }
+    println!("{} of {} checks passed", report.checks - report.failures.len(), report.checks);
+    if report.passed() { 0 } else { 1 }
+}
+
+fn main() -> Result<(), std::io::Error> {
+    let args: Vec<String> = env::args().collect();
+    let registry = OpRegistry::with_builtins();
+    let code = match args.get(1).map(String::as_str) {
+        Some("doctor") => doctor(&args[2..])?,
+        Some("apply") => apply(&args[2..], &registry)?,
+        Some("dump") => dump(&args[2..])?,
+        Some("info") => info(&args[2..])?,
+        Some("convert") => convert(&args[2..])?,
+        Some("validate") => validate(&args[2..])?,
+        Some("compare") => compare(&args[2..])?,
+        Some("prune") => prune(&args[2..])?,
+        Some("merge") => merge(&args[2..])?,
+        Some("ops") => list_ops(&registry),
+        Some("self-test") => self_test(),
+        _ => usage(),
+    };
+    process::exit(code);
+}
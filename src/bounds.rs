@@ -0,0 +1,88 @@
+// Spatial summaries of a cloud for camera setup, culling and level of detail selection. These
+// work on the packed positions directly: fixed point values decode monotonically, so bounds
+// are found on the raw integers and only the results are decoded, and the centroid is summed
+// exactly in integers. Results are in the cloud's own axis convention, its `coordinate_system`,
+// rather than converted to RUB as unpacking does.
+
+use crate::codec::{decode_fixed24, fixed_point_step, half_to_f32};
+use crate::PackedGaussians;
+
+fn fixed24(bytes: &[u8]) -> i32 {
+    // Sign extends the 24 bit value by shifting it into the top of an i32 and back
+    i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8
+}
+
+fn decode_raw(raw: i32, fractional_bits: usize) -> f32 {
+    let bytes = raw.to_le_bytes();
+    decode_fixed24(&bytes[..3], fractional_bits as u32)
+}
+
+impl PackedGaussians {
+    /// Axis aligned bounds of the splat centers, or None for an empty cloud
+    pub fn compute_bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        if self.num_points == 0 {
+            return None;
+        }
+
+        if self.uses_float16() {
+            let mut min = [f32::INFINITY; 3];
+            let mut max = [f32::NEG_INFINITY; 3];
+            for (j, half) in self.positions.chunks_exact(2).enumerate() {
+                let x = half_to_f32(u16::from_le_bytes([half[0], half[1]]));
+                min[j % 3] = min[j % 3].min(x);
+                max[j % 3] = max[j % 3].max(x);
+            }
+            return Some((min, max));
+        }
+
+        let mut min = [i32::MAX; 3];
+        let mut max = [i32::MIN; 3];
+        for (j, bytes) in self.positions.chunks_exact(3).enumerate() {
+            let x = fixed24(bytes);
+            min[j % 3] = min[j % 3].min(x);
+            max[j % 3] = max[j % 3].max(x);
+        }
+        Some((min.map(|x| decode_raw(x, self.fractional_bits)), max.map(|x| decode_raw(x, self.fractional_bits))))
+    }
+
+    /// Size of the bounds of the splat centers along each axis
+    pub fn extents(&self) -> Option<[f32; 3]> {
+        let (min, max) = self.compute_bounds()?;
+        Some([max[0] - min[0], max[1] - min[1], max[2] - min[2]])
+    }
+
+    /// Mean of the splat centers, or None for an empty cloud
+    pub fn centroid(&self) -> Option<[f32; 3]> {
+        if self.num_points == 0 {
+            return None;
+        }
+
+        let n = self.num_points as f64;
+        if self.uses_float16() {
+            let mut sum = [0.0f64; 3];
+            for (j, half) in self.positions.chunks_exact(2).enumerate() {
+                sum[j % 3] += half_to_f32(u16::from_le_bytes([half[0], half[1]])) as f64;
+            }
+            return Some(sum.map(|s| (s / n) as f32));
+        }
+
+        let mut sum = [0i64; 3];
+        for (j, bytes) in self.positions.chunks_exact(3).enumerate() {
+            sum[j % 3] += fixed24(bytes) as i64;
+        }
+        let step = fixed_point_step(self.fractional_bits as u32) as f64;
+        Some(sum.map(|s| (s as f64 / n * step) as f32))
+    }
+
+    /// Distance from the centroid to the furthest splat center
+    pub fn bounding_radius(&self) -> Option<f32> {
+        let c = self.centroid()?;
+        let max_squared = (0..self.num_points)
+            .map(|i| {
+                let p = self.unpack_position(i);
+                (p[0] - c[0]).powi(2) + (p[1] - c[1]).powi(2) + (p[2] - c[2]).powi(2)
+            })
+            .fold(0.0f32, f32::max);
+        Some(max_squared.sqrt())
+    }
+}
@@ -0,0 +1,237 @@
+// Construction of packed clouds from float attributes, for generating scenes in code rather than
+// loading them. Attributes are pushed as flat slices in the same layout as `UnpackedGaussians`
+// and quantized when the cloud is built, the same way as the .ply importer does it.
+
+use std::io;
+
+use crate::attributes::CustomAttributes;
+use crate::codec::{quantize_sh, SH1_BITS, SH_REST_BITS};
+use crate::coordinates::CoordinateSystem;
+use crate::export::{fixed24_extent, fractional_bits_for_extent};
+use crate::validate::MAX_FRACTIONAL_BITS;
+use crate::{dim_for_degree, PackedGaussian, PackedGaussians, PositionEncoding, UnpackedGaussian, MAX_SH_DEGREE};
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Collects float attributes for a new cloud. Only positions are required, attributes which are
+/// never pushed default to the identity rotation, unit scales, black, half transparent and no
+/// view dependent color.
+#[derive(Clone, Debug)]
+pub struct GaussianCloudBuilder {
+    sh_degree: usize,
+    fractional_bits: usize,
+    antialiased: bool,
+    sh1_bits: u32,
+    sh_rest_bits: u32,
+    coordinate_system: CoordinateSystem,
+    positions: Vec<f32>,
+    rotations: Vec<f32>,
+    scales: Vec<f32>,
+    colors: Vec<f32>,
+    alphas: Vec<f32>,
+    sh: Vec<f32>,
+}
+
+impl Default for GaussianCloudBuilder {
+    fn default() -> GaussianCloudBuilder {
+        GaussianCloudBuilder {
+            sh_degree: 0,
+            fractional_bits: 12,
+            antialiased: false,
+            sh1_bits: SH1_BITS,
+            sh_rest_bits: SH_REST_BITS,
+            coordinate_system: CoordinateSystem::Unspecified,
+            positions: Vec::new(),
+            rotations: Vec::new(),
+            scales: Vec::new(),
+            colors: Vec::new(),
+            alphas: Vec::new(),
+            sh: Vec::new(),
+        }
+    }
+}
+
+impl GaussianCloudBuilder {
+    pub fn new() -> GaussianCloudBuilder {
+        GaussianCloudBuilder::default()
+    }
+
+    pub fn sh_degree(mut self, sh_degree: usize) -> GaussianCloudBuilder {
+        self.sh_degree = sh_degree;
+        self
+    }
+
+    pub fn fractional_bits(mut self, fractional_bits: usize) -> GaussianCloudBuilder {
+        self.fractional_bits = fractional_bits;
+        self
+    }
+
+    pub fn antialiased(mut self, antialiased: bool) -> GaussianCloudBuilder {
+        self.antialiased = antialiased;
+        self
+    }
+
+    /// Bits kept for each degree 1 SH coefficient
+    pub fn sh1_bits(mut self, sh1_bits: u32) -> GaussianCloudBuilder {
+        self.sh1_bits = sh1_bits;
+        self
+    }
+
+    /// Bits kept for each SH coefficient above degree 1
+    pub fn sh_rest_bits(mut self, sh_rest_bits: u32) -> GaussianCloudBuilder {
+        self.sh_rest_bits = sh_rest_bits;
+        self
+    }
+
+    /// Axis convention the attributes are given in. Saved files are converted to RUB.
+    pub fn coordinate_system(mut self, coordinate_system: CoordinateSystem) -> GaussianCloudBuilder {
+        self.coordinate_system = coordinate_system;
+        self
+    }
+
+    /// x, y, z for each splat
+    pub fn push_positions(&mut self, positions: &[f32]) -> &mut GaussianCloudBuilder {
+        self.positions.extend_from_slice(positions);
+        self
+    }
+
+    /// w, x, y, z for each splat
+    pub fn push_rotations(&mut self, rotations: &[f32]) -> &mut GaussianCloudBuilder {
+        self.rotations.extend_from_slice(rotations);
+        self
+    }
+
+    /// Log scales along each axis for each splat
+    pub fn push_scales(&mut self, scales: &[f32]) -> &mut GaussianCloudBuilder {
+        self.scales.extend_from_slice(scales);
+        self
+    }
+
+    /// DC color coefficients r, g, b for each splat
+    pub fn push_colors(&mut self, colors: &[f32]) -> &mut GaussianCloudBuilder {
+        self.colors.extend_from_slice(colors);
+        self
+    }
+
+    /// Alphas before the sigmoid, one for each splat
+    pub fn push_alphas(&mut self, alphas: &[f32]) -> &mut GaussianCloudBuilder {
+        self.alphas.extend_from_slice(alphas);
+        self
+    }
+
+    /// SH coefficients for each splat, ordered by coefficient with the r, g and b values of each
+    /// coefficient next to each other
+    pub fn push_sh(&mut self, sh: &[f32]) -> &mut GaussianCloudBuilder {
+        self.sh.extend_from_slice(sh);
+        self
+    }
+
+    /// Pushes every attribute of one splat, keeping the coefficients up to the builder's degree
+    pub fn push(&mut self, gaussian: &UnpackedGaussian) -> &mut GaussianCloudBuilder {
+        self.positions.extend_from_slice(&gaussian.position);
+        self.rotations.extend_from_slice(&gaussian.rotation);
+        self.scales.extend_from_slice(&gaussian.scale);
+        self.colors.extend_from_slice(&gaussian.color);
+        self.alphas.push(gaussian.alpha);
+        for j in 0..dim_for_degree(self.sh_degree) {
+            self.sh.extend_from_slice(&[gaussian.sh_r[j], gaussian.sh_g[j], gaussian.sh_b[j]]);
+        }
+        self
+    }
+
+    /// Quantizes the pushed attributes into a cloud. Fails if the SH degree or fractional bits
+    /// aren't supported, if a position is too far out for the fractional bits, or if an
+    /// attribute that was pushed doesn't have a value for every splat.
+    pub fn build(&self) -> Result<PackedGaussians, io::Error> {
+        if self.sh_degree > MAX_SH_DEGREE {
+            return Err(invalid_input(format!("Unsupported SH degree: {}", self.sh_degree)));
+        }
+        if self.fractional_bits > MAX_FRACTIONAL_BITS {
+            return Err(invalid_input(format!("Invalid number of fractional bits: {}", self.fractional_bits)));
+        }
+        if !self.positions.len().is_multiple_of(3) {
+            return Err(invalid_input(format!("Expected 3 position values per splat but found {} values", self.positions.len())));
+        }
+        let extent = self.positions.iter().fold(0.0, |extent: f64, &x| extent.max((x as f64).abs()));
+        if extent > fixed24_extent(self.fractional_bits) {
+            return Err(invalid_input(format!("Positions reach {} but {} fractional bits only hold up to {}, use {} or fewer",
+                extent, self.fractional_bits, fixed24_extent(self.fractional_bits), fractional_bits_for_extent(extent))));
+        }
+        let n = self.positions.len() / 3;
+        let sh_dim = dim_for_degree(self.sh_degree);
+        for (name, values, stride) in [
+            ("rotation", &self.rotations, 4),
+            ("scale", &self.scales, 3),
+            ("color", &self.colors, 3),
+            ("alpha", &self.alphas, 1),
+            ("SH", &self.sh, sh_dim * 3),
+        ] {
+            if !values.is_empty() && values.len() != n * stride {
+                return Err(invalid_input(format!("Expected {} {} values for {} splats but found {}", n * stride, name, n, values.len())));
+            }
+        }
+
+        let mut result = PackedGaussians {
+            num_points: 0,
+            sh_degree: self.sh_degree,
+            fractional_bits: self.fractional_bits,
+            antialiased: self.antialiased,
+            positions: Vec::with_capacity(n * 9),
+            scales: Vec::with_capacity(n * 3),
+            rotations: Vec::with_capacity(n * 3),
+            alphas: Vec::with_capacity(n),
+            colors: Vec::with_capacity(n * 3),
+            sh: Vec::with_capacity(n * sh_dim * 3),
+            attributes: CustomAttributes::default(),
+            position_encoding: PositionEncoding::FixedPoint24,
+            coordinate_system: self.coordinate_system,
+        };
+
+        let get = |values: &[f32], i: usize, j: usize, stride: usize, default: f32| {
+            values.get(i * stride + j).copied().unwrap_or(default)
+        };
+        for i in 0..n {
+            let mut g = UnpackedGaussian {
+                position: [0, 1, 2].map(|j| self.positions[i * 3 + j]),
+                rotation: [0, 1, 2, 3].map(|j| get(&self.rotations, i, j, 4, if j == 0 { 1.0 } else { 0.0 })),
+                scale: [0, 1, 2].map(|j| get(&self.scales, i, j, 3, 0.0)),
+                color: [0, 1, 2].map(|j| get(&self.colors, i, j, 3, 0.0)),
+                alpha: get(&self.alphas, i, 0, 1, 0.0),
+                ..Default::default()
+            };
+            for j in 0..sh_dim {
+                g.sh_r[j] = get(&self.sh, i, j * 3, sh_dim * 3, 0.0);
+                g.sh_g[j] = get(&self.sh, i, j * 3 + 1, sh_dim * 3, 0.0);
+                g.sh_b[j] = get(&self.sh, i, j * 3 + 2, sh_dim * 3, 0.0);
+            }
+
+            let mut packed = PackedGaussian::pack(&g, false, self.fractional_bits as u32);
+            for j in 0..sh_dim {
+                let bits = if j < 3 { self.sh1_bits } else { self.sh_rest_bits };
+                packed.sh_r[j] = quantize_sh(g.sh_r[j], bits);
+                packed.sh_g[j] = quantize_sh(g.sh_g[j], bits);
+                packed.sh_b[j] = quantize_sh(g.sh_b[j], bits);
+            }
+            result.push(&packed);
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_beyond_the_fixed_point_range_are_rejected() {
+        let mut builder = GaussianCloudBuilder::new();
+        builder.push_positions(&[3000.0, 0.0, -5000.0]);
+        assert!(matches!(builder.build(), Err(error) if error.kind() == io::ErrorKind::InvalidInput));
+
+        let cloud = builder.clone().fractional_bits(10).build().unwrap();
+        assert_eq!(cloud.unpack_position(0), [3000.0, 0.0, -5000.0]);
+    }
+}
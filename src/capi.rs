@@ -0,0 +1,296 @@
+// C interface for native engine plugins and C++ renderers, enabled by the `capi` feature and
+// declared in include/spz_rs.h. Clouds are opaque handles owned by the caller, who releases them
+// with spz_free. Functions which can fail return null or a non zero status, and spz_last_error
+// then describes what went wrong on the calling thread.
+//
+// Decoded attributes are copied into caller owned arrays. Each spz_unpack_all_* function returns
+// the number of floats the attribute needs, and only writes them when the array is big enough,
+// so callers can pass null first to size their buffer.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::fmt::Display;
+use std::ptr;
+use std::slice;
+use std::sync::OnceLock;
+
+use crate::covariance::COVARIANCE_DIM;
+use crate::{load_packed_gaussians_from_bytes, load_packed_gaussians_from_file, save_packed_gaussians_to_file};
+use crate::{PackedGaussians, UnpackedGaussians};
+
+/// Opaque handle to a loaded cloud
+pub struct SpzCloud {
+    packed: PackedGaussians,
+    // Decoded on the first request for an attribute, so fetching each one only decodes once
+    unpacked: OnceLock<UnpackedGaussians>,
+}
+
+impl SpzCloud {
+    fn unpacked(&self) -> &UnpackedGaussians {
+        self.unpacked.get_or_init(|| self.packed.unpack_all())
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(error: impl Display) {
+    // Interior nul bytes can't appear in a C string, so they're dropped from the message
+    let message = error.to_string().replace('\0', "");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+fn into_handle(result: Result<PackedGaussians, impl Display>) -> *mut SpzCloud {
+    match result {
+        Ok(packed) => Box::into_raw(Box::new(SpzCloud { packed, unpacked: OnceLock::new() })),
+        Err(error) => {
+            set_last_error(error);
+            ptr::null_mut()
+        }
+    }
+}
+
+unsafe fn path_str<'a>(path: *const c_char) -> Result<&'a str, &'static str> {
+    if path.is_null() {
+        return Err("Path is null");
+    }
+    CStr::from_ptr(path).to_str().map_err(|_| "Path is not valid UTF-8")
+}
+
+unsafe fn copy_out(values: &[f32], out: *mut f32, capacity: usize) -> usize {
+    if !out.is_null() && capacity >= values.len() {
+        ptr::copy_nonoverlapping(values.as_ptr(), out, values.len());
+    }
+    values.len()
+}
+
+/// Message for the last failure on this thread, or null. Valid until the next failing call.
+#[no_mangle]
+pub extern "C" fn spz_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// Loads a .spz file, returning null on failure
+///
+/// # Safety
+///
+/// `path` must be null or a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn spz_load_file(path: *const c_char) -> *mut SpzCloud {
+    match path_str(path) {
+        Ok(path) => into_handle(load_packed_gaussians_from_file(path)),
+        Err(error) => into_handle(Err(error)),
+    }
+}
+
+/// Loads a .spz file held in memory, returning null on failure
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, or be null when `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn spz_load_bytes(data: *const u8, len: usize) -> *mut SpzCloud {
+    let bytes = if len == 0 { &[][..] } else { slice::from_raw_parts(data, len) };
+    into_handle(load_packed_gaussians_from_bytes(bytes))
+}
+
+/// Saves a cloud as a .spz file, returning 0 on success
+///
+/// # Safety
+///
+/// `cloud` must be a live handle and `path` null or a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn spz_save_file(cloud: *const SpzCloud, path: *const c_char) -> i32 {
+    let result = path_str(path)
+        .map_err(|error| error.to_string())
+        .and_then(|path| save_packed_gaussians_to_file(&(*cloud).packed, path).map_err(|error| error.to_string()));
+    match result {
+        Ok(()) => 0,
+        Err(error) => {
+            set_last_error(error);
+            -1
+        }
+    }
+}
+
+/// Releases a cloud. Null is ignored.
+///
+/// # Safety
+///
+/// `cloud` must be null or a live handle, which mustn't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn spz_free(cloud: *mut SpzCloud) {
+    if !cloud.is_null() {
+        drop(Box::from_raw(cloud));
+    }
+}
+
+/// # Safety
+///
+/// `cloud` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn spz_get_num_points(cloud: *const SpzCloud) -> usize {
+    (*cloud).packed.num_points
+}
+
+/// # Safety
+///
+/// `cloud` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn spz_get_sh_degree(cloud: *const SpzCloud) -> usize {
+    (*cloud).packed.sh_degree
+}
+
+/// # Safety
+///
+/// `cloud` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn spz_is_antialiased(cloud: *const SpzCloud) -> bool {
+    (*cloud).packed.antialiased
+}
+
+/// x, y, z for each splat
+///
+/// # Safety
+///
+/// `cloud` must be a live handle and `out` null or writable for `capacity` floats.
+#[no_mangle]
+pub unsafe extern "C" fn spz_unpack_all_positions(cloud: *const SpzCloud, out: *mut f32, capacity: usize) -> usize {
+    copy_out(&(*cloud).unpacked().positions, out, capacity)
+}
+
+/// w, x, y, z for each splat
+///
+/// # Safety
+///
+/// `cloud` must be a live handle and `out` null or writable for `capacity` floats.
+#[no_mangle]
+pub unsafe extern "C" fn spz_unpack_all_rotations(cloud: *const SpzCloud, out: *mut f32, capacity: usize) -> usize {
+    copy_out(&(*cloud).unpacked().rotations, out, capacity)
+}
+
+/// Log scales along each axis for each splat
+///
+/// # Safety
+///
+/// `cloud` must be a live handle and `out` null or writable for `capacity` floats.
+#[no_mangle]
+pub unsafe extern "C" fn spz_unpack_all_scales(cloud: *const SpzCloud, out: *mut f32, capacity: usize) -> usize {
+    copy_out(&(*cloud).unpacked().scales, out, capacity)
+}
+
+/// DC color coefficients r, g, b for each splat
+///
+/// # Safety
+///
+/// `cloud` must be a live handle and `out` null or writable for `capacity` floats.
+#[no_mangle]
+pub unsafe extern "C" fn spz_unpack_all_colors(cloud: *const SpzCloud, out: *mut f32, capacity: usize) -> usize {
+    copy_out(&(*cloud).unpacked().colors, out, capacity)
+}
+
+/// Alphas before the sigmoid, one for each splat
+///
+/// # Safety
+///
+/// `cloud` must be a live handle and `out` null or writable for `capacity` floats.
+#[no_mangle]
+pub unsafe extern "C" fn spz_unpack_all_alphas(cloud: *const SpzCloud, out: *mut f32, capacity: usize) -> usize {
+    copy_out(&(*cloud).unpacked().alphas, out, capacity)
+}
+
+/// SH coefficients for each splat, ordered by coefficient with r, g and b next to each other
+///
+/// # Safety
+///
+/// `cloud` must be a live handle and `out` null or writable for `capacity` floats.
+#[no_mangle]
+pub unsafe extern "C" fn spz_unpack_all_sh(cloud: *const SpzCloud, out: *mut f32, capacity: usize) -> usize {
+    copy_out(&(*cloud).unpacked().sh, out, capacity)
+}
+
+/// Covariances xx, xy, xz, yy, yz, zz for each splat
+///
+/// # Safety
+///
+/// `cloud` must be a live handle and `out` null or writable for `capacity` floats.
+#[no_mangle]
+pub unsafe extern "C" fn spz_unpack_all_covariances(cloud: *const SpzCloud, out: *mut f32, capacity: usize) -> usize {
+    // Computed rather than cached, so only when the caller has room for them
+    let needed = (*cloud).packed.num_points * COVARIANCE_DIM;
+    if !out.is_null() && capacity >= needed {
+        copy_out(&(*cloud).unpacked().covariances(), out, capacity);
+    }
+    needed
+}
+
+#[cfg(test)]
+mod tests {
+    // The header is written by hand, so its declarations are checked against the exported
+    // functions in this file, with Rust types mapped to the C types they're passed as
+    const HEADER: &str = include_str!("../include/spz_rs.h");
+    const SOURCE: &str = include_str!("capi.rs");
+
+    fn c_type(rust: &str) -> String {
+        let rust = rust.trim();
+        if let Some(pointee) = rust.strip_prefix("*const ") {
+            return format!("const {} *", c_type(pointee));
+        }
+        if let Some(pointee) = rust.strip_prefix("*mut ") {
+            return format!("{} *", c_type(pointee));
+        }
+        match rust {
+            "c_char" => "char",
+            "u8" => "uint8_t",
+            "i32" => "int32_t",
+            "usize" => "size_t",
+            "f32" => "float",
+            other => other,
+        }
+        .to_string()
+    }
+
+    // Declarations as return type, name and parameter types, with whitespace removed
+    fn exported_functions() -> Vec<(String, String, Vec<String>)> {
+        let mut functions = Vec::new();
+        for line in SOURCE.lines().filter(|line| line.starts_with("pub ") && line.contains("extern \"C\" fn ")) {
+            let (_, rest) = line.split_once("fn ").unwrap();
+            let (name, rest) = rest.split_once('(').unwrap();
+            let (params, rest) = rest.split_once(')').unwrap();
+            let returns = rest.split_once("->").map_or("void".to_string(), |(_, ret)| c_type(ret.trim_end_matches('{')));
+            let params = params.split(',').filter_map(|param| param.split_once(':')).map(|(_, ty)| c_type(ty)).collect();
+            functions.push((returns, name.to_string(), params));
+        }
+        functions
+    }
+
+    fn declared_functions() -> Vec<(String, String, Vec<String>)> {
+        let mut functions = Vec::new();
+        for line in HEADER.lines().filter(|line| line.contains("spz_") && line.ends_with(");")) {
+            let (signature, params) = line.trim_end_matches(");").split_once('(').unwrap();
+            let name_start = signature.rfind([' ', '*']).unwrap() + 1;
+            let (returns, name) = signature.split_at(name_start);
+            let params = params.split(',').filter(|param| param.trim() != "void")
+                .map(|param| param.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_').to_string())
+                .collect();
+            functions.push((returns.to_string(), name.to_string(), params));
+        }
+        functions
+    }
+
+    fn squeeze(functions: Vec<(String, String, Vec<String>)>) -> Vec<String> {
+        let mut squeezed: Vec<String> = functions.into_iter()
+            .map(|(returns, name, params)| format!("{} {}({})", returns, name, params.join(",")).replace(' ', ""))
+            .collect();
+        squeezed.sort();
+        squeezed
+    }
+
+    #[test]
+    fn header_declares_every_exported_function() {
+        let exported = squeeze(exported_functions());
+        assert_eq!(exported.len(), SOURCE.lines().filter(|line| *line == "#[no_mangle]").count());
+        assert_eq!(squeeze(declared_functions()), exported);
+    }
+}
@@ -0,0 +1,795 @@
+// Low level quantizers used by the .spz format. These mirror the reference C++ implementation
+// exactly and are public so that GPU shaders and ports to other languages can reproduce the same
+// math. Every quantizer rounds to the nearest representable value, with halfway cases rounded
+// away from zero, and clamps to the representable range.
+
+use std::fs;
+use std::io;
+use std::sync::OnceLock;
+
+use crate::json::JsonValue;
+
+/// Scale factor for DC color components. To convert to RGB, we should multiply by 0.282, but it
+/// can be useful to represent base colors that are out of range if the higher spherical harmonics
+/// bands bring them back into range so we multiply by a smaller value.
+pub const COLOR_SCALE: f32 = 0.15;
+
+/// Log scales are stored in steps of 1 / SCALE_STEPS_PER_UNIT starting from SCALE_OFFSET
+pub const SCALE_STEPS_PER_UNIT: f32 = 16.0;
+pub const SCALE_OFFSET: f32 = -10.0;
+
+/// Number of bits kept by the writer for degree 1 SH coefficients
+pub const SH1_BITS: u32 = 5;
+/// Number of bits kept by the writer for SH coefficients of degree 2 and above
+pub const SH_REST_BITS: u32 = 4;
+
+fn to_u8(x: f32) -> u8 {
+    x.round().clamp(0.0, 255.0) as u8
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+fn inv_sigmoid(x: f32) -> f32 {
+    (x / (1.0 - x)).ln()
+}
+
+/// Log scale to byte: `round((x + 10) * 16)`
+pub fn quantize_scale(x: f32) -> u8 {
+    to_u8((x - SCALE_OFFSET) * SCALE_STEPS_PER_UNIT)
+}
+
+/// Byte to log scale: `x / 16 - 10`
+pub fn unquantize_scale(x: u8) -> f32 {
+    x as f32 / SCALE_STEPS_PER_UNIT + SCALE_OFFSET
+}
+
+/// Alpha before the sigmoid to byte: `round(sigmoid(x) * 255)`
+pub fn quantize_alpha(x: f32) -> u8 {
+    to_u8(sigmoid(x) * 255.0)
+}
+
+/// Byte to alpha before the sigmoid: `ln(a / (1 - a))` where `a = x / 255`. Note that 0 and 255
+/// decode to negative and positive infinity respectively.
+pub fn unquantize_alpha(x: u8) -> f32 {
+    inv_sigmoid(x as f32 / 255.0)
+}
+
+/// Same as [`unquantize_alpha`] but evaluating the logarithm with basic arithmetic only, rather
+/// than the platform's `ln`, so the result is bit identical on every target.
+pub fn unquantize_alpha_deterministic(x: u8) -> f32 {
+    let a = x as f64 / 255.0;
+    ln_deterministic(a / (1.0 - a)) as f32
+}
+
+// Natural log using only IEEE 754 basic operations, which are correctly rounded and so give the
+// same result everywhere. The argument is split as m * 2^e with m in [sqrt(1/2), sqrt(2)) and
+// ln(m) evaluated with the atanh series, which converges quickly over that range.
+pub(crate) fn ln_deterministic(x: f64) -> f64 {
+    if x.is_nan() || x < 0.0 {
+        return f64::NAN;
+    }
+    if x == 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if x.is_infinite() {
+        return f64::INFINITY;
+    }
+
+    let mut bits = x.to_bits();
+    let mut e = 0i64;
+    if bits >> 52 == 0 {
+        // Subnormal, normalize first
+        bits = (x * 18_014_398_509_481_984.0).to_bits(); // 2^54
+        e -= 54;
+    }
+    e += ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let mut m = f64::from_bits((bits & 0x000f_ffff_ffff_ffff) | 0x3ff0_0000_0000_0000);
+    if m > std::f64::consts::SQRT_2 {
+        m *= 0.5;
+        e += 1;
+    }
+
+    let s = (m - 1.0) / (m + 1.0);
+    let s2 = s * s;
+    let mut term = s;
+    let mut sum = 0.0;
+    for k in 0..12 {
+        sum += term / (2 * k + 1) as f64;
+        term *= s2;
+    }
+    e as f64 * std::f64::consts::LN_2 + 2.0 * sum
+}
+
+// e^x, by the same reasoning as ln_deterministic. The argument is split as k ln(2) + r with
+// |r| <= ln(2) / 2 and e^r evaluated with its Taylor series. Results which would be subnormal
+// are flushed to zero.
+pub(crate) fn exp_deterministic(x: f64) -> f64 {
+    if x.is_nan() {
+        return f64::NAN;
+    }
+    if x > 709.0 {
+        return f64::INFINITY;
+    }
+    if x < -708.0 {
+        return 0.0;
+    }
+
+    let k = (x / std::f64::consts::LN_2).round();
+    let r = x - k * std::f64::consts::LN_2;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for n in 1..18 {
+        term *= r / n as f64;
+        sum += term;
+    }
+    sum * f64::from_bits(((k as i64 + 1023) as u64) << 52)
+}
+
+/// DC color coefficient to byte: `round(x * 0.15 * 255 + 127.5)`
+pub fn quantize_color(x: f32) -> u8 {
+    to_u8(x * (COLOR_SCALE * 255.0) + (0.5 * 255.0))
+}
+
+/// Byte to DC color coefficient: `(x / 255 - 0.5) / 0.15`
+pub fn unquantize_color(x: u8) -> f32 {
+    (x as f32 / 255.0 - 0.5) / COLOR_SCALE
+}
+
+/// SH coefficient to byte keeping the top `bits` bits: `round(x * 128 + 128)`, then rounded to
+/// the nearest multiple of `2^(8 - bits)`. Use 8 bits for no loss beyond the base quantization.
+pub fn quantize_sh(x: f32, bits: u32) -> u8 {
+    let bucket_size = 1i32 << (8 - bits.clamp(1, 8));
+    let q = ((x * 128.0).round() + 128.0) as i32;
+    let q = (q + bucket_size / 2) / bucket_size * bucket_size;
+    q.clamp(0, 255) as u8
+}
+
+/// Byte to SH coefficient: `(x - 128) / 128`
+pub fn unquantize_sh(x: u8) -> f32 {
+    (x as f32 - 128.0) / 128.0
+}
+
+/// Position component to a 24 bit two's complement fixed point value with `fractional_bits`
+/// fractional bits, stored little endian.
+pub fn encode_fixed24(x: f32, fractional_bits: u32) -> [u8; 3] {
+    let fixed32 = (x / fixed_point_step(fractional_bits)).round() as i32;
+    [(fixed32 & 0xff) as u8, ((fixed32 >> 8) & 0xff) as u8, ((fixed32 >> 16) & 0xff) as u8]
+}
+
+/// Value of the lowest fixed point bit, 2^-fractional_bits. Built from its bits so it's exact
+/// for any number a header can declare, where a shift would overflow past 31, and zero once it
+/// would be subnormal.
+pub fn fixed_point_step(fractional_bits: u32) -> f32 {
+    if fractional_bits < 127 {
+        f32::from_bits((127 - fractional_bits) << 23)
+    } else {
+        0.0
+    }
+}
+
+/// 24 bit little endian fixed point value to a position component
+pub fn decode_fixed24(bytes: &[u8], fractional_bits: u32) -> f32 {
+    let scale = fixed_point_step(fractional_bits);
+    let mut fixed32: i32 = bytes[0] as i32;
+    fixed32 |= (bytes[1] as i32) << 8;
+    fixed32 |= (bytes[2] as i32) << 16;
+    fixed32 |= if fixed32 & 0x800000 != 0 { 0xff000000u32 as i32 } else { 0 };
+    fixed32 as f32 * scale
+}
+
+/// Quaternion stored as w, x, y, z to three bytes. The quaternion is normalized and flipped so
+/// that w is non-negative, letting the decoder recover w from x, y and z. Each of those is then
+/// stored as `round(v * 127.5 + 127.5)`.
+pub fn encode_quat3(q: [f32; 4]) -> [u8; 3] {
+    let norm = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
+    let sign = if q[0] < 0.0 { -1.0 } else { 1.0 };
+    let s = if norm > 0.0 { sign / norm } else { 0.0 };
+    [
+        to_u8(q[1] * s * 127.5 + 127.5),
+        to_u8(q[2] * s * 127.5 + 127.5),
+        to_u8(q[3] * s * 127.5 + 127.5),
+    ]
+}
+
+/// Three bytes to a quaternion stored as w, x, y, z. Each of x, y and z is `v / 127.5 - 1` and
+/// w is `sqrt(max(0, 1 - x^2 - y^2 - z^2))`.
+pub fn decode_quat3(bytes: &[u8]) -> [f32; 4] {
+    let x = bytes[0] as f32 / 127.5 - 1.0;
+    let y = bytes[1] as f32 / 127.5 - 1.0;
+    let z = bytes[2] as f32 / 127.5 - 1.0;
+    let w = (1.0 - (x * x + y * y + z * z)).sqrt().max(0.0);
+    [w, x, y, z]
+}
+
+// Largest magnitude each of the three smaller components is stored with, in 9 bits
+const SMALLEST_THREE_MASK: u32 = (1 << 9) - 1;
+
+/// Quaternion stored as w, x, y, z to the four bytes of version 3 files. The component with the
+/// largest magnitude is left out and made positive, its index in x, y, z, w order is kept in the
+/// top two bits, and each other component gets a sign bit and 9 bits of magnitude scaled by
+/// `sqrt(1/2)`, the largest a smaller component can be. The first of them ends up in the highest
+/// bits of the little endian word.
+pub fn encode_quat_smallest_three(q: [f32; 4]) -> [u8; 4] {
+    let q = [q[1], q[2], q[3], q[0]];
+    let norm = q.iter().map(|v| v * v).sum::<f32>().sqrt();
+    let q = if norm > 0.0 { q.map(|v| v / norm) } else { [0.0, 0.0, 0.0, 1.0] };
+    let largest = (0..4).fold(0, |best, i| if q[i].abs() > q[best].abs() { i } else { best });
+    let negate = q[largest] < 0.0;
+    let mut word = largest as u32;
+    for (_, &v) in q.iter().enumerate().filter(|&(i, _)| i != largest) {
+        let sign = ((v < 0.0) != negate) as u32;
+        let magnitude = (SMALLEST_THREE_MASK as f32 * (v.abs() / std::f32::consts::FRAC_1_SQRT_2) + 0.5).floor() as u32;
+        word = word << 10 | sign << 9 | magnitude.min(SMALLEST_THREE_MASK);
+    }
+    word.to_le_bytes()
+}
+
+/// Four bytes of a version 3 file to a quaternion stored as w, x, y, z, the inverse of
+/// [`encode_quat_smallest_three`]. The left out component is `sqrt(max(0, 1 - sum of squares))`.
+pub fn decode_quat_smallest_three(bytes: &[u8]) -> [f32; 4] {
+    let mut word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let largest = (word >> 30) as usize;
+    let mut q = [0.0f32; 4];
+    let mut sum_squares = 0.0;
+    for i in (0..4).rev().filter(|&i| i != largest) {
+        let magnitude = word & SMALLEST_THREE_MASK;
+        let negative = (word >> 9) & 1 == 1;
+        word >>= 10;
+        let v = std::f32::consts::FRAC_1_SQRT_2 * magnitude as f32 / SMALLEST_THREE_MASK as f32;
+        q[i] = if negative { -v } else { v };
+        sum_squares += q[i] * q[i];
+    }
+    q[largest] = (1.0 - sum_squares).max(0.0).sqrt();
+    [q[3], q[0], q[1], q[2]]
+}
+
+/// IEEE 754 binary16 to f32. The conversion is exact and built directly from the bits.
+pub fn half_to_f32(h: u16) -> f32 {
+    let sign = ((h as u32) & 0x8000) << 16;
+    let exponent = ((h >> 10) & 0x1f) as u32;
+    let mantissa = (h & 0x3ff) as u32;
+
+    if exponent == 0 {
+        // Subnormal numbers (no exponent, 0 in the mantissa decimal).
+        let magnitude = mantissa as f32 * (1.0 / 16_777_216.0); // 2^-24
+        return f32::from_bits(sign | magnitude.to_bits());
+    }
+
+    if exponent == 31 {
+        // Infinity or NaN.
+        if mantissa == 0 {
+            return f32::from_bits(sign | 0x7f800000);
+        } else {
+            return f32::NAN;
+        }
+    }
+
+    // non-zero exponent implies 1 in the mantissa decimal.
+    f32::from_bits(sign | ((exponent + 127 - 15) << 23) | (mantissa << 13))
+}
+
+/// f32 to IEEE 754 binary16, rounding to nearest even. Values too large for a half become
+/// infinity.
+pub fn f32_to_half(x: f32) -> u16 {
+    let bits = x.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7fffff;
+
+    if exponent == 0xff {
+        // Infinity or NaN, keeping NaNs quiet
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if half_exponent <= 0 {
+        // Subnormal half or zero
+        if half_exponent < -10 {
+            return sign;
+        }
+        let full_mantissa = mantissa | 0x800000;
+        let shift = (14 - half_exponent) as u32;
+        let half_mantissa = full_mantissa >> shift;
+        let remainder = full_mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = remainder > halfway || (remainder == halfway && half_mantissa & 1 == 1);
+        return sign | (half_mantissa + round_up as u32) as u16;
+    }
+
+    let half_mantissa = mantissa >> 13;
+    let remainder = mantissa & 0x1fff;
+    let round_up = remainder > 0x1000 || (remainder == 0x1000 && half_mantissa & 1 == 1);
+    // A carry out of the mantissa correctly bumps the exponent, and can overflow to infinity
+    sign | (((half_exponent as u32) << 10 | half_mantissa) + round_up as u32) as u16
+}
+
+// Batch decoders for whole fields, used when decoding entire clouds. They give bit identical
+// results to the scalar functions. The arithmetic decoders are written as branch free loops the
+// compiler vectorizes, while alphas, which need a logarithm, are looked up in tables filled from
+// the scalar functions.
+
+fn alpha_tables() -> &'static [[f32; 256]; 2] {
+    static TABLES: OnceLock<[[f32; 256]; 2]> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        [
+            std::array::from_fn(|x| unquantize_alpha(x as u8)),
+            std::array::from_fn(|x| unquantize_alpha_deterministic(x as u8)),
+        ]
+    })
+}
+
+fn lookup_batch(table: &[f32; 256], bytes: &[u8], out: &mut [f32]) {
+    for (x, &b) in out.iter_mut().zip(bytes) {
+        *x = table[b as usize];
+    }
+}
+
+/// [`unquantize_scale`] for each byte, writing as many values as fit in `out`
+pub fn unquantize_scale_batch(bytes: &[u8], out: &mut [f32]) {
+    for (x, &b) in out.iter_mut().zip(bytes) {
+        *x = unquantize_scale(b);
+    }
+}
+
+/// [`unquantize_alpha`] for each byte, writing as many values as fit in `out`
+pub fn unquantize_alpha_batch(bytes: &[u8], out: &mut [f32]) {
+    lookup_batch(&alpha_tables()[0], bytes, out);
+}
+
+/// [`unquantize_alpha_deterministic`] for each byte, writing as many values as fit in `out`
+pub fn unquantize_alpha_deterministic_batch(bytes: &[u8], out: &mut [f32]) {
+    lookup_batch(&alpha_tables()[1], bytes, out);
+}
+
+/// [`unquantize_color`] for each byte, writing as many values as fit in `out`
+pub fn unquantize_color_batch(bytes: &[u8], out: &mut [f32]) {
+    for (x, &b) in out.iter_mut().zip(bytes) {
+        *x = unquantize_color(b);
+    }
+}
+
+/// [`unquantize_sh`] for each byte, writing as many values as fit in `out`
+pub fn unquantize_sh_batch(bytes: &[u8], out: &mut [f32]) {
+    for (x, &b) in out.iter_mut().zip(bytes) {
+        *x = unquantize_sh(b);
+    }
+}
+
+/// [`decode_quat3`] for each three bytes, writing four values for each to `out`
+pub fn decode_quat3_batch(bytes: &[u8], out: &mut [f32]) {
+    for (q, b) in out.chunks_exact_mut(4).zip(bytes.chunks_exact(3)) {
+        q.copy_from_slice(&decode_quat3(b));
+    }
+}
+
+/// [`decode_fixed24`] for each three bytes, writing as many values as fit in `out`
+pub fn decode_fixed24_batch(bytes: &[u8], fractional_bits: u32, out: &mut [f32]) {
+    let scale = fixed_point_step(fractional_bits);
+    for (x, b) in out.iter_mut().zip(bytes.chunks_exact(3)) {
+        // Sign extends the 24 bit value by shifting it into the top of an i32 and back
+        *x = (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 * scale;
+    }
+}
+
+/// [`half_to_f32`] for each little endian half, writing as many values as fit in `out`
+pub fn half_to_f32_batch(bytes: &[u8], out: &mut [f32]) {
+    for (x, b) in out.iter_mut().zip(bytes.chunks_exact(2)) {
+        let h = u16::from_le_bytes([b[0], b[1]]) as u32;
+        let sign = (h & 0x8000) << 16;
+        let magnitude = h & 0x7fff;
+        // Shifting the exponent and mantissa into place gives the value scaled down by 2^112,
+        // which the multiply exactly undoes, for normal and subnormal halves alike
+        let finite = (f32::from_bits(magnitude << 13) * f32::from_bits(0x7780_0000)).to_bits();
+        let infinite = 0x7f80_0000;
+        *x = if magnitude < 0x7c00 {
+            f32::from_bits(sign | finite)
+        } else if magnitude == 0x7c00 {
+            f32::from_bits(sign | infinite)
+        } else {
+            f32::NAN
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderLang {
+    Wgsl,
+    Glsl,
+}
+
+/// Generates shader source with decode functions matching the functions in this module, using
+/// the same constants, so GPU side unpacking can't drift from the CPU implementation. Packed
+/// bytes are passed in as individual unsigned integers in the range 0 to 255. The generated
+/// functions are named `spz_decode_*` and quaternions are returned as (w, x, y, z) like the CPU
+/// decoder. `spz_decode_fixed24` takes the position step, [`fixed_point_step`] of the cloud's
+/// fractional bits, such as from a uniform. Use [`emit_shader_for`] to have it emitted as a
+/// constant instead.
+pub fn emit_shader(lang: ShaderLang) -> String {
+    let (float, uint, vec4, header) = match lang {
+        ShaderLang::Wgsl => ("f32", "u32", "vec4<f32>", "// Generated by spz_rs, do not edit\n"),
+        ShaderLang::Glsl => ("float", "uint", "vec4", "// Generated by spz_rs, do not edit. Requires GLSL 3.00 ES or 1.30+\n"),
+    };
+
+    let constant = |name: &str, value: f32| match lang {
+        ShaderLang::Wgsl => format!("const {}: f32 = {:?};\n", name, value),
+        ShaderLang::Glsl => format!("const float {} = {:?};\n", name, value),
+    };
+
+    let function = |name: &str, params: &[(&str, &str)], ret: &str, body: &str| {
+        let body: String = body.lines().map(|l| format!("    {}\n", l)).collect();
+        match lang {
+            ShaderLang::Wgsl => {
+                let params: Vec<String> = params.iter().map(|(n, t)| format!("{}: {}", n, t)).collect();
+                format!("fn {}({}) -> {} {{\n{}}}\n", name, params.join(", "), ret, body)
+            }
+            ShaderLang::Glsl => {
+                let params: Vec<String> = params.iter().map(|(n, t)| format!("{} {}", t, n)).collect();
+                format!("{} {}({}) {{\n{}}}\n", ret, name, params.join(", "), body)
+            }
+        }
+    };
+
+    let (let_uint, to_int, let_float) = match lang {
+        ShaderLang::Wgsl => ("var v: u32", "bitcast<i32>(v)", "let a: f32"),
+        // Converting a uint to an int with a constructor preserves the bit pattern in GLSL
+        ShaderLang::Glsl => ("uint v", "int(v)", "float a"),
+    };
+
+    let mut out = String::from(header);
+    out.push_str(&constant("SPZ_COLOR_SCALE", COLOR_SCALE));
+    out.push_str(&constant("SPZ_SCALE_STEPS_PER_UNIT", SCALE_STEPS_PER_UNIT));
+    out.push_str(&constant("SPZ_SCALE_OFFSET", SCALE_OFFSET));
+    out.push('\n');
+
+    // The step is passed in rather than computed from the fractional bits, as shifting by 32 or
+    // more is undefined in shaders while headers can declare up to 255
+    out.push_str(&function("spz_decode_fixed24",
+        &[("b0", uint), ("b1", uint), ("b2", uint), ("step", float)], float,
+        &format!("{} = b0 | (b1 << 8u) | (b2 << 16u);\n\
+                  if ((v & 0x800000u) != 0u) {{\n    v = v | 0xff000000u;\n}}\n\
+                  return {}({}) * step;",
+            let_uint, float, to_int)));
+    out.push('\n');
+
+    out.push_str(&function("spz_decode_scale", &[("x", uint)], float,
+        &format!("return {}(x) / SPZ_SCALE_STEPS_PER_UNIT + SPZ_SCALE_OFFSET;", float)));
+    out.push('\n');
+
+    out.push_str(&function("spz_decode_alpha", &[("x", uint)], float,
+        &format!("{} = {}(x) / 255.0;\nreturn log(a / (1.0 - a));", let_float, float)));
+    out.push('\n');
+
+    out.push_str(&function("spz_decode_opacity", &[("x", uint)], float,
+        &format!("return {}(x) / 255.0;", float)));
+    out.push('\n');
+
+    out.push_str(&function("spz_decode_color", &[("x", uint)], float,
+        &format!("return ({}(x) / 255.0 - 0.5) / SPZ_COLOR_SCALE;", float)));
+    out.push('\n');
+
+    out.push_str(&function("spz_decode_sh", &[("x", uint)], float,
+        &format!("return ({}(x) - 128.0) / 128.0;", float)));
+    out.push('\n');
+
+    let quat_body = match lang {
+        ShaderLang::Wgsl => "let x: f32 = f32(b0) / 127.5 - 1.0;\n\
+                             let y: f32 = f32(b1) / 127.5 - 1.0;\n\
+                             let z: f32 = f32(b2) / 127.5 - 1.0;\n\
+                             let w: f32 = sqrt(max(1.0 - (x * x + y * y + z * z), 0.0));\n\
+                             return vec4<f32>(w, x, y, z);".to_string(),
+        ShaderLang::Glsl => "float x = float(b0) / 127.5 - 1.0;\n\
+                             float y = float(b1) / 127.5 - 1.0;\n\
+                             float z = float(b2) / 127.5 - 1.0;\n\
+                             float w = sqrt(max(1.0 - (x * x + y * y + z * z), 0.0));\n\
+                             return vec4(w, x, y, z);".to_string(),
+    };
+    out.push_str(&function("spz_decode_quat3", &[("b0", uint), ("b1", uint), ("b2", uint)], vec4, &quat_body));
+
+    out
+}
+
+/// Same as [`emit_shader`], along with an `SPZ_POSITION_STEP` constant holding
+/// [`fixed_point_step`] of the given fractional bits, for shaders built for one cloud
+pub fn emit_shader_for(lang: ShaderLang, fractional_bits: u32) -> String {
+    let step = fixed_point_step(fractional_bits);
+    let constant = match lang {
+        ShaderLang::Wgsl => format!("const SPZ_POSITION_STEP: f32 = {:?};\n", step),
+        ShaderLang::Glsl => format!("const float SPZ_POSITION_STEP = {:?};\n", step),
+    };
+    let mut out = emit_shader(lang);
+    let end_of_constants = out.find("\n\n").map_or(out.len(), |i| i + 1);
+    out.insert_str(end_of_constants, &constant);
+    out
+}
+
+/// Version of the layout written by [`export_test_vectors`]
+pub const TEST_VECTORS_VERSION: u32 = 1;
+
+// Small deterministic generator so the exported sample inputs are the same on every run
+struct SampleGenerator(u32);
+
+impl SampleGenerator {
+    fn next_byte(&mut self) -> u8 {
+        self.0 = self.0.wrapping_mul(1664525).wrapping_add(1013904223);
+        (self.0 >> 24) as u8
+    }
+}
+
+fn byte_vectors(decode: impl Fn(u8) -> f32) -> JsonValue {
+    JsonValue::Array((0..=255u8).map(|x| JsonValue::Object(vec![
+        ("input".to_string(), JsonValue::Number(x as f64)),
+        ("output".to_string(), JsonValue::from_f32(decode(x))),
+    ])).collect())
+}
+
+fn bytes_to_json(bytes: &[u8]) -> JsonValue {
+    JsonValue::Array(bytes.iter().map(|&b| JsonValue::Number(b as f64)).collect())
+}
+
+/// Builds JSON test vectors pairing packed inputs with the values this crate decodes them to,
+/// for validating decoders in other languages or shaders. Single byte codecs list every input,
+/// the rest use a fixed set of edge cases plus deterministic pseudo random samples. Decoded values
+/// are written with enough digits to recover the exact f32, and non-finite results (alpha inputs
+/// 0 and 255, half infinities and NaNs) are written as null. Alphas come from
+/// [`unquantize_alpha_deterministic`], so the vectors are the same whichever platform exports
+/// them, while decoders using a platform `ln` may differ from them in the last bit.
+pub fn test_vectors_json() -> String {
+    let mut rng = SampleGenerator(0x5053474e);
+
+    let mut fixed24_inputs: Vec<[u8; 3]> = vec![[0, 0, 0], [1, 0, 0], [255, 255, 255], [255, 255, 127], [0, 0, 128]];
+    fixed24_inputs.extend((0..59).map(|_| [rng.next_byte(), rng.next_byte(), rng.next_byte()]));
+    let mut fixed24 = Vec::new();
+    for fractional_bits in [0, 4, 12, 16, 23] {
+        for bytes in &fixed24_inputs {
+            fixed24.push(JsonValue::Object(vec![
+                ("input".to_string(), bytes_to_json(bytes)),
+                ("fractional_bits".to_string(), JsonValue::Number(fractional_bits as f64)),
+                ("output".to_string(), JsonValue::from_f32(decode_fixed24(bytes, fractional_bits))),
+            ]));
+        }
+    }
+
+    let mut quat_inputs: Vec<[u8; 3]> = vec![[0, 0, 0], [128, 128, 128], [127, 127, 127], [255, 128, 128], [255, 255, 255]];
+    quat_inputs.extend((0..123).map(|_| [rng.next_byte(), rng.next_byte(), rng.next_byte()]));
+    let quat3 = quat_inputs.iter().map(|bytes| JsonValue::Object(vec![
+        ("input".to_string(), bytes_to_json(bytes)),
+        ("output".to_string(), JsonValue::from_f32_slice(&decode_quat3(bytes))),
+    ])).collect();
+
+    let mut half_inputs: Vec<u16> = vec![0x0000, 0x0001, 0x03ff, 0x0400, 0x3c00, 0x7bff, 0x7c00, 0x7e00, 0x8000, 0xbc00, 0xfc00];
+    half_inputs.extend((0..117).map(|_| u16::from_le_bytes([rng.next_byte(), rng.next_byte()])));
+    let half = half_inputs.iter().map(|&h| JsonValue::Object(vec![
+        ("input".to_string(), JsonValue::Number(h as f64)),
+        ("output".to_string(), JsonValue::from_f32(half_to_f32(h))),
+    ])).collect();
+
+    JsonValue::Object(vec![
+        ("version".to_string(), JsonValue::Number(TEST_VECTORS_VERSION as f64)),
+        ("scale".to_string(), byte_vectors(unquantize_scale)),
+        ("alpha".to_string(), byte_vectors(unquantize_alpha_deterministic)),
+        ("color".to_string(), byte_vectors(unquantize_color)),
+        ("sh".to_string(), byte_vectors(unquantize_sh)),
+        ("fixed24".to_string(), JsonValue::Array(fixed24)),
+        ("quat3".to_string(), JsonValue::Array(quat3)),
+        ("half".to_string(), JsonValue::Array(half)),
+    ]).to_json_string_pretty()
+}
+
+/// Writes the output of [`test_vectors_json`] to a file
+pub fn export_test_vectors(path: &str) -> Result<(), io::Error> {
+    fs::write(path, test_vectors_json())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_quantizers_round_trip() {
+        for b in 0..=255u8 {
+            assert_eq!(quantize_scale(unquantize_scale(b)), b, "scale {}", b);
+            assert_eq!(quantize_alpha(unquantize_alpha(b)), b, "alpha {}", b);
+            assert_eq!(quantize_alpha(unquantize_alpha_deterministic(b)), b, "deterministic alpha {}", b);
+            assert_eq!(quantize_color(unquantize_color(b)), b, "color {}", b);
+            assert_eq!(quantize_sh(unquantize_sh(b), 8), b, "sh {}", b);
+        }
+    }
+
+    #[test]
+    fn sh_bits_keep_buckets() {
+        for bits in 1..=8 {
+            let bucket_size = 1u32 << (8 - bits);
+            for b in 0..=255u8 {
+                let q = quantize_sh(unquantize_sh(b), bits);
+                assert!((q as u32).is_multiple_of(bucket_size) || q == 255, "{} bits, {} gave {}", bits, b, q);
+                assert!((q as i32 - b as i32).unsigned_abs() <= bucket_size / 2, "{} bits, {} gave {}", bits, b, q);
+            }
+        }
+    }
+
+    #[test]
+    fn fixed24_round_trips_every_value() {
+        for v in 0..1u32 << 24 {
+            let bytes = [v as u8, (v >> 8) as u8, (v >> 16) as u8];
+            assert_eq!(encode_fixed24(decode_fixed24(&bytes, 12), 12), bytes, "{:06x}", v);
+        }
+    }
+
+    #[test]
+    fn fixed24_round_trips_for_each_fractional_bits() {
+        for fractional_bits in 0..=23 {
+            for v in (0..1u32 << 24).step_by(997).chain([0x7fffff, 0x800000, 0xffffff]) {
+                let bytes = [v as u8, (v >> 8) as u8, (v >> 16) as u8];
+                assert_eq!(encode_fixed24(decode_fixed24(&bytes, fractional_bits), fractional_bits), bytes,
+                    "{:06x} with {} fractional bits", v, fractional_bits);
+            }
+        }
+    }
+
+    #[test]
+    fn quat3_round_trips_valid_bytes() {
+        for v in 0..1u32 << 24 {
+            let bytes = [v as u8, (v >> 8) as u8, (v >> 16) as u8];
+            let [_, x, y, z] = decode_quat3(&bytes);
+            if x * x + y * y + z * z <= 1.0 {
+                assert_eq!(encode_quat3(decode_quat3(&bytes)), bytes, "{:?}", bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn quat3_error_is_bounded() {
+        let mut rng = SampleGenerator(1);
+        let mut component = || rng.next_byte() as f32 / 127.5 - 1.0;
+        for _ in 0..100_000 {
+            let q = [component(), component(), component(), component()];
+            let norm = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+            if norm < 0.1 {
+                continue;
+            }
+            let sign = if q[0] < 0.0 { -1.0 } else { 1.0 };
+            let q = q.map(|c| sign * c / norm);
+            let decoded = decode_quat3(&encode_quat3(q));
+            // x, y and z are each within half a step of the input, while w is recovered from
+            // them and so loses accuracy as it approaches zero
+            for j in 1..4 {
+                assert!((decoded[j] - q[j]).abs() <= 0.5 / 127.5 + 1e-6, "{:?} decoded as {:?}", q, decoded);
+            }
+            let dot = q.iter().zip(&decoded).map(|(a, b)| a * b).sum::<f32>();
+            let decoded_norm = decoded.iter().map(|c| c * c).sum::<f32>().sqrt();
+            let angle = 2.0 * (dot / decoded_norm).min(1.0).acos();
+            let limit = if q[0] >= 0.5 { 0.03 } else { 0.25 };
+            assert!(angle < limit, "{:?} decoded as {:?}, {} radians apart", q, decoded, angle);
+        }
+    }
+
+    #[test]
+    fn smallest_three_error_is_bounded() {
+        // The identity leaves out w, index 3 in x, y, z, w order, with the rest zero
+        assert_eq!(encode_quat_smallest_three([1.0, 0.0, 0.0, 0.0]), [0, 0, 0, 0xc0]);
+        assert_eq!(decode_quat_smallest_three(&[0, 0, 0, 0xc0]), [1.0, 0.0, 0.0, 0.0]);
+
+        let mut rng = SampleGenerator(2);
+        let mut component = || rng.next_byte() as f32 / 127.5 - 1.0;
+        for _ in 0..100_000 {
+            let q = [component(), component(), component(), component()];
+            let norm = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+            if norm < 0.1 {
+                continue;
+            }
+            let q = q.map(|c| c / norm);
+            let decoded = decode_quat_smallest_three(&encode_quat_smallest_three(q));
+            // Either sign is the same rotation
+            let dot = q.iter().zip(&decoded).map(|(a, b)| a * b).sum::<f32>().abs();
+            let angle = 2.0 * dot.min(1.0).acos();
+            assert!(angle < 0.005, "{:?} decoded as {:?}, {} radians apart", q, decoded, angle);
+        }
+    }
+
+    #[test]
+    fn half_round_trips_every_value() {
+        for h in 0..=u16::MAX {
+            let x = half_to_f32(h);
+            if x.is_nan() {
+                assert!(f32_to_half(x) & 0x7c00 == 0x7c00 && f32_to_half(x) & 0x3ff != 0);
+            } else {
+                assert_eq!(f32_to_half(x), h, "{:04x}", h);
+            }
+        }
+    }
+    fn assert_bits_eq(actual: &[f32], expected: &[f32], what: &str) {
+        assert_eq!(actual.len(), expected.len());
+        for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+            assert!(a.to_bits() == e.to_bits() || (a.is_nan() && e.is_nan()), "{} {}: {} != {}", what, i, a, e);
+        }
+    }
+
+    // Name, batch decoder and the scalar decoder it has to match
+    type Decoders = (&'static str, fn(&[u8], &mut [f32]), fn(u8) -> f32);
+
+    #[test]
+    fn byte_batches_match_scalar_decoders() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let mut out = vec![0.0; 256];
+        let decoders: [Decoders; 5] = [
+            ("scale", unquantize_scale_batch, unquantize_scale),
+            ("alpha", unquantize_alpha_batch, unquantize_alpha),
+            ("deterministic alpha", unquantize_alpha_deterministic_batch, unquantize_alpha_deterministic),
+            ("color", unquantize_color_batch, unquantize_color),
+            ("sh", unquantize_sh_batch, unquantize_sh),
+        ];
+        for (what, batch, scalar) in decoders {
+            batch(&bytes, &mut out);
+            let expected: Vec<f32> = bytes.iter().map(|&b| scalar(b)).collect();
+            assert_bits_eq(&out, &expected, what);
+        }
+    }
+
+    #[test]
+    fn fixed24_batch_matches_scalar_decoder() {
+        let bytes: Vec<u8> = (0..1u32 << 24).step_by(7).flat_map(|v| [v as u8, (v >> 8) as u8, (v >> 16) as u8]).collect();
+        let mut out = vec![0.0; bytes.len() / 3];
+        for fractional_bits in [0, 12, 23, 40, 255] {
+            decode_fixed24_batch(&bytes, fractional_bits, &mut out);
+            let expected: Vec<f32> = bytes.chunks_exact(3).map(|b| decode_fixed24(b, fractional_bits)).collect();
+            assert_bits_eq(&out, &expected, "fixed24");
+        }
+    }
+
+    #[test]
+    fn quat3_batch_matches_scalar_decoder() {
+        let bytes: Vec<u8> = (0..1u32 << 24).step_by(7).flat_map(|v| [v as u8, (v >> 8) as u8, (v >> 16) as u8]).collect();
+        let mut out = vec![0.0; bytes.len() / 3 * 4];
+        decode_quat3_batch(&bytes, &mut out);
+        let expected: Vec<f32> = bytes.chunks_exact(3).flat_map(decode_quat3).collect();
+        assert_bits_eq(&out, &expected, "quat3");
+    }
+
+    #[test]
+    fn half_batch_matches_scalar_decoder() {
+        let bytes: Vec<u8> = (0..=u16::MAX).flat_map(u16::to_le_bytes).collect();
+        let mut out = vec![0.0; 1 << 16];
+        half_to_f32_batch(&bytes, &mut out);
+        let expected: Vec<f32> = (0..=u16::MAX).map(half_to_f32).collect();
+        assert_bits_eq(&out, &expected, "half");
+    }
+
+    // Results of the deterministic functions are part of their contract, so any change to them
+    // has to show up here rather than in some user's hashes
+
+    #[test]
+    fn deterministic_alpha_is_pinned() {
+        for (x, expected) in [
+            (0, 0xff800000), (1, 0xc0b131d8), (2, 0xc09ae344), (17, 0xc028e651), (100, 0xbee062f3),
+            (128, 0x3c0080ac), (200, 0x3fa53ef8), (254, 0x40b131d8), (255, 0x7f800000),
+        ] {
+            assert_eq!(unquantize_alpha_deterministic(x).to_bits(), expected, "alpha {}", x);
+        }
+    }
+
+    #[test]
+    fn deterministic_ln_and_exp_are_pinned() {
+        for (x, expected) in [(0.5, 0xbfe62e42fefa39ef), (2.0, 0x3fe62e42fefa39ef), (1e-300, 0xc085963447f87fb5), (123456.789, 0x40277281cad8a844)] {
+            assert_eq!(ln_deterministic(x).to_bits(), expected, "ln {}", x);
+        }
+        for (x, expected) in [(-1.0, 0x3fd78b56362cef39), (0.5, 0x3ffa61298e1e069b), (10.0, 0x40d5829dcf950562), (-700.0, 0x00d14f2b0fb92f8b)] {
+            assert_eq!(exp_deterministic(x).to_bits(), expected, "exp {}", x);
+        }
+    }
+
+    #[test]
+    fn deterministic_functions_stay_close_to_libm() {
+        for i in 1..10_000 {
+            let x = i as f64 * 0.0137;
+            assert!((ln_deterministic(x) - x.ln()).abs() <= 1e-14 * x.ln().abs().max(1.0), "ln {}", x);
+            let y = x - 60.0;
+            assert!((exp_deterministic(y) - y.exp()).abs() <= 1e-13 * y.exp(), "exp {}", y);
+        }
+    }
+}
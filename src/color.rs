@@ -0,0 +1,79 @@
+// Conversion of decoded base colors into the forms image pipelines expect. Files store the DC
+// band of each splat's SH expansion, which is a color only after `0.5 + SH_C0 * dc`. The codec's
+// COLOR_SCALE is just the quantization step of those coefficients, not a color transform, so
+// colors decoded with it alone are neither linear nor sRGB.
+//
+// Each color byte decodes to one of only 256 values, so the batch decoders convert through a
+// table built once per call rather than evaluating the transfer function for every splat.
+
+use crate::codec::{exp_deterministic, ln_deterministic, unquantize_color};
+use crate::sh::SH_C0;
+
+/// Form of the base colors decoded by `unpack`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMode {
+    /// The DC SH coefficients as stored, which is what renderers evaluating SH expect
+    #[default]
+    ShDc,
+    /// Linear RGB. Values outside 0 to 1 are kept, as the higher SH bands can bring them back
+    /// into range.
+    Linear,
+    /// sRGB encoded RGB, clamped between 0 and 1
+    Srgb,
+    /// sRGB encoded RGB as for `Srgb`, and also 8 bit r, g, b and a for each splat in
+    /// [`UnpackedGaussians::rgba8`](crate::UnpackedGaussians::rgba8)
+    Rgba8,
+}
+
+/// DC color coefficient to linear RGB: `0.5 + SH_C0 * x`
+pub fn dc_to_linear(x: f32) -> f32 {
+    0.5 + SH_C0 * x
+}
+
+/// Linear RGB to DC color coefficient, the inverse of [`dc_to_linear`]
+pub fn linear_to_dc(x: f32) -> f32 {
+    (x - 0.5) / SH_C0
+}
+
+/// Linear RGB to the sRGB transfer curve, clamping to 0 to 1 first
+pub fn linear_to_srgb(x: f32) -> f32 {
+    let x = x.clamp(0.0, 1.0);
+    if x <= 0.003_130_8 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Same as [`linear_to_srgb`] but with basic arithmetic only, so the result is bit identical on
+/// every target
+pub fn linear_to_srgb_deterministic(x: f32) -> f32 {
+    let x = x.clamp(0.0, 1.0);
+    if x <= 0.003_130_8 {
+        x * 12.92
+    } else {
+        (1.055 * exp_deterministic(ln_deterministic(x as f64) / 2.4) - 0.055) as f32
+    }
+}
+
+/// Decodes a color byte in the given mode
+pub(crate) fn decode_color(mode: ColorMode, deterministic: bool, x: u8) -> f32 {
+    let linear = dc_to_linear(unquantize_color(x));
+    match mode {
+        ColorMode::ShDc => unquantize_color(x),
+        ColorMode::Linear => linear,
+        ColorMode::Srgb | ColorMode::Rgba8 if deterministic => linear_to_srgb_deterministic(linear),
+        ColorMode::Srgb | ColorMode::Rgba8 => linear_to_srgb(linear),
+    }
+}
+
+/// Decoded value of every color byte in the given mode
+pub(crate) fn color_table(mode: ColorMode, deterministic: bool) -> [f32; 256] {
+    std::array::from_fn(|x| decode_color(mode, deterministic, x as u8))
+}
+
+/// 8 bit sRGB value of every color byte
+pub(crate) fn srgb8_table(deterministic: bool) -> [u8; 256] {
+    let table = color_table(ColorMode::Srgb, deterministic);
+    std::array::from_fn(|x| (table[x] * 255.0).round() as u8)
+}
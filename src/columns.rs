@@ -0,0 +1,72 @@
+// Columnar view of decoded splat attributes, one f32 column per scalar attribute. This is the
+// layout expected by columnar data tooling (Arrow, Parquet, dataframes) and can be handed to those
+// libraries without any further reshaping.
+
+use crate::{dim_for_degree, PackedGaussians};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnTable {
+    pub num_rows: usize,
+    pub columns: Vec<Column>,
+}
+
+impl ColumnTable {
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    pub fn column_names(&self) -> Vec<&str> {
+        self.columns.iter().map(|c| c.name.as_str()).collect()
+    }
+}
+
+fn column_names(sh_degree: usize) -> Vec<String> {
+    let mut names: Vec<String> = [
+        "position_x", "position_y", "position_z",
+        "rotation_w", "rotation_x", "rotation_y", "rotation_z",
+        "scale_x", "scale_y", "scale_z",
+        "color_r", "color_g", "color_b",
+        "alpha",
+    ].iter().map(|s| s.to_string()).collect();
+
+    for channel in ["r", "g", "b"] {
+        for j in 0..dim_for_degree(sh_degree) {
+            names.push(format!("sh_{}_{}", channel, j));
+        }
+    }
+    names
+}
+
+impl PackedGaussians {
+    /// Decodes every splat into a table with one column per attribute. Only the SH coefficients
+    /// actually stored for the cloud's SH degree get a column.
+    pub fn to_columns(&self) -> ColumnTable {
+        let sh_dim = dim_for_degree(self.sh_degree);
+        let mut columns: Vec<Column> = column_names(self.sh_degree).into_iter()
+            .map(|name| Column { name, values: Vec::with_capacity(self.num_points) })
+            .collect();
+
+        for i in 0..self.num_points {
+            let g = self.unpack(i);
+            let row = g.position.iter()
+                .chain(g.rotation.iter())
+                .chain(g.scale.iter())
+                .chain(g.color.iter())
+                .chain(std::iter::once(&g.alpha))
+                .chain(g.sh_r[..sh_dim].iter())
+                .chain(g.sh_g[..sh_dim].iter())
+                .chain(g.sh_b[..sh_dim].iter());
+            for (column, &v) in columns.iter_mut().zip(row) {
+                column.values.push(v);
+            }
+        }
+
+        ColumnTable { num_rows: self.num_points, columns }
+    }
+}
@@ -0,0 +1,195 @@
+// Error statistics between two versions of the same cloud, such as the splats read from a PLY
+// file and the same splats after a round trip through .spz, for choosing the fractional bits and
+// SH degree of an asset or checking the encoder against the reference implementation. Splats are
+// matched by index, so both clouds need the same splats in the same order.
+
+use std::fmt;
+use std::io;
+
+use crate::color::dc_to_linear;
+use crate::math;
+use crate::{dim_for_degree, PackedGaussians, UnpackedGaussians};
+
+/// Distribution of the absolute errors of one attribute
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ErrorStats {
+    pub mean: f32,
+    /// Root mean square error
+    pub rmse: f32,
+    pub max: f32,
+}
+
+impl ErrorStats {
+    fn from_errors(errors: impl Iterator<Item = f32>) -> ErrorStats {
+        let (mut count, mut sum, mut sum_squared, mut max) = (0usize, 0.0f64, 0.0f64, 0.0f32);
+        for error in errors {
+            count += 1;
+            sum += error as f64;
+            sum_squared += error as f64 * error as f64;
+            max = max.max(error);
+        }
+        if count == 0 {
+            return ErrorStats::default();
+        }
+        ErrorStats {
+            mean: (sum / count as f64) as f32,
+            rmse: (sum_squared / count as f64).sqrt() as f32,
+            max,
+        }
+    }
+
+    /// Peak signal to noise ratio in decibels for values spanning `peak`, infinite when there's
+    /// no error at all
+    pub fn psnr(&self, peak: f32) -> f32 {
+        20.0 * (peak / self.rmse).log10()
+    }
+}
+
+/// Errors of each attribute of one cloud against another
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Comparison {
+    pub num_points: usize,
+    /// Distance between splat centers in world units
+    pub position: ErrorStats,
+    /// Angle between orientations in radians
+    pub rotation: ErrorStats,
+    /// Difference of log scales, over each axis
+    pub scale: ErrorStats,
+    /// Difference of base colors in linear RGB, over each channel
+    pub color: ErrorStats,
+    /// Difference of opacities between 0 and 1
+    pub alpha: ErrorStats,
+    /// Difference of SH coefficients above degree 0, over each coefficient and channel. Bands
+    /// only one cloud has count against the other as zero.
+    pub sh: ErrorStats,
+}
+
+impl Comparison {
+    /// PSNR of the base colors, which are displayed between 0 and 1
+    pub fn color_psnr(&self) -> f32 {
+        self.color.psnr(1.0)
+    }
+
+    /// PSNR of the SH coefficients, which are stored between -1 and 1
+    pub fn sh_psnr(&self) -> f32 {
+        self.sh.psnr(2.0)
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} splats", self.num_points)?;
+        for (name, stats) in [
+            ("position", self.position),
+            ("rotation", self.rotation),
+            ("scale", self.scale),
+            ("color", self.color),
+            ("alpha", self.alpha),
+            ("sh", self.sh),
+        ] {
+            writeln!(f, "  {}: mean {:.6}, rmse {:.6}, max {:.6}", name, stats.mean, stats.rmse, stats.max)?;
+        }
+        write!(f, "  PSNR: color {:.2} dB, sh {:.2} dB", self.color_psnr(), self.sh_psnr())
+    }
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Compares decoded splats, such as the floats of a training run against a decoded file. Colors
+/// are taken to be SH DC coefficients, as `unpack_all` gives by default.
+pub fn compare_unpacked(a: &UnpackedGaussians, b: &UnpackedGaussians) -> Result<Comparison, io::Error> {
+    if a.num_points != b.num_points {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("Clouds have different numbers of splats: {} and {}", a.num_points, b.num_points)));
+    }
+
+    let position = ErrorStats::from_errors(a.positions.chunks_exact(3).zip(b.positions.chunks_exact(3)).map(|(p, q)| {
+        ((p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2) + (p[2] - q[2]).powi(2)).sqrt()
+    }));
+    // q and -q are the same orientation, so q is flipped towards p first. The angle between unit
+    // quaternions is then 2 atan2(|p - q|, |p + q|), which unlike acos of their dot product stays
+    // accurate for nearly equal rotations, and the rotation between them is twice that.
+    let rotation = ErrorStats::from_errors(a.rotations.chunks_exact(4).zip(b.rotations.chunks_exact(4)).map(|(p, q)| {
+        let (p, q) = (math::quat_normalize([p[0], p[1], p[2], p[3]]), math::quat_normalize([q[0], q[1], q[2], q[3]]));
+        let sign = if (0..4).map(|j| p[j] * q[j]).sum::<f32>() < 0.0 { -1.0 } else { 1.0 };
+        let difference = (0..4).map(|j| (p[j] - sign * q[j]).powi(2)).sum::<f32>().sqrt();
+        let sum = (0..4).map(|j| (p[j] + sign * q[j]).powi(2)).sum::<f32>().sqrt();
+        4.0 * difference.atan2(sum)
+    }));
+    let scale = ErrorStats::from_errors(a.scales.iter().zip(&b.scales).map(|(x, y)| (x - y).abs()));
+    let color = ErrorStats::from_errors(a.colors.iter().zip(&b.colors).map(|(&x, &y)| (dc_to_linear(x) - dc_to_linear(y)).abs()));
+    let alpha = ErrorStats::from_errors(a.alphas.iter().zip(&b.alphas).map(|(&x, &y)| (sigmoid(x) - sigmoid(y)).abs()));
+
+    let a_stride = dim_for_degree(a.sh_degree) * 3;
+    let b_stride = dim_for_degree(b.sh_degree) * 3;
+    let sh_stride = a_stride.max(b_stride);
+    let sh = ErrorStats::from_errors((0..a.num_points * sh_stride).map(|k| {
+        let (i, j) = (k / sh_stride, k % sh_stride);
+        let coefficient = |sh: &[f32], stride: usize| if j < stride { sh[i * stride + j] } else { 0.0 };
+        (coefficient(&a.sh, a_stride) - coefficient(&b.sh, b_stride)).abs()
+    }));
+
+    Ok(Comparison { num_points: a.num_points, position, rotation, scale, color, alpha, sh })
+}
+
+impl PackedGaussians {
+    /// Errors of this cloud's decoded splats against another's, such as an original against its
+    /// requantized copy
+    pub fn compare(&self, other: &PackedGaussians) -> Result<Comparison, io::Error> {
+        compare_unpacked(&self.unpack_all(), &other.unpack_all())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+
+    #[test]
+    fn a_cloud_has_no_error_against_itself() {
+        let cloud = random_cloud(100, 2, 40);
+        let comparison = cloud.compare(&cloud).unwrap();
+        assert_eq!(comparison.num_points, 100);
+        for stats in [comparison.position, comparison.rotation, comparison.scale, comparison.color, comparison.alpha, comparison.sh] {
+            assert_eq!(stats, ErrorStats::default());
+        }
+        assert_eq!(comparison.color_psnr(), f32::INFINITY);
+        assert_eq!(comparison.sh_psnr(), f32::INFINITY);
+    }
+
+    #[test]
+    fn negated_quaternions_are_the_same_rotation() {
+        let a = random_cloud(100, 0, 41).unpack_all();
+        let mut b = a.clone();
+        for x in b.rotations.iter_mut() {
+            *x = -*x;
+        }
+        let rotation = compare_unpacked(&a, &b).unwrap().rotation;
+        assert!(rotation.max < 1e-3, "{:?}", rotation);
+    }
+
+    #[test]
+    fn missing_bands_count_as_zero() {
+        let a = random_cloud(50, 2, 42).unpack_all();
+        let (a_stride, b_stride) = (dim_for_degree(2) * 3, dim_for_degree(1) * 3);
+        let mut b = a.clone();
+        b.sh_degree = 1;
+        b.sh = a.sh.chunks_exact(a_stride).flat_map(|sh| sh[..b_stride].to_vec()).collect();
+
+        let expected = ErrorStats::from_errors(a.sh.chunks_exact(a_stride)
+            .flat_map(|sh| sh.iter().enumerate().map(|(j, x)| if j < b_stride { 0.0 } else { x.abs() })));
+        for (first, second) in [(&a, &b), (&b, &a)] {
+            let sh = compare_unpacked(first, second).unwrap().sh;
+            assert!((sh.mean - expected.mean).abs() < 1e-6 && (sh.rmse - expected.rmse).abs() < 1e-6, "{:?} vs {:?}", sh, expected);
+            assert_eq!(sh.max, expected.max);
+        }
+    }
+
+    #[test]
+    fn different_splat_counts_are_an_error() {
+        let (a, b) = (random_cloud(10, 0, 43), random_cloud(11, 0, 43));
+        assert_eq!(a.compare(&b).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,216 @@
+// Concatenation of whole clouds, such as per room scans stitched into one building. Clouds are
+// usually first moved into a shared frame with `transform`. Splats are copied as they are where
+// the encodings agree. Positions are re-encoded where they don't, and SH coefficients are padded
+// with zeros or dropped to reach the merged degree. Clouds in another axis convention than the
+// first are converted to it first.
+
+use std::borrow::Cow;
+
+use crate::attributes::CustomAttributes;
+use crate::codec::decode_fixed24;
+use crate::coordinates::CoordinateSystem;
+use crate::error::SpzError;
+use crate::ply::{has_ply_extension, load_gaussians_from_ply_file};
+use crate::validate::MAX_FRACTIONAL_BITS;
+use crate::{dim_for_degree, load_packed_gaussians_from_file, quantize_position, PackedGaussians, PositionEncoding, MAX_SH_DEGREE};
+
+// Used when no cloud has fixed point positions to take the precision from
+const DEFAULT_FRACTIONAL_BITS: usize = 12;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MergeOptions {
+    /// SH degree of the merged cloud. Defaults to the highest degree of the inputs.
+    pub sh_degree: Option<usize>,
+    /// Fractional bits of the merged positions. Defaults to the fewest of the fixed point inputs,
+    /// lowered if needed so float16 inputs fit in range.
+    pub fractional_bits: Option<usize>,
+}
+
+impl MergeOptions {
+    pub fn sh_degree(mut self, sh_degree: usize) -> MergeOptions {
+        self.sh_degree = Some(sh_degree);
+        self
+    }
+
+    pub fn fractional_bits(mut self, fractional_bits: usize) -> MergeOptions {
+        self.fractional_bits = Some(fractional_bits);
+        self
+    }
+}
+
+/// The most fractional bits which keeps every position of the clouds in range without losing
+/// precision already present in the fixed point inputs
+fn merged_fractional_bits(clouds: &[&PackedGaussians]) -> usize {
+    let fixed = clouds.iter().filter(|c| !c.uses_float16() && c.num_points > 0).map(|c| c.fractional_bits).min();
+    let mut fractional_bits = fixed.unwrap_or(DEFAULT_FRACTIONAL_BITS).min(MAX_FRACTIONAL_BITS);
+
+    let extent = clouds.iter().filter(|c| c.uses_float16())
+        .flat_map(|c| (0..c.num_points).map(|i| c.unpack_position(i)))
+        .flat_map(|p| p.map(f32::abs))
+        .filter(|x| x.is_finite())
+        .fold(0.0f32, f32::max);
+    while fractional_bits > 0 && extent > decode_fixed24(&[0xff, 0xff, 0x7f], fractional_bits as u32) {
+        fractional_bits -= 1;
+    }
+    fractional_bits
+}
+
+impl PackedGaussians {
+    /// Concatenates clouds into one, in order, with the default [`MergeOptions`]
+    pub fn merge(clouds: &[&PackedGaussians]) -> PackedGaussians {
+        PackedGaussians::merge_with(clouds, &MergeOptions::default())
+    }
+
+    /// Concatenates clouds into one. The result always uses fixed point positions, and is
+    /// antialiased only if every input is. Custom attributes which every input has are kept. The
+    /// result is in the coordinate system of the first cloud, and the others are converted to it.
+    pub fn merge_with(clouds: &[&PackedGaussians], options: &MergeOptions) -> PackedGaussians {
+        let coordinate_system = clouds.first().map_or(CoordinateSystem::Unspecified, |c| c.coordinate_system);
+        // Unspecified clouds are in RUB, the convention of the format
+        let frame = |system| if system == CoordinateSystem::Unspecified { CoordinateSystem::RUB } else { system };
+        let converted: Vec<Cow<PackedGaussians>> = clouds.iter().map(|&cloud| {
+            if frame(cloud.coordinate_system) == frame(coordinate_system) {
+                Cow::Borrowed(cloud)
+            } else {
+                let mut cloud = cloud.clone();
+                cloud.convert_coordinates(frame(coordinate_system));
+                Cow::Owned(cloud)
+            }
+        }).collect();
+        let clouds: Vec<&PackedGaussians> = converted.iter().map(|c| c.as_ref()).collect();
+        let clouds = &clouds[..];
+
+        let sh_degree = options.sh_degree
+            .unwrap_or_else(|| clouds.iter().map(|c| c.sh_degree).max().unwrap_or(0))
+            .min(MAX_SH_DEGREE);
+        let fractional_bits = options.fractional_bits.unwrap_or_else(|| merged_fractional_bits(clouds));
+        let sh_stride = dim_for_degree(sh_degree) * 3;
+        let n: usize = clouds.iter().map(|c| c.num_points).sum();
+
+        let mut result = PackedGaussians {
+            num_points: n,
+            sh_degree,
+            fractional_bits,
+            antialiased: !clouds.is_empty() && clouds.iter().all(|c| c.antialiased),
+            positions: Vec::with_capacity(n * 9),
+            scales: Vec::with_capacity(n * 3),
+            rotations: Vec::with_capacity(n * 3),
+            alphas: Vec::with_capacity(n),
+            colors: Vec::with_capacity(n * 3),
+            sh: Vec::with_capacity(n * sh_stride),
+            attributes: CustomAttributes::concat(&clouds.iter().map(|c| &c.attributes).collect::<Vec<_>>()),
+            position_encoding: PositionEncoding::FixedPoint24,
+            coordinate_system,
+        };
+
+        for cloud in clouds {
+            if !cloud.uses_float16() && cloud.fractional_bits == fractional_bits {
+                result.positions.extend_from_slice(&cloud.positions);
+            } else {
+                for i in 0..cloud.num_points {
+                    result.positions.extend_from_slice(&quantize_position(cloud.unpack_position(i), false, fractional_bits as u32));
+                }
+            }
+            result.scales.extend_from_slice(&cloud.scales);
+            result.rotations.extend_from_slice(&cloud.rotations);
+            result.alphas.extend_from_slice(&cloud.alphas);
+            result.colors.extend_from_slice(&cloud.colors);
+
+            let cloud_stride = dim_for_degree(cloud.sh_degree) * 3;
+            if cloud_stride == sh_stride {
+                result.sh.extend_from_slice(&cloud.sh);
+            } else {
+                let kept = cloud_stride.min(sh_stride);
+                for i in 0..cloud.num_points {
+                    result.sh.extend_from_slice(&cloud.sh[i * cloud_stride..i * cloud_stride + kept]);
+                    // 128 decodes to a zero coefficient
+                    result.sh.resize(result.sh.len() + sh_stride - kept, 128);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// Loads .spz and .ply files and concatenates them with [`PackedGaussians::merge_with`]
+pub fn merge_files(filenames: &[&str], options: &MergeOptions) -> Result<PackedGaussians, SpzError> {
+    let mut clouds = Vec::new();
+    for &filename in filenames {
+        clouds.push(if has_ply_extension(filename) {
+            load_gaussians_from_ply_file(filename)?
+        } else {
+            load_packed_gaussians_from_file(filename)?
+        });
+    }
+    Ok(PackedGaussians::merge_with(&clouds.iter().collect::<Vec<_>>(), options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+
+    #[test]
+    fn mismatched_sh_degrees_are_padded_or_dropped() {
+        let low = random_cloud(10, 1, 1);
+        let high = random_cloud(20, 3, 2);
+
+        let merged = PackedGaussians::merge(&[&low, &high]);
+        assert_eq!((merged.num_points, merged.sh_degree), (30, 3));
+        for i in 0..10 {
+            assert_eq!(&merged.sh[i * 45..i * 45 + 9], &low.sh[i * 9..i * 9 + 9]);
+            assert!(merged.sh[i * 45 + 9..i * 45 + 45].iter().all(|&b| b == 128));
+        }
+        assert_eq!(&merged.sh[10 * 45..], &high.sh[..]);
+
+        let truncated = PackedGaussians::merge_with(&[&low, &high], &MergeOptions::default().sh_degree(1));
+        assert_eq!(truncated.sh_degree, 1);
+        assert_eq!(&truncated.sh[..90], &low.sh[..]);
+        for i in 0..20 {
+            assert_eq!(&truncated.sh[90 + i * 9..90 + i * 9 + 9], &high.sh[i * 45..i * 45 + 9]);
+        }
+        assert!(truncated.validate().is_ok());
+    }
+
+    #[test]
+    fn other_conventions_are_converted_to_the_first() {
+        let mut first = random_cloud(10, 1, 5);
+        first.coordinate_system = CoordinateSystem::RDF;
+        let second = random_cloud(10, 1, 6);
+
+        let merged = PackedGaussians::merge(&[&first, &second]);
+        assert_eq!(merged.coordinate_system, CoordinateSystem::RDF);
+        assert_eq!(&merged.positions[..90], &first.positions[..]);
+        // RUB to RDF negates y and z
+        for i in 0..10 {
+            let [x, y, z] = second.unpack_position(i);
+            assert_eq!(merged.unpack_position(10 + i), [x, -y, -z]);
+        }
+    }
+
+    #[test]
+    fn mismatched_fractional_bits_use_the_coarsest() {
+        let mut fine = random_cloud(10, 0, 3);
+        let mut coarse = random_cloud(10, 0, 4);
+        fine.fractional_bits = 12;
+        coarse.fractional_bits = 8;
+
+        let merged = PackedGaussians::merge(&[&fine, &coarse]);
+        assert_eq!(merged.fractional_bits, 8);
+        // The coarse positions are copied as they are, and the fine ones rounded to its step
+        assert_eq!(&merged.positions[90..], &coarse.positions[..]);
+        for i in 0..10 {
+            let (expected, actual) = (fine.unpack_position(i), merged.unpack_position(i));
+            for j in 0..3 {
+                assert!((expected[j] - actual[j]).abs() <= 0.5 / 256.0, "{} != {}", actual[j], expected[j]);
+            }
+        }
+        // Everything but the positions is copied unchanged
+        assert_eq!(&merged.rotations[..30], &fine.rotations[..]);
+        assert_eq!(&merged.alphas[10..], &coarse.alphas[..]);
+
+        let forced = PackedGaussians::merge_with(&[&fine, &coarse], &MergeOptions::default().fractional_bits(12));
+        assert_eq!(&forced.positions[..90], &fine.positions[..]);
+    }
+}
@@ -0,0 +1,235 @@
+// Conversion between the axis conventions of different engines, applied while unpacking.
+// Conventions are named after the directions of their x, y and z axes, each Left or Right, Up
+// or Down and Forward or Back, as in the reference implementation. .spz files always store
+// splats in RUB, the OpenGL convention, and don't record it, so any other convention is
+// tracked in memory and converted away when saving.
+//
+// A change of convention is a signed permutation of the axes. Positions are permuted and
+// flipped directly. Orientations are conjugated by the change, which keeps them proper
+// rotations even when it mirrors the scene, and the scales are permuted to follow the axes.
+// View dependent color is transformed band by band with exact tables, so every step only moves
+// and negates values, apart from the SH bands mixed by a swap involving z.
+
+use std::sync::OnceLock;
+
+use crate::math::{self, Mat3};
+use crate::{sh, UnpackedGaussian, MAX_SH_DEGREE};
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CoordinateSystem {
+    /// Leaves splats in the convention they're stored in
+    #[default]
+    Unspecified,
+    LDB,
+    RDB,
+    LUB,
+    /// Y up, right handed, as used by OpenGL, three.js and the .spz format itself
+    RUB,
+    /// Y down, right handed, as used by OpenCV and COLMAP
+    LDF,
+    RDF,
+    /// Y up, left handed, as used by Unity
+    LUF,
+    RUF,
+    /// Z up, right handed, as used by Blender
+    RFU,
+    /// Z up, left handed, as used by Unreal
+    FRU,
+}
+
+const SYSTEM_COUNT: usize = 11;
+
+impl CoordinateSystem {
+    /// Matrix taking coordinates in this convention to RUB, whose columns are this convention's
+    /// axes expressed in RUB
+    fn to_rub(self) -> Option<Mat3> {
+        use CoordinateSystem::*;
+        let (r, u, b) = ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]);
+        let (l, d, f) = ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]);
+        let axes: [[f32; 3]; 3] = match self {
+            Unspecified => return None,
+            LDB => [l, d, b],
+            RDB => [r, d, b],
+            LUB => [l, u, b],
+            RUB => [r, u, b],
+            LDF => [l, d, f],
+            RDF => [r, d, f],
+            LUF => [l, u, f],
+            RUF => [r, u, f],
+            RFU => [r, f, u],
+            FRU => [f, r, u],
+        };
+        Some([0, 1, 2].map(|row| axes.map(|axis| axis[row])))
+    }
+}
+
+// SH matrices for each pair of conventions, built the first time the pair is used
+static SH_MATRICES: [OnceLock<Vec<Vec<f32>>>; SYSTEM_COUNT * SYSTEM_COUNT] = [const { OnceLock::new() }; SYSTEM_COUNT * SYSTEM_COUNT];
+
+/// A change of convention between two coordinate systems
+#[derive(Clone, Copy)]
+pub(crate) struct AxisConversion {
+    matrix: Mat3,
+    // Proper rotation with the same effect on orientations as the matrix
+    rotation: Mat3,
+    // Source axis of each destination axis
+    permutation: [usize; 3],
+    sh: &'static [Vec<f32>],
+}
+
+impl AxisConversion {
+    /// The conversion between two conventions, or None if there's nothing to do
+    pub(crate) fn new(from: CoordinateSystem, to: CoordinateSystem) -> Option<AxisConversion> {
+        if from == to {
+            return None;
+        }
+        let from_rub = from.to_rub()?;
+        let to_rub = to.to_rub()?;
+
+        // Coordinates in `to` are the transpose of its axes applied to coordinates in RUB
+        let mut matrix = [[0.0; 3]; 3];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..3).map(|k| to_rub[k][i] * from_rub[k][j]).sum();
+            }
+        }
+
+        let sign = math::mat3_determinant(&matrix).signum();
+        let rotation = matrix.map(|row| row.map(|v| v * sign));
+        let permutation = matrix.map(|row| row.iter().position(|&v| v != 0.0).unwrap_or(0));
+        let sh = SH_MATRICES[from as usize * SYSTEM_COUNT + to as usize]
+            .get_or_init(|| sh::axis_change_matrices(&matrix, MAX_SH_DEGREE));
+        Some(AxisConversion { matrix, rotation, permutation, sh })
+    }
+
+    /// Matrices of each SH band, up to [`MAX_SH_DEGREE`]
+    pub(crate) fn sh_matrices(&self) -> &'static [Vec<f32>] {
+        self.sh
+    }
+
+    pub(crate) fn convert_position(&self, position: &mut [f32]) {
+        let p = [position[0], position[1], position[2]];
+        position.copy_from_slice(&math::mat3_mul_vec3(&self.matrix, p));
+    }
+
+    /// Converts a w, x, y, z quaternion. Conjugating by a rotation keeps w and rotates the
+    /// vector part, which for a signed permutation only moves and negates components.
+    pub(crate) fn convert_rotation(&self, rotation: &mut [f32]) {
+        let v = [rotation[1], rotation[2], rotation[3]];
+        rotation[1..4].copy_from_slice(&math::mat3_mul_vec3(&self.rotation, v));
+    }
+
+    /// Permutes log scales, or their packed bytes
+    pub(crate) fn convert_scale<T: Copy>(&self, scale: &mut [T]) {
+        let s = [scale[0], scale[1], scale[2]];
+        for (i, &j) in self.permutation.iter().enumerate() {
+            scale[i] = s[j];
+        }
+    }
+
+    /// Converts the SH coefficients of one color channel for the first `sh_degree` bands, with
+    /// coefficient `j` at `coefficients[j * stride]`
+    pub(crate) fn convert_sh(&self, coefficients: &mut [f32], stride: usize, sh_degree: usize) {
+        for (l, m) in self.sh.iter().take(sh_degree).enumerate() {
+            let offset = (l + 1) * (l + 1) - 1;
+            let size = 2 * l + 3;
+            let mut original = [0.0; 2 * MAX_SH_DEGREE + 1];
+            for (j, v) in original[..size].iter_mut().enumerate() {
+                *v = coefficients[(offset + j) * stride];
+            }
+            for row in 0..size {
+                coefficients[(offset + row) * stride] = (0..size).map(|j| m[row * size + j] * original[j]).sum();
+            }
+        }
+    }
+
+    pub(crate) fn convert(&self, gaussian: &mut UnpackedGaussian) {
+        self.convert_position(&mut gaussian.position);
+        self.convert_rotation(&mut gaussian.rotation);
+        self.convert_scale(&mut gaussian.scale);
+        for channel in [&mut gaussian.sh_r, &mut gaussian.sh_g, &mut gaussian.sh_b] {
+            self.convert_sh(channel, 1, MAX_SH_DEGREE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAX_SH_DIM;
+    use CoordinateSystem::*;
+
+    const SYSTEMS: [CoordinateSystem; 10] = [LDB, RDB, LUB, RUB, LDF, RDF, LUF, RUF, RFU, FRU];
+
+    fn conversions() -> impl Iterator<Item = AxisConversion> {
+        SYSTEMS.into_iter().flat_map(|from| SYSTEMS.into_iter().filter_map(move |to| AxisConversion::new(from, to)))
+    }
+
+    #[test]
+    fn sh_tables_match_the_fit() {
+        for conversion in conversions() {
+            let fitted = sh::rotation_matrices(&conversion.matrix, MAX_SH_DEGREE);
+            for (exact, fitted) in conversion.sh.iter().zip(&fitted) {
+                for (a, b) in exact.iter().zip(fitted) {
+                    assert!((a - b).abs() < 1e-4, "{:?} against {:?}", conversion.matrix, fitted);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sh_tables_are_orthogonal() {
+        for conversion in conversions() {
+            for band in conversion.sh {
+                let size = (band.len() as f64).sqrt() as usize;
+                for i in 0..size {
+                    for j in 0..size {
+                        let dot: f32 = (0..size).map(|k| band[i * size + k] * band[j * size + k]).sum();
+                        assert!((dot - if i == j { 1.0 } else { 0.0 }).abs() < 1e-6);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rotations_match_quaternion_conjugation() {
+        let q = math::quat_normalize([0.8, -0.3, 0.4, 0.2]);
+        for conversion in conversions() {
+            let p = math::mat3_to_quat(&conversion.rotation);
+            let expected = math::quat_mul(math::quat_mul(p, q), [p[0], -p[1], -p[2], -p[3]]);
+            let mut converted = q;
+            conversion.convert_rotation(&mut converted);
+            // Either sign is the same orientation
+            let sign = if expected[0] * converted[0] < 0.0 { -1.0 } else { 1.0 };
+            for (a, b) in converted.iter().zip(expected) {
+                assert!((a - sign * b).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn converting_back_restores_the_gaussian() {
+        let mut original = UnpackedGaussian {
+            position: [1.5, -2.25, 3.0],
+            rotation: math::quat_normalize([0.8, -0.3, 0.4, 0.2]),
+            scale: [-1.0, -2.0, -3.0],
+            ..Default::default()
+        };
+        for (j, v) in original.sh_r.iter_mut().enumerate() {
+            *v = (j as f32 * 0.37).sin();
+        }
+        for from in SYSTEMS {
+            for to in SYSTEMS.into_iter().filter(|&to| to != from) {
+                let mut g = original.clone();
+                AxisConversion::new(from, to).unwrap().convert(&mut g);
+                AxisConversion::new(to, from).unwrap().convert(&mut g);
+                assert_eq!((g.position, g.rotation, g.scale), (original.position, original.rotation, original.scale));
+                for j in 0..MAX_SH_DIM {
+                    assert!((g.sh_r[j] - original.sh_r[j]).abs() < 1e-6, "{from:?} to {to:?}");
+                }
+            }
+        }
+    }
+}
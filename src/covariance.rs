@@ -0,0 +1,71 @@
+// 3D covariances of splats, the form most rasterizers consume instead of scales and rotations.
+// Buffers hold the six unique elements of each symmetric matrix in the order xx, xy, xz, yy, yz,
+// zz, the layout of the cov3D arrays in the reference 3DGS rasterizer and the shaders derived
+// from it.
+
+use crate::codec::unquantize_scale;
+use crate::math::{self, Mat3};
+use crate::view::PackedGaussiansView;
+use crate::{PackedGaussians, UnpackOptions, UnpackedGaussian, UnpackedGaussians};
+
+/// Number of floats per splat in a covariance buffer
+pub const COVARIANCE_DIM: usize = 6;
+
+/// The six unique elements of a symmetric matrix, xx, xy, xz, yy, yz, zz
+pub fn upper_triangle(m: &Mat3) -> [f32; COVARIANCE_DIM] {
+    [m[0][0], m[0][1], m[0][2], m[1][1], m[1][2], m[2][2]]
+}
+
+impl UnpackedGaussian {
+    /// Covariance of the splat, R * S * S * R^T for its rotation R and scales S
+    pub fn covariance3d(&self) -> Mat3 {
+        math::covariance_from_scale_rotation(self.scale, self.rotation)
+    }
+}
+
+impl UnpackedGaussians {
+    /// Covariances of all splats, six floats each
+    pub fn covariances(&self) -> Vec<f32> {
+        self.scales.chunks_exact(3)
+            .zip(self.rotations.chunks_exact(4))
+            .flat_map(|(scale, rotation)| {
+                let m = math::covariance_from_scale_rotation([scale[0], scale[1], scale[2]], [rotation[0], rotation[1], rotation[2], rotation[3]]);
+                upper_triangle(&m)
+            })
+            .collect()
+    }
+}
+
+impl PackedGaussiansView<'_> {
+    pub fn covariances(&self) -> Vec<f32> {
+        self.covariances_with(&UnpackOptions::default())
+    }
+
+    /// Covariances of all splats, six floats each, decoding only the scales and rotations. The
+    /// options apply as they do to `unpack_all_with`, so the covariances match its splats.
+    pub fn covariances_with(&self, options: &UnpackOptions) -> Vec<f32> {
+        let options = options.for_stored(self.coordinate_system);
+        let conversion = options.conversion();
+        let mut result = Vec::with_capacity(self.num_points * COVARIANCE_DIM);
+        for (scale, rotation) in self.scales.chunks_exact(3).zip(self.rotations.chunks_exact(3)) {
+            let mut scale = [0, 1, 2].map(|j| unquantize_scale(scale[j]));
+            let mut rotation = options.decode_rotation(rotation);
+            if let Some(conversion) = conversion {
+                conversion.convert_scale(&mut scale);
+                conversion.convert_rotation(&mut rotation);
+            }
+            result.extend_from_slice(&upper_triangle(&math::covariance_from_scale_rotation(scale, rotation)));
+        }
+        result
+    }
+}
+
+impl PackedGaussians {
+    pub fn covariances(&self) -> Vec<f32> {
+        self.view().covariances()
+    }
+
+    pub fn covariances_with(&self, options: &UnpackOptions) -> Vec<f32> {
+        self.view().covariances_with(options)
+    }
+}
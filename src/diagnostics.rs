@@ -0,0 +1,319 @@
+// Quality checks for splat assets, gathering validation, artifact detection, statistics and size
+// analysis into a single report with suggested fixes. This backs the `spz doctor` command.
+
+use std::fmt;
+
+use crate::repair::ZeroRotationPolicy;
+use crate::validate::MAX_FRACTIONAL_BITS;
+use crate::{dim_for_degree, PackedGaussians, MAX_SH_DEGREE};
+
+/// Splats further than this many times the median distance from the center of the cloud are
+/// considered floaters
+pub const FLOATER_DISTANCE_FACTOR: f32 = 8.0;
+
+/// Splats with a long axis more than this many times their short axis are considered needles
+pub const NEEDLE_RATIO: f32 = 10.0;
+
+// Suggest dropping SH when it takes up more than this fraction of the payload
+const SH_SIZE_FRACTION: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// Automatic fixes which can be applied with [`PackedGaussians::apply_fix`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fix {
+    ClampNeedles,
+    RemoveFloaters,
+    RemoveTransparent,
+    ResetZeroRotations,
+    ConvertZUp,
+    DropSh,
+}
+
+impl Fix {
+    /// Command line flag which applies this fix in `spz doctor`
+    pub fn flag(&self) -> &'static str {
+        match self {
+            Fix::ClampNeedles => "--clamp-needles",
+            Fix::RemoveFloaters => "--remove-floaters",
+            Fix::RemoveTransparent => "--remove-transparent",
+            Fix::ResetZeroRotations => "--reset-zero-rotations",
+            Fix::ConvertZUp => "--z-up-to-y-up",
+            Fix::DropSh => "--drop-sh",
+        }
+    }
+
+    pub fn from_flag(flag: &str) -> Option<Fix> {
+        [Fix::ClampNeedles, Fix::RemoveFloaters, Fix::RemoveTransparent, Fix::ResetZeroRotations, Fix::ConvertZUp, Fix::DropSh]
+            .into_iter()
+            .find(|fix| fix.flag() == flag)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    pub suggestion: Option<String>,
+    pub fix: Option<Fix>,
+}
+
+/// Distribution of splat sizes, as the length of each splat's longest axis in world units
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ScaleStats {
+    pub min: f32,
+    pub median: f32,
+    pub p99: f32,
+    pub max: f32,
+}
+
+/// Uncompressed size of each section of the payload, in bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SectionSizes {
+    pub positions: usize,
+    pub alphas: usize,
+    pub colors: usize,
+    pub scales: usize,
+    pub rotations: usize,
+    pub sh: usize,
+}
+
+impl SectionSizes {
+    pub fn total(&self) -> usize {
+        self.positions + self.alphas + self.colors + self.scales + self.rotations + self.sh
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Diagnosis {
+    pub num_points: usize,
+    pub sh_degree: usize,
+    pub bounds: Option<([f32; 3], [f32; 3])>,
+    pub scale_stats: ScaleStats,
+    pub sizes: SectionSizes,
+    pub findings: Vec<Finding>,
+}
+
+impl Diagnosis {
+    pub fn has_errors(&self) -> bool {
+        self.findings.iter().any(|f| f.severity == Severity::Error)
+    }
+
+    fn add(&mut self, severity: Severity, message: String, suggestion: Option<&str>, fix: Option<Fix>) {
+        self.findings.push(Finding { severity, message, suggestion: suggestion.map(str::to_string), fix });
+    }
+}
+
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((sorted.len() - 1) as f32 * p).round() as usize;
+    sorted[index]
+}
+
+impl PackedGaussians {
+    /// Indices of splats which are isolated far away from the bulk of the cloud, typically
+    /// training artifacts floating in front of the camera or in the sky
+    pub fn floater_indices(&self, distance_factor: f32) -> Vec<usize> {
+        if self.num_points == 0 {
+            return Vec::new();
+        }
+
+        let positions: Vec<[f32; 3]> = (0..self.num_points).map(|i| self.unpack_position(i)).collect();
+        let center = [0, 1, 2].map(|j| {
+            let mut values: Vec<f32> = positions.iter().map(|p| p[j]).collect();
+            values.sort_by(f32::total_cmp);
+            percentile(&values, 0.5)
+        });
+        let distances: Vec<f32> = positions.iter()
+            .map(|p| ((p[0] - center[0]).powi(2) + (p[1] - center[1]).powi(2) + (p[2] - center[2]).powi(2)).sqrt())
+            .collect();
+        let mut sorted = distances.clone();
+        sorted.sort_by(f32::total_cmp);
+        let limit = percentile(&sorted, 0.5) * distance_factor;
+        if limit <= 0.0 {
+            return Vec::new();
+        }
+
+        distances.iter().enumerate()
+            .filter(|(_, &d)| d > limit)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Guesses whether the cloud was exported Z up. Captured scenes usually spread out along the
+    /// ground more than they extend vertically, so the axis with the smallest spread is likely
+    /// to be up.
+    pub fn looks_z_up(&self) -> bool {
+        if self.num_points == 0 {
+            return false;
+        }
+        let spreads = [0, 1, 2].map(|j| {
+            let mut values: Vec<f32> = (0..self.num_points).map(|i| self.unpack_position(i)[j]).collect();
+            values.sort_by(f32::total_cmp);
+            percentile(&values, 0.95) - percentile(&values, 0.05)
+        });
+        spreads[2] < spreads[0] && spreads[2] < 0.5 * spreads[1]
+    }
+
+    pub fn section_sizes(&self) -> SectionSizes {
+        SectionSizes {
+            positions: self.positions.len(),
+            alphas: self.alphas.len(),
+            colors: self.colors.len(),
+            scales: self.scales.len(),
+            rotations: self.rotations.len(),
+            sh: self.sh.len(),
+        }
+    }
+
+    pub fn diagnose(&self) -> Diagnosis {
+        let n = self.num_points;
+        let mut diagnosis = Diagnosis {
+            num_points: n,
+            sh_degree: self.sh_degree,
+            sizes: self.section_sizes(),
+            ..Default::default()
+        };
+
+        // Validation. Later checks index the buffers, so stop here if they're inconsistent.
+        if self.sh_degree > MAX_SH_DEGREE {
+            diagnosis.add(Severity::Error, format!("Unsupported SH degree {}", self.sh_degree), None, None);
+        }
+        if self.fractional_bits > MAX_FRACTIONAL_BITS {
+            diagnosis.add(Severity::Error,
+                format!("{} fractional bits leaves no room for the integer part of positions", self.fractional_bits),
+                None, None);
+        }
+        let position_bytes = self.position_encoding.size();
+        let lengths_valid = self.positions.len() == n * position_bytes
+            && self.alphas.len() == n
+            && self.colors.len() == n * 3
+            && self.scales.len() == n * 3
+            && self.rotations.len() == n * 3
+            && self.sh.len() == n * dim_for_degree(self.sh_degree) * 3;
+        if !lengths_valid {
+            diagnosis.add(Severity::Error, "Attribute buffers don't match the number of splats".to_string(), None, None);
+        }
+        if diagnosis.has_errors() {
+            return diagnosis;
+        }
+        if n == 0 {
+            diagnosis.add(Severity::Warning, "The file contains no splats".to_string(), None, None);
+            return diagnosis;
+        }
+
+        let non_finite = (0..n).filter(|&i| self.unpack_position(i).iter().any(|v| !v.is_finite())).count();
+        if non_finite > 0 {
+            diagnosis.add(Severity::Error, format!("{} splats have non-finite positions", non_finite),
+                Some("Re-export the asset from the training tool"), None);
+        }
+        if self.uses_float16() {
+            diagnosis.add(Severity::Info, "Positions are stored as float16 (version 1)".to_string(),
+                Some("Re-save as version 2 for fixed point positions with uniform precision"), None);
+        }
+
+        let zero_rotations = self.count_zero_rotations();
+        if zero_rotations > 0 {
+            diagnosis.add(Severity::Warning, format!("{} splats have all zero rotation bytes", zero_rotations),
+                Some("These decode to a skewed orientation, the exporter probably didn't write rotations"),
+                Some(Fix::ResetZeroRotations));
+        }
+
+        // Artifacts
+        let transparent = self.alphas.iter().filter(|&&a| a == 0).count();
+        if transparent > 0 {
+            diagnosis.add(Severity::Warning, format!("{} splats are fully transparent", transparent),
+                Some("They cost space and sorting time without being visible"), Some(Fix::RemoveTransparent));
+        }
+        let floaters = self.floater_indices(FLOATER_DISTANCE_FACTOR).len();
+        if floaters > 0 {
+            diagnosis.add(Severity::Warning,
+                format!("{} floaters more than {}x the median distance from the center", floaters, FLOATER_DISTANCE_FACTOR),
+                Some("Floaters are usually training artifacts and inflate the bounds"), Some(Fix::RemoveFloaters));
+        }
+        let needles = self.count_anisotropic(NEEDLE_RATIO);
+        if needles > 0 {
+            diagnosis.add(Severity::Warning,
+                format!("{} needle-like splats with anisotropy above {}", needles, NEEDLE_RATIO),
+                Some("Needles shimmer as the view moves"), Some(Fix::ClampNeedles));
+        }
+
+        // Scale statistics
+        let mut sizes: Vec<f32> = (0..n)
+            .map(|i| self.unpack_scale(i).iter().fold(f32::NEG_INFINITY, |m, &s| m.max(s)).exp())
+            .collect();
+        sizes.sort_by(f32::total_cmp);
+        diagnosis.scale_stats = ScaleStats {
+            min: sizes[0],
+            median: percentile(&sizes, 0.5),
+            p99: percentile(&sizes, 0.99),
+            max: sizes[n - 1],
+        };
+
+        let bounds = self.compute_bounds();
+        diagnosis.bounds = bounds;
+        if self.looks_z_up() {
+            diagnosis.add(Severity::Info,
+                "The cloud is flattest along z, so it may have been exported Z up".to_string(),
+                Some("The .spz format is y up, check the asset displays upright and rotate it if not"),
+                Some(Fix::ConvertZUp));
+        }
+        if let Some((min, max)) = bounds {
+            let extent = (0..3).map(|j| max[j] - min[j]).fold(0.0f32, f32::max);
+            if extent > 0.0 && diagnosis.scale_stats.p99 > 0.1 * extent {
+                diagnosis.add(Severity::Warning,
+                    format!("1% of splats are longer than {:.3}, over a tenth of the scene extent", diagnosis.scale_stats.p99),
+                    Some("Very large splats are often background artifacts which cause popping when sorted"), None);
+            }
+        }
+
+        // Size
+        let total = diagnosis.sizes.total();
+        if total > 0 && diagnosis.sizes.sh as f32 > SH_SIZE_FRACTION * total as f32 {
+            diagnosis.add(Severity::Info,
+                format!("SH coefficients take up {:.0}% of the payload", 100.0 * diagnosis.sizes.sh as f32 / total as f32),
+                Some("Drop SH if view dependent color isn't needed, for example on mobile"), Some(Fix::DropSh));
+        }
+
+        diagnosis
+    }
+
+    /// Applies a fix suggested by [`PackedGaussians::diagnose`], returning the number of splats
+    /// changed or removed
+    pub fn apply_fix(&mut self, fix: Fix) -> usize {
+        match fix {
+            Fix::ClampNeedles => self.clamp_anisotropy(NEEDLE_RATIO),
+            Fix::RemoveFloaters => {
+                let mut floater = vec![false; self.num_points];
+                for i in self.floater_indices(FLOATER_DISTANCE_FACTOR) {
+                    floater[i] = true;
+                }
+                self.retain_indices(|i| !floater[i])
+            }
+            Fix::RemoveTransparent => self.prune_transparent(0.0),
+            Fix::ResetZeroRotations => self.handle_zero_rotations(ZeroRotationPolicy::Identity).unwrap_or(0),
+            Fix::ConvertZUp => {
+                self.convert_z_up_to_y_up();
+                self.num_points
+            }
+            Fix::DropSh => self.truncate_sh(0),
+        }
+    }
+}
@@ -0,0 +1,303 @@
+// Editable document model for GUI editors. A document owns a cloud together with named selection
+// masks and text annotations, keeps an undo history of edits and tracks whether it has changed
+// since it was last saved.
+//
+// Masks are stored as custom attribute channels on the cloud, so they follow the splats through
+// edits which remove or reorder them. Undo takes a snapshot of the whole document before each
+// edit, which is simple and robust but costs a copy of the cloud per step, so the history is
+// limited to a maximum depth.
+
+use std::io;
+
+use crate::attributes::AttributeData;
+use crate::export::{save_packed_gaussians_to_file_with, ExportOptions};
+use crate::{load_packed_gaussians_from_file, save_packed_gaussians_to_file, PackedGaussians};
+
+/// Prefix of the attribute channels holding masks
+pub const MASK_ATTRIBUTE_PREFIX: &str = "mask:";
+
+pub const DEFAULT_MAX_UNDO: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveFormat {
+    Spz,
+    /// .spz with the origin rebased and fractional bits picked automatically
+    SpzRebased,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Annotation {
+    pub position: [f32; 3],
+    pub text: String,
+}
+
+#[derive(Clone)]
+struct State {
+    cloud: PackedGaussians,
+    annotations: Vec<Annotation>,
+    version: u64,
+}
+
+pub struct SplatDocument {
+    state: State,
+    undo: Vec<(String, State)>,
+    redo: Vec<(String, State)>,
+    next_version: u64,
+    saved_version: Option<u64>,
+    path: Option<String>,
+    max_undo: usize,
+}
+
+impl SplatDocument {
+    /// A new unsaved document
+    pub fn new(cloud: PackedGaussians) -> SplatDocument {
+        SplatDocument {
+            state: State { cloud, annotations: Vec::new(), version: 0 },
+            undo: Vec::new(),
+            redo: Vec::new(),
+            next_version: 1,
+            saved_version: None,
+            path: None,
+            max_undo: DEFAULT_MAX_UNDO,
+        }
+    }
+
+    pub fn open(path: &str) -> Result<SplatDocument, io::Error> {
+        let mut document = SplatDocument::new(load_packed_gaussians_from_file(path)?);
+        document.path = Some(path.to_string());
+        document.saved_version = Some(0);
+        Ok(document)
+    }
+
+    pub fn cloud(&self) -> &PackedGaussians {
+        &self.state.cloud
+    }
+
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.state.annotations
+    }
+
+    /// File the document was opened from or last saved to
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// True if the document has changed since it was opened or last saved. New documents are
+    /// dirty until saved.
+    pub fn is_dirty(&self) -> bool {
+        self.saved_version != Some(self.state.version)
+    }
+
+    pub fn set_max_undo(&mut self, max_undo: usize) {
+        self.max_undo = max_undo;
+        let excess = self.undo.len().saturating_sub(max_undo);
+        self.undo.drain(..excess);
+    }
+
+    /// Descriptions of the edits which can be undone, oldest first
+    pub fn edit_log(&self) -> impl Iterator<Item = &str> {
+        self.undo.iter().map(|(description, _)| description.as_str())
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Applies an edit to the cloud as a single undoable step
+    pub fn edit<T, F: FnOnce(&mut PackedGaussians) -> T>(&mut self, description: &str, f: F) -> T {
+        self.checkpoint(description);
+        f(&mut self.state.cloud)
+    }
+
+    fn checkpoint(&mut self, description: &str) {
+        if self.max_undo > 0 {
+            if self.undo.len() >= self.max_undo {
+                self.undo.remove(0);
+            }
+            self.undo.push((description.to_string(), self.state.clone()));
+        }
+        self.redo.clear();
+        self.state.version = self.next_version;
+        self.next_version += 1;
+    }
+
+    /// Reverts the most recent edit, returning false if there's nothing to undo
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop() {
+            Some((description, state)) => {
+                let current = std::mem::replace(&mut self.state, state);
+                self.redo.push((description, current));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reapplies the most recently undone edit, returning false if there's nothing to redo
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some((description, state)) => {
+                let current = std::mem::replace(&mut self.state, state);
+                self.undo.push((description, current));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Names of the masks in the document
+    pub fn mask_names(&self) -> impl Iterator<Item = &str> {
+        self.state.cloud.attributes.names().filter_map(|name| name.strip_prefix(MASK_ATTRIBUTE_PREFIX))
+    }
+
+    pub fn mask(&self, name: &str) -> Option<Vec<bool>> {
+        match self.state.cloud.attributes.get(&format!("{}{}", MASK_ATTRIBUTE_PREFIX, name)) {
+            Some(AttributeData::U32(values)) => Some(values.iter().map(|&v| v != 0).collect()),
+            _ => None,
+        }
+    }
+
+    /// Indices of the splats selected by a mask
+    pub fn mask_indices(&self, name: &str) -> Option<Vec<usize>> {
+        let mask = self.mask(name)?;
+        Some(mask.iter().enumerate().filter(|(_, &selected)| selected).map(|(i, _)| i).collect())
+    }
+
+    /// Adds or replaces a mask, which must have one entry per splat
+    pub fn set_mask(&mut self, name: &str, selected: &[bool]) -> Result<(), io::Error> {
+        if selected.len() != self.state.cloud.num_points {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("Mask '{}' has {} entries but the cloud has {} splats", name, selected.len(), self.state.cloud.num_points)));
+        }
+        let data = AttributeData::U32(selected.iter().map(|&s| s as u32).collect());
+        self.checkpoint(&format!("Set mask {}", name));
+        self.state.cloud.set_attribute(&format!("{}{}", MASK_ATTRIBUTE_PREFIX, name), data)
+    }
+
+    pub fn remove_mask(&mut self, name: &str) -> bool {
+        let key = format!("{}{}", MASK_ATTRIBUTE_PREFIX, name);
+        if self.state.cloud.attributes.get(&key).is_none() {
+            return false;
+        }
+        self.checkpoint(&format!("Remove mask {}", name));
+        self.state.cloud.attributes.remove(&key);
+        true
+    }
+
+    pub fn add_annotation(&mut self, annotation: Annotation) {
+        self.checkpoint("Add annotation");
+        self.state.annotations.push(annotation);
+    }
+
+    pub fn remove_annotation(&mut self, index: usize) -> Option<Annotation> {
+        if index >= self.state.annotations.len() {
+            return None;
+        }
+        self.checkpoint("Remove annotation");
+        Some(self.state.annotations.remove(index))
+    }
+
+    /// Saves to the file the document was opened from or last saved to
+    pub fn save(&mut self) -> Result<(), io::Error> {
+        let path = self.path.clone().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Document has no path"))?;
+        self.save_as(&path, SaveFormat::Spz)
+    }
+
+    /// Saves the cloud, after which the document refers to the new file. Masks and annotations
+    /// are editor state and aren't written out.
+    pub fn save_as(&mut self, path: &str, format: SaveFormat) -> Result<(), io::Error> {
+        match format {
+            SaveFormat::Spz => save_packed_gaussians_to_file(&self.state.cloud, path)?,
+            SaveFormat::SpzRebased => {
+                let options = ExportOptions::default().rebase_origin(true).auto_fractional_bits(true);
+                save_packed_gaussians_to_file_with(&self.state.cloud, path, &options)?;
+            }
+        }
+        self.path = Some(path.to_string());
+        self.saved_version = Some(self.state.version);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("spz_rs_document_{}_{}.spz", std::process::id(), name)).to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn edits_undo_and_redo() {
+        let mut document = SplatDocument::new(random_cloud(10, 0, 18));
+        let original = document.cloud().alphas.clone();
+        document.edit("Clear alphas", |cloud| cloud.alphas.fill(0));
+        assert_eq!(document.edit_log().collect::<Vec<_>>(), ["Clear alphas"]);
+
+        assert!(document.undo());
+        assert_eq!(document.cloud().alphas, original);
+        assert!(!document.can_undo() && document.can_redo());
+        assert!(!document.undo());
+
+        assert!(document.redo());
+        assert_eq!(document.cloud().alphas, vec![0; 10]);
+        assert!(document.can_undo() && !document.can_redo());
+        assert!(!document.redo());
+    }
+
+    #[test]
+    fn undoing_past_a_save_makes_the_document_dirty() {
+        let path = temp_path("dirty");
+        let mut document = SplatDocument::new(random_cloud(10, 0, 19));
+        assert!(document.is_dirty());
+        document.add_annotation(Annotation { position: [0.0; 3], text: "door".to_string() });
+        document.save_as(&path, SaveFormat::Spz).unwrap();
+        assert!(!document.is_dirty());
+        assert_eq!(document.path(), Some(path.as_str()));
+
+        document.undo();
+        assert!(document.is_dirty());
+        document.redo();
+        assert!(!document.is_dirty());
+
+        let reopened = SplatDocument::open(&path).unwrap();
+        assert!(!reopened.is_dirty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn new_edits_clear_redo() {
+        let mut document = SplatDocument::new(random_cloud(10, 0, 20));
+        document.edit("First", |cloud| cloud.alphas[0] = 1);
+        document.undo();
+        assert!(document.can_redo());
+        document.set_mask("left", &[true; 10]).unwrap();
+        assert!(!document.can_redo());
+        assert!(!document.redo());
+        assert_eq!(document.mask_indices("left"), Some((0..10).collect()));
+    }
+
+    #[test]
+    fn max_undo_trims_the_oldest_edits() {
+        let mut document = SplatDocument::new(random_cloud(10, 0, 21));
+        for i in 0..5u8 {
+            document.edit(&format!("Edit {}", i), |cloud| cloud.alphas[0] = i);
+        }
+        document.set_max_undo(2);
+        assert_eq!(document.edit_log().collect::<Vec<_>>(), ["Edit 3", "Edit 4"]);
+        document.edit("Edit 5", |cloud| cloud.alphas[0] = 5);
+        assert_eq!(document.edit_log().collect::<Vec<_>>(), ["Edit 4", "Edit 5"]);
+
+        while document.undo() {}
+        assert_eq!(document.cloud().alphas[0], 3);
+
+        document.set_max_undo(0);
+        document.edit("Unrecorded", |cloud| cloud.alphas[0] = 9);
+        assert!(!document.can_undo());
+    }
+}
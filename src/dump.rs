@@ -0,0 +1,412 @@
+// Debug dumps of clouds as JSON or CBOR, for inspecting conversion pipelines and writing golden
+// files to compare against other implementations. A dump holds the header, a summary of the cloud
+// and the decoded splats, optionally only the first few. Both encodings are built from the same
+// JSON values, with CBOR written directly from them rather than through a serialization library.
+//
+// JSON can't represent infinities and NaNs, which alphas of 0 and 255 decode to, so they're
+// written as the strings "Infinity", "-Infinity" and "NaN". CBOR stores them as plain floats,
+// and strings with those names stay strings.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::json::{self, JsonValue};
+use crate::{dim_for_degree, PackedGaussians, PackedGaussiansHeader, UnpackOptions, UnpackedGaussian, MAX_SH_DEGREE};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DumpOptions {
+    /// Number of splats to include, from the start of the cloud. All of them if not set.
+    pub max_splats: Option<usize>,
+    pub unpack: UnpackOptions,
+}
+
+impl DumpOptions {
+    pub fn max_splats(mut self, max_splats: usize) -> DumpOptions {
+        self.max_splats = Some(max_splats);
+        self
+    }
+
+    pub fn unpack(mut self, unpack: UnpackOptions) -> DumpOptions {
+        self.unpack = unpack;
+        self
+    }
+}
+
+/// Overview of a cloud, covering every splat even when the dump only includes some
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CloudSummary {
+    pub num_points: usize,
+    pub sh_degree: usize,
+    pub fractional_bits: usize,
+    pub antialiased: bool,
+    pub uses_float16: bool,
+    /// Bounds of the splat centers, if there are any splats
+    pub bounds: Option<([f32; 3], [f32; 3])>,
+}
+
+impl PackedGaussians {
+    pub fn summary(&self) -> CloudSummary {
+        CloudSummary {
+            num_points: self.num_points,
+            sh_degree: self.sh_degree,
+            fractional_bits: self.fractional_bits,
+            antialiased: self.antialiased,
+            uses_float16: self.uses_float16(),
+            bounds: self.compute_bounds(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Dump {
+    pub header: PackedGaussiansHeader,
+    pub summary: CloudSummary,
+    /// SH coefficients above the cloud's degree are zero
+    pub splats: Vec<UnpackedGaussian>,
+}
+
+impl Dump {
+    pub fn new(gaussians: &PackedGaussians, options: &DumpOptions) -> Dump {
+        let count = options.max_splats.unwrap_or(usize::MAX).min(gaussians.num_points);
+        Dump {
+            header: gaussians.header(),
+            summary: gaussians.summary(),
+            splats: (0..count).map(|i| gaussians.unpack_with(i, &options.unpack)).collect(),
+        }
+    }
+
+    fn to_value(&self) -> JsonValue {
+        let number = |x: usize| JsonValue::Number(x as f64);
+        let h = &self.header;
+        let header = JsonValue::Object(vec![
+            ("magic".to_string(), number(h.magic as usize)),
+            ("version".to_string(), number(h.version as usize)),
+            ("num_points".to_string(), number(h.num_points as usize)),
+            ("sh_degree".to_string(), number(h.sh_degree as usize)),
+            ("fractional_bits".to_string(), number(h.fractional_bits as usize)),
+            ("flags".to_string(), number(h.flags as usize)),
+            ("reserved".to_string(), number(h.reserved as usize)),
+        ]);
+
+        let s = &self.summary;
+        let bounds = match s.bounds {
+            Some((min, max)) => JsonValue::Object(vec![
+                ("min".to_string(), floats(&min)),
+                ("max".to_string(), floats(&max)),
+            ]),
+            None => JsonValue::Null,
+        };
+        let summary = JsonValue::Object(vec![
+            ("num_points".to_string(), number(s.num_points)),
+            ("sh_degree".to_string(), number(s.sh_degree)),
+            ("fractional_bits".to_string(), number(s.fractional_bits)),
+            ("antialiased".to_string(), JsonValue::Bool(s.antialiased)),
+            ("uses_float16".to_string(), JsonValue::Bool(s.uses_float16)),
+            ("bounds".to_string(), bounds),
+        ]);
+
+        let sh_dim = dim_for_degree(s.sh_degree);
+        let splats = self.splats.iter().map(|g| JsonValue::Object(vec![
+            ("position".to_string(), floats(&g.position)),
+            ("rotation".to_string(), floats(&g.rotation)),
+            ("scale".to_string(), floats(&g.scale)),
+            ("color".to_string(), floats(&g.color)),
+            ("alpha".to_string(), float(g.alpha)),
+            ("sh_r".to_string(), floats(&g.sh_r[..sh_dim])),
+            ("sh_g".to_string(), floats(&g.sh_g[..sh_dim])),
+            ("sh_b".to_string(), floats(&g.sh_b[..sh_dim])),
+        ])).collect();
+
+        JsonValue::Object(vec![
+            ("header".to_string(), header),
+            ("summary".to_string(), summary),
+            ("splats".to_string(), JsonValue::Array(splats)),
+        ])
+    }
+
+    pub fn to_json(&self) -> String {
+        self.to_value().to_json_string_pretty()
+    }
+
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_cbor(&self.to_value(), &mut out);
+        out
+    }
+
+    pub fn from_json(text: &str) -> Result<Dump, io::Error> {
+        let root = json::parse(text)?;
+
+        let header_json = field(&root, "header")?;
+        let byte = |key: &str| -> Result<u8, io::Error> { Ok(number_field(header_json, key)? as u8) };
+        let header = PackedGaussiansHeader {
+            magic: number_field(header_json, "magic")? as u32,
+            version: number_field(header_json, "version")? as u32,
+            num_points: number_field(header_json, "num_points")? as u32,
+            sh_degree: byte("sh_degree")?,
+            fractional_bits: byte("fractional_bits")?,
+            flags: byte("flags")?,
+            reserved: byte("reserved")?,
+        };
+
+        let summary_json = field(&root, "summary")?;
+        let bounds = match field(summary_json, "bounds")? {
+            JsonValue::Null => None,
+            bounds => Some((float_array_field(bounds, "min")?, float_array_field(bounds, "max")?)),
+        };
+        let summary = CloudSummary {
+            num_points: number_field(summary_json, "num_points")? as usize,
+            sh_degree: number_field(summary_json, "sh_degree")? as usize,
+            fractional_bits: number_field(summary_json, "fractional_bits")? as usize,
+            antialiased: bool_field(summary_json, "antialiased")?,
+            uses_float16: bool_field(summary_json, "uses_float16")?,
+            bounds,
+        };
+        if summary.sh_degree > MAX_SH_DEGREE {
+            return Err(invalid_dump(&format!("Unsupported SH degree: {}", summary.sh_degree)));
+        }
+
+        let sh_dim = dim_for_degree(summary.sh_degree);
+        let mut splats = Vec::new();
+        for splat_json in field(&root, "splats")?.as_array().ok_or_else(|| invalid_dump("Invalid dump field 'splats'"))? {
+            let mut g = UnpackedGaussian {
+                position: float_array_field(splat_json, "position")?,
+                rotation: float_array_field(splat_json, "rotation")?,
+                scale: float_array_field(splat_json, "scale")?,
+                color: float_array_field(splat_json, "color")?,
+                alpha: parse_float(field(splat_json, "alpha")?).ok_or_else(|| invalid_dump("Invalid dump field 'alpha'"))?,
+                ..Default::default()
+            };
+            for (key, sh) in [("sh_r", &mut g.sh_r), ("sh_g", &mut g.sh_g), ("sh_b", &mut g.sh_b)] {
+                sh[..sh_dim].copy_from_slice(&float_vec_field(splat_json, key, sh_dim)?);
+            }
+            splats.push(g);
+        }
+
+        Ok(Dump { header, summary, splats })
+    }
+}
+
+fn float(x: f32) -> JsonValue {
+    if x.is_finite() {
+        JsonValue::from_f32(x)
+    } else {
+        JsonValue::NonFinite(x as f64)
+    }
+}
+
+fn floats(values: &[f32]) -> JsonValue {
+    JsonValue::Array(values.iter().map(|&v| float(v)).collect())
+}
+
+fn parse_float(value: &JsonValue) -> Option<f32> {
+    match value {
+        JsonValue::Number(n) => Some(*n as f32),
+        JsonValue::String(s) => match s.as_str() {
+            "NaN" => Some(f32::NAN),
+            "Infinity" => Some(f32::INFINITY),
+            "-Infinity" => Some(f32::NEG_INFINITY),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn invalid_dump(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn field<'a>(value: &'a JsonValue, key: &str) -> Result<&'a JsonValue, io::Error> {
+    value.get(key).ok_or_else(|| invalid_dump(&format!("Missing dump field '{}'", key)))
+}
+
+fn number_field(value: &JsonValue, key: &str) -> Result<f64, io::Error> {
+    field(value, key)?.as_f64().ok_or_else(|| invalid_dump(&format!("Dump field '{}' should be numeric", key)))
+}
+
+fn bool_field(value: &JsonValue, key: &str) -> Result<bool, io::Error> {
+    match field(value, key)? {
+        JsonValue::Bool(b) => Ok(*b),
+        _ => Err(invalid_dump(&format!("Dump field '{}' should be a boolean", key))),
+    }
+}
+
+fn float_vec_field(value: &JsonValue, key: &str, len: usize) -> Result<Vec<f32>, io::Error> {
+    let values = field(value, key)?.as_array().filter(|values| values.len() == len)
+        .ok_or_else(|| invalid_dump(&format!("Dump field '{}' should have {} elements", key, len)))?;
+    values.iter().map(|v| parse_float(v).ok_or_else(|| invalid_dump(&format!("Dump field '{}' should be numeric", key))))
+        .collect()
+}
+
+fn float_array_field<const N: usize>(value: &JsonValue, key: &str) -> Result<[f32; N], io::Error> {
+    let values = float_vec_field(value, key, N)?;
+    let mut result = [0.0; N];
+    result.copy_from_slice(&values);
+    Ok(result)
+}
+
+// Writes the major type and argument of a CBOR data item in its shortest form
+fn write_cbor_head(major: u8, argument: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    match argument {
+        0..=23 => out.push(major | argument as u8),
+        24..=0xff => out.extend([major | 24, argument as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((argument as u16).to_be_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((argument as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(argument.to_be_bytes());
+        }
+    }
+}
+
+// Whether a number is an f32 exactly, or the shortest decimal for one as written by
+// JsonValue::from_f32, so single precision loses nothing
+fn holds_single(n: f64) -> bool {
+    let x = n as f32;
+    n.is_nan() || x as f64 == n || x.to_string().parse::<f64>() == Ok(n)
+}
+
+fn write_cbor(value: &JsonValue, out: &mut Vec<u8>) {
+    match value {
+        JsonValue::Null => out.push(0xf6),
+        JsonValue::Bool(b) => out.push(if *b { 0xf5 } else { 0xf4 }),
+        // Whole numbers are written as integers and the rest as the smallest float holding them
+        // exactly, which is single precision for everything decoded from a cloud
+        JsonValue::Number(n) if n.fract() == 0.0 && n.abs() < u64::MAX as f64 && !(*n == 0.0 && n.is_sign_negative()) => {
+            if *n >= 0.0 {
+                write_cbor_head(0, *n as u64, out);
+            } else {
+                write_cbor_head(1, (-1.0 - n) as u64, out);
+            }
+        }
+        JsonValue::Number(n) if holds_single(*n) => {
+            out.push(0xfa);
+            out.extend((*n as f32).to_be_bytes());
+        }
+        JsonValue::Number(n) => {
+            out.push(0xfb);
+            out.extend(n.to_be_bytes());
+        }
+        // Non-finite floats are strings in JSON but CBOR can hold them directly
+        JsonValue::NonFinite(n) => {
+            out.push(0xfa);
+            out.extend((*n as f32).to_be_bytes());
+        }
+        JsonValue::String(s) => {
+            write_cbor_head(3, s.len() as u64, out);
+            out.extend(s.as_bytes());
+        }
+        JsonValue::Array(values) => {
+            write_cbor_head(4, values.len() as u64, out);
+            for v in values {
+                write_cbor(v, out);
+            }
+        }
+        JsonValue::Object(fields) => {
+            write_cbor_head(5, fields.len() as u64, out);
+            for (k, v) in fields {
+                write_cbor_head(3, k.len() as u64, out);
+                out.extend(k.as_bytes());
+                write_cbor(v, out);
+            }
+        }
+    }
+}
+
+/// Writes the dump as CBOR if the filename ends in .cbor and as JSON otherwise
+pub fn save_dump_to_file(dump: &Dump, filename: &str) -> Result<(), io::Error> {
+    if Path::new(filename).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("cbor")) {
+        fs::write(filename, dump.to_cbor())
+    } else {
+        fs::write(filename, dump.to_json())
+    }
+}
+
+pub fn load_dump_from_file(filename: &str) -> Result<Dump, io::Error> {
+    let text = fs::read_to_string(filename)?;
+    Dump::from_json(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+
+    fn cbor(value: &JsonValue) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_cbor(value, &mut out);
+        out
+    }
+
+    #[test]
+    fn integers_use_the_shortest_head() {
+        let cases: [(f64, &[u8]); 11] = [
+            (0.0, &[0x00]),
+            (23.0, &[0x17]),
+            (24.0, &[0x18, 0x18]),
+            (255.0, &[0x18, 0xff]),
+            (256.0, &[0x19, 0x01, 0x00]),
+            (65536.0, &[0x1a, 0x00, 0x01, 0x00, 0x00]),
+            (4294967296.0, &[0x1b, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]),
+            (-1.0, &[0x20]),
+            (-24.0, &[0x37]),
+            (-25.0, &[0x38, 0x18]),
+            (-257.0, &[0x39, 0x01, 0x00]),
+        ];
+        for (n, expected) in cases {
+            assert_eq!(cbor(&JsonValue::Number(n)), expected, "{}", n);
+        }
+    }
+
+    #[test]
+    fn floats_are_single_precision_when_that_loses_nothing() {
+        assert_eq!(cbor(&JsonValue::Number(0.5)), [0xfa, 0x3f, 0x00, 0x00, 0x00]);
+        assert_eq!(cbor(&JsonValue::from_f32(0.1)), [0xfa, 0x3d, 0xcc, 0xcc, 0xcd]);
+        assert_eq!(cbor(&JsonValue::Number(0.1 + 0.2)), [0xfb, 0x3f, 0xd3, 0x33, 0x33, 0x33, 0x33, 0x33, 0x34]);
+        assert_eq!(cbor(&JsonValue::Number(-0.0)), [0xfa, 0x80, 0x00, 0x00, 0x00]);
+        assert_eq!(cbor(&JsonValue::NonFinite(f64::INFINITY)), [0xfa, 0x7f, 0x80, 0x00, 0x00]);
+        assert_eq!(cbor(&JsonValue::NonFinite(f64::NEG_INFINITY)), [0xfa, 0xff, 0x80, 0x00, 0x00]);
+        assert_eq!(cbor(&JsonValue::NonFinite(f64::NAN))[..3], [0xfa, 0x7f, 0xc0]);
+    }
+
+    #[test]
+    fn strings_named_like_non_finite_floats_stay_strings() {
+        assert_eq!(cbor(&JsonValue::String("NaN".to_string())), b"\x63NaN");
+        assert_eq!(cbor(&JsonValue::String("-Infinity".to_string())), b"\x69-Infinity");
+    }
+
+    #[test]
+    fn maps_and_arrays_are_written_in_order() {
+        let value = JsonValue::Object(vec![
+            ("a".to_string(), JsonValue::Number(1.0)),
+            ("b".to_string(), JsonValue::Array(vec![JsonValue::Bool(true), JsonValue::Bool(false), JsonValue::Null])),
+            ("long key with 24 bytes!!".to_string(), JsonValue::String("é".to_string())),
+        ]);
+        let mut expected = vec![0xa3, 0x61, b'a', 0x01, 0x61, b'b', 0x83, 0xf5, 0xf4, 0xf6, 0x78, 0x18];
+        expected.extend(b"long key with 24 bytes!!");
+        expected.extend([0x62, 0xc3, 0xa9]);
+        assert_eq!(cbor(&value), expected);
+    }
+
+    #[test]
+    fn json_dumps_round_trip_with_non_finite_alphas() {
+        let mut cloud = random_cloud(5, 1, 17);
+        cloud.alphas[..2].copy_from_slice(&[0, 255]);
+        let dump = Dump::new(&cloud, &DumpOptions::default());
+        assert!(dump.splats[0].alpha.is_infinite() && dump.splats[1].alpha.is_infinite());
+        let json = dump.to_json();
+        assert!(json.contains("\"-Infinity\"") && json.contains("\"Infinity\""));
+        let parsed = Dump::from_json(&json).unwrap();
+        for (a, b) in parsed.splats.iter().zip(&dump.splats) {
+            assert_eq!((a.position, a.alpha, a.sh_r), (b.position, b.alpha, b.sh_r));
+        }
+    }
+}
@@ -0,0 +1,154 @@
+// Edits to splat opacity and appearance for embedding captures in larger scenes
+
+use crate::codec::quantize_scale;
+use crate::PackedGaussians;
+
+fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl PackedGaussians {
+    /// Smoothly fades out splats within `width` of the boundary of the cloud's bounds, avoiding
+    /// the hard cut-off wall look of cropped captures. Returns the number of splats changed.
+    pub fn feather_boundary(&mut self, width: f32) -> usize {
+        match self.compute_bounds() {
+            Some((min, max)) => self.feather_boundary_within(width, min, max),
+            None => 0,
+        }
+    }
+
+    /// Smoothly fades out splats approaching the boundary of the given box, over a band of the
+    /// given width inside it. Splats outside the box are made fully transparent. Returns the
+    /// number of splats changed.
+    pub fn feather_boundary_within(&mut self, width: f32, min: [f32; 3], max: [f32; 3]) -> usize {
+        let mut affected = 0;
+        for i in 0..self.num_points {
+            let p = self.unpack_position(i);
+            let inside_distance = (0..3)
+                .map(|j| (p[j] - min[j]).min(max[j] - p[j]))
+                .fold(f32::INFINITY, f32::min);
+            let factor = if width > 0.0 {
+                smoothstep(inside_distance / width)
+            } else if inside_distance >= 0.0 {
+                1.0
+            } else {
+                0.0
+            };
+
+            let alpha = (self.alphas[i] as f32 * factor).round() as u8;
+            if alpha != self.alphas[i] {
+                self.alphas[i] = alpha;
+                affected += 1;
+            }
+        }
+        affected
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegionShape {
+    Box { min: [f32; 3], max: [f32; 3] },
+    Sphere { center: [f32; 3], radius: f32 },
+}
+
+impl RegionShape {
+    pub fn contains(&self, p: [f32; 3]) -> bool {
+        match self {
+            RegionShape::Box { min, max } => (0..3).all(|j| p[j] >= min[j] && p[j] <= max[j]),
+            RegionShape::Sphere { center, radius } => {
+                let d = [0, 1, 2].map(|j| p[j] - center[j]);
+                d[0] * d[0] + d[1] * d[1] + d[2] * d[2] <= radius * radius
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScrubMode {
+    /// Deletes the splats in the region
+    Remove,
+    /// Replaces the colors in the region by their average, removes view dependent color and
+    /// multiplies opacity by `alpha_factor`, leaving the geometry but hiding any detail
+    Blur { alpha_factor: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScrubRegion {
+    pub shape: RegionShape,
+    pub mode: ScrubMode,
+}
+
+impl PackedGaussians {
+    /// Hides detail inside the given regions, such as faces or number plates, before a capture is
+    /// published. Splats in several regions are removed if any of them removes, otherwise each
+    /// blurring region is applied in turn. Returns the number of splats removed or changed.
+    pub fn scrub_regions(&mut self, regions: &[ScrubRegion]) -> usize {
+        let positions: Vec<[f32; 3]> = (0..self.num_points).map(|i| self.unpack_position(i)).collect();
+        let mut affected = vec![false; self.num_points];
+
+        let sh_stride = self.sh.len().checked_div(self.num_points).unwrap_or(0);
+        for region in regions {
+            let ScrubMode::Blur { alpha_factor } = region.mode else { continue };
+            let inside: Vec<usize> = (0..self.num_points).filter(|&i| region.shape.contains(positions[i])).collect();
+            if inside.is_empty() {
+                continue;
+            }
+
+            let mut sum = [0u64; 3];
+            for &i in &inside {
+                for (s, &c) in sum.iter_mut().zip(&self.colors[3 * i..3 * i + 3]) {
+                    *s += c as u64;
+                }
+            }
+            let average = sum.map(|s| ((s as f64 / inside.len() as f64).round()) as u8);
+            for &i in &inside {
+                self.colors[3 * i..3 * i + 3].copy_from_slice(&average);
+                self.sh[i * sh_stride..(i + 1) * sh_stride].fill(128);
+                self.alphas[i] = (self.alphas[i] as f32 * alpha_factor.clamp(0.0, 1.0)).round() as u8;
+                affected[i] = true;
+            }
+        }
+
+        let removed: Vec<bool> = positions.iter()
+            .map(|&p| regions.iter().any(|r| r.mode == ScrubMode::Remove && r.shape.contains(p)))
+            .collect();
+        if removed.iter().any(|&r| r) {
+            let keep: Vec<usize> = (0..self.num_points).filter(|&i| !removed[i]).collect();
+            *self = self.select(&keep);
+        }
+
+        affected.iter().zip(&removed).filter(|(&a, &r)| a || r).count()
+    }
+}
+
+impl PackedGaussians {
+    /// Convolves every splat with an isotropic gaussian of standard deviation `sigma`, giving a
+    /// softer version of the scene suited to far field levels of detail. Adding sigma squared to
+    /// a covariance keeps its axes, so only the scales grow, and opacity is reduced by the ratio
+    /// of the axis lengths so each splat keeps the same total density. Returns the number of
+    /// splats changed.
+    pub fn blur(&mut self, sigma: f32) -> usize {
+        let variance = sigma * sigma;
+        let mut affected = 0;
+        for i in 0..self.num_points {
+            let log_scale = self.unpack_scale(i);
+            let mut attenuation = 1.0;
+            let mut scales = [0u8; 3];
+            for j in 0..3 {
+                let s2 = (2.0 * log_scale[j]).exp();
+                let blurred = s2 + variance;
+                attenuation *= (s2 / blurred).sqrt();
+                scales[j] = quantize_scale(0.5 * blurred.ln());
+            }
+            let alpha = (self.alphas[i] as f32 * attenuation).round() as u8;
+
+            if scales != self.scales[3 * i..3 * i + 3] || alpha != self.alphas[i] {
+                self.scales[3 * i..3 * i + 3].copy_from_slice(&scales);
+                self.alphas[i] = alpha;
+                affected += 1;
+            }
+        }
+        affected
+    }
+}
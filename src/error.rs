@@ -0,0 +1,141 @@
+// Errors from reading and writing .spz files. Functions which aren't specific to .spz still
+// return io::Error, which any SpzError converts into, so `?` works across both.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+
+#[derive(Debug)]
+pub enum SpzError {
+    /// The stream doesn't start with the .spz magic number
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    UnsupportedShDegree(u8),
+    /// The stream ended part way through a field. Sizes are in bytes.
+    TruncatedField { field: &'static str, expected: usize, got: usize },
+    /// The header claims more splats than the loader was allowed to accept
+    TooManyPoints { num_points: usize, limit: usize },
+    /// The header describes more decompressed data, in bytes, than the loader was allowed to
+    /// accept or than fits in memory
+    TooLarge { size: u64, limit: u64 },
+    /// Fixed point positions with this many fractional bits have no integer part
+    InvalidFractionalBits(usize),
+    /// A field of an in memory cloud doesn't match its number of splats. Sizes are in bytes.
+    InvalidFieldLength { field: &'static str, expected: usize, got: usize },
+    /// A float16 position is infinite or NaN
+    NonFinitePosition { index: usize },
+    /// Strict loading found header flag bits or a reserved byte value from a newer writer.
+    /// `flags` holds only the unknown bits.
+    UnknownHeaderFields { flags: u8, reserved: u8 },
+    /// A tile was requested from a tiled container which doesn't have that many
+    TileOutOfRange { index: usize, num_tiles: usize },
+    /// Reading the underlying stream failed
+    Io(io::Error),
+    /// The gzip stream is corrupt
+    Decompress(io::Error),
+}
+
+impl SpzError {
+    /// Reclassifies data errors from reading through a gzip decoder as decompression errors
+    pub(crate) fn through_gzip(self) -> SpzError {
+        match self {
+            SpzError::Io(error) if matches!(error.kind(), io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput) => {
+                SpzError::Decompress(error)
+            }
+            error => error,
+        }
+    }
+}
+
+impl fmt::Display for SpzError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpzError::InvalidMagic => write!(f, "Header not found"),
+            SpzError::UnsupportedVersion(version) => write!(f, "Unsupported version: {}", version),
+            SpzError::UnsupportedShDegree(degree) => write!(f, "Unsupported SH degree: {}", degree),
+            SpzError::TruncatedField { field, expected, got } => {
+                write!(f, "Truncated {}: expected {} bytes but found {}", field, expected, got)
+            }
+            SpzError::TooManyPoints { num_points, limit } => {
+                write!(f, "Too many splats: {} is over the limit of {}", num_points, limit)
+            }
+            SpzError::TooLarge { size, limit } => {
+                write!(f, "Cloud too large: {} bytes is over the limit of {}", size, limit)
+            }
+            SpzError::InvalidFractionalBits(bits) => write!(f, "Invalid number of fractional bits: {}", bits),
+            SpzError::InvalidFieldLength { field, expected, got } => {
+                write!(f, "Invalid {} length: expected {} bytes but found {}", field, expected, got)
+            }
+            SpzError::NonFinitePosition { index } => write!(f, "Splat {} has a non-finite position", index),
+            SpzError::UnknownHeaderFields { flags, reserved } => {
+                write!(f, "Unknown header fields: flags {:#04x}, reserved byte {}", flags, reserved)
+            }
+            SpzError::TileOutOfRange { index, num_tiles } => {
+                write!(f, "Tile {} requested from a container of {} tiles", index, num_tiles)
+            }
+            SpzError::Io(error) => write!(f, "{}", error),
+            SpzError::Decompress(error) => write!(f, "Decompression failed: {}", error),
+        }
+    }
+}
+
+impl Error for SpzError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SpzError::Io(error) | SpzError::Decompress(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SpzError {
+    fn from(error: io::Error) -> SpzError {
+        SpzError::Io(error)
+    }
+}
+
+impl From<SpzError> for io::Error {
+    fn from(error: SpzError) -> io::Error {
+        match error {
+            SpzError::Io(error) => error,
+            SpzError::TruncatedField { .. } => io::Error::new(io::ErrorKind::UnexpectedEof, error),
+            error => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+/// Fills `buf` from the reader. `offset` is where `buf` starts within a field of `field_size`
+/// bytes, so that a truncated read reports how much of the whole field was found.
+pub(crate) fn read_field<R: io::Read>(
+    reader: &mut R,
+    buf: &mut [u8],
+    field: &'static str,
+    offset: usize,
+    field_size: usize,
+) -> Result<(), SpzError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => return Err(SpzError::TruncatedField { field, expected: field_size, got: offset + filled }),
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(SpzError::Io(error)),
+        }
+    }
+    Ok(())
+}
+
+// Most memory reserved for a field before any of its data has been read
+const INITIAL_FIELD_CAPACITY: usize = 1 << 16;
+
+/// Reads a whole field of `size` bytes. The buffer grows as data arrives rather than being
+/// allocated at `size` up front, so a header claiming a huge field can't reserve memory the
+/// stream doesn't back up.
+pub(crate) fn read_field_vec<R: io::Read>(reader: &mut R, field: &'static str, size: usize) -> Result<Vec<u8>, SpzError> {
+    let mut buf = Vec::with_capacity(size.min(INITIAL_FIELD_CAPACITY));
+    reader.take(size as u64).read_to_end(&mut buf)?;
+    if buf.len() < size {
+        return Err(SpzError::TruncatedField { field, expected: size, got: buf.len() });
+    }
+    Ok(buf)
+}
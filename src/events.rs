@@ -0,0 +1,190 @@
+// Structured JSONL log of batch operations, so asset farm jobs leave an auditable machine
+// readable record of what they read, what they did and what they wrote. Each line is a JSON
+// object with an "event" name, a "time" in seconds since the Unix epoch and event specific
+// fields.
+
+use std::fs;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::json::JsonValue;
+use crate::ply::{export_to_ply, has_ply_extension, load_gaussians_from_ply_buffer};
+use crate::{load_packed_gaussians_from_spz_buffer, save_packed_gaussians_to_spz_buffer, PackedGaussians};
+
+/// 64 bit FNV-1a hash of the given bytes, used to identify outputs in the log. It tells files
+/// apart but isn't cryptographic, so it can't show that a file wasn't deliberately altered.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+pub struct EventLog {
+    writer: Box<dyn Write>,
+}
+
+impl EventLog {
+    pub fn new<W: Write + 'static>(writer: W) -> EventLog {
+        EventLog { writer: Box::new(writer) }
+    }
+
+    /// Appends to the log file at `path`, creating it if needed
+    pub fn open(path: &str) -> Result<EventLog, io::Error> {
+        let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(EventLog::new(file))
+    }
+
+    fn emit(&mut self, event: &str, fields: Vec<(&str, JsonValue)>) -> Result<(), io::Error> {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+        let mut object = vec![
+            ("event".to_string(), JsonValue::String(event.to_string())),
+            ("time".to_string(), JsonValue::Number(time)),
+        ];
+        object.extend(fields.into_iter().map(|(k, v)| (k.to_string(), v)));
+        // One write per line, so lines from a job killed mid run are never interleaved
+        let line = JsonValue::Object(object).to_json_string() + "\n";
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.flush()
+    }
+
+    pub fn file_opened(&mut self, path: &str, bytes: usize) -> Result<(), io::Error> {
+        self.emit("file_opened", vec![
+            ("path", JsonValue::String(path.to_string())),
+            ("bytes", JsonValue::Number(bytes as f64)),
+        ])
+    }
+
+    pub fn splats_decoded(&mut self, path: &str, gaussians: &PackedGaussians) -> Result<(), io::Error> {
+        self.emit("splats_decoded", vec![
+            ("path", JsonValue::String(path.to_string())),
+            ("num_points", JsonValue::Number(gaussians.num_points as f64)),
+            ("sh_degree", JsonValue::Number(gaussians.sh_degree as f64)),
+        ])
+    }
+
+    /// Records an operation and the number of splats it changed
+    pub fn operation_applied(&mut self, name: &str, affected: usize) -> Result<(), io::Error> {
+        self.emit("operation_applied", vec![
+            ("operation", JsonValue::String(name.to_string())),
+            ("affected", JsonValue::Number(affected as f64)),
+        ])
+    }
+
+    pub fn output_written(&mut self, path: &str, contents: &[u8]) -> Result<(), io::Error> {
+        self.emit("output_written", vec![
+            ("path", JsonValue::String(path.to_string())),
+            ("bytes", JsonValue::Number(contents.len() as f64)),
+            ("hash", JsonValue::String(format!("fnv1a64:{:016x}", content_hash(contents)))),
+        ])
+    }
+
+    /// Loads a .spz file, or a .ply file if it has that extension, logging the file being
+    /// opened and the splats decoded from it
+    pub fn load_file(&mut self, path: &str) -> Result<PackedGaussians, io::Error> {
+        let contents = fs::read(path)?;
+        self.file_opened(path, contents.len())?;
+        let gaussians = if has_ply_extension(path) {
+            load_gaussians_from_ply_buffer(&contents[..])?
+        } else {
+            load_packed_gaussians_from_spz_buffer(&contents[..])?
+        };
+        self.splats_decoded(path, &gaussians)?;
+        Ok(gaussians)
+    }
+
+    /// Saves a .spz file, or a .ply file if it has that extension, logging its size and content
+    /// hash
+    pub fn save_file(&mut self, gaussians: &PackedGaussians, path: &str) -> Result<(), io::Error> {
+        let mut contents = Vec::new();
+        if has_ply_extension(path) {
+            export_to_ply(gaussians, &mut contents)?;
+        } else {
+            save_packed_gaussians_to_spz_buffer(gaussians, &mut contents)?;
+        }
+        fs::write(path, &contents)?;
+        self.output_written(path, &contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::json;
+    use crate::testing::random_cloud;
+
+    // Writer whose bytes the test can still read after handing it to the log
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn logged_events(buffer: &SharedBuffer) -> Vec<JsonValue> {
+        let text = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        assert!(text.ends_with('\n'));
+        text.lines().map(|line| json::parse(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn hash_matches_the_fnv1a_reference() {
+        assert_eq!(content_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(content_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(content_hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn each_event_is_one_json_line() {
+        let buffer = SharedBuffer::default();
+        let mut log = EventLog::new(buffer.clone());
+        log.file_opened("in \"quoted\".spz", 1234).unwrap();
+        log.operation_applied("blur", 7).unwrap();
+        log.output_written("out.spz", b"foobar").unwrap();
+
+        let events = logged_events(&buffer);
+        let names: Vec<_> = events.iter().map(|e| e.get("event").and_then(JsonValue::as_str).unwrap()).collect();
+        assert_eq!(names, ["file_opened", "operation_applied", "output_written"]);
+        for event in &events {
+            assert!(event.get("time").and_then(JsonValue::as_f64).unwrap() > 0.0);
+        }
+        assert_eq!(events[0].get("path").and_then(JsonValue::as_str), Some("in \"quoted\".spz"));
+        assert_eq!(events[0].get("bytes").and_then(JsonValue::as_f64), Some(1234.0));
+        assert_eq!(events[1].get("operation").and_then(JsonValue::as_str), Some("blur"));
+        assert_eq!(events[1].get("affected").and_then(JsonValue::as_f64), Some(7.0));
+        assert_eq!(events[2].get("bytes").and_then(JsonValue::as_f64), Some(6.0));
+        assert_eq!(events[2].get("hash").and_then(JsonValue::as_str), Some("fnv1a64:85944171f73967e8"));
+    }
+
+    #[test]
+    fn saved_files_load_back_with_matching_events() {
+        let path = std::env::temp_dir().join(format!("spz_rs_events_{}.spz", std::process::id())).to_string_lossy().into_owned();
+        let buffer = SharedBuffer::default();
+        let mut log = EventLog::new(buffer.clone());
+        let cloud = random_cloud(20, 1, 50);
+        log.save_file(&cloud, &path).unwrap();
+        let loaded = log.load_file(&path);
+        let contents = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap().num_points, 20);
+
+        let events = logged_events(&buffer);
+        assert_eq!(events.len(), 3);
+        let hash = format!("fnv1a64:{:016x}", content_hash(&contents));
+        assert_eq!(events[0].get("hash").and_then(JsonValue::as_str), Some(hash.as_str()));
+        assert_eq!(events[1].get("bytes").and_then(JsonValue::as_f64), Some(contents.len() as f64));
+        assert_eq!(events[2].get("num_points").and_then(JsonValue::as_f64), Some(20.0));
+        assert_eq!(events[2].get("sh_degree").and_then(JsonValue::as_f64), Some(1.0));
+    }
+}
@@ -0,0 +1,222 @@
+// Precision preserving export. Fixed point positions have a fixed number of significant bits, so
+// scenes far from the origin waste most of them on the offset. Rebasing recenters the cloud on
+// its centroid and picks the fractional bits from what's left, and the offset is recorded in an
+// extra field of the gzip header. Readers which don't know about the field, such as the reference
+// implementation, ignore it and see the cloud centered on the origin.
+
+use std::fs;
+use std::io;
+
+use flate2::read::GzDecoder;
+use flate2::{Compression, GzBuilder};
+
+use crate::codec::{encode_fixed24, fixed_point_step};
+use crate::coordinates::CoordinateSystem;
+use crate::error::SpzError;
+use crate::{load_packed_gaussians_from_decompressed_buffer, save_packed_gaussians_to_decompressed_buffer, PackedGaussians, PositionEncoding};
+
+// Subfield id of the origin in the gzip extra field, followed by three little endian f64s
+const ORIGIN_SUBFIELD_ID: [u8; 2] = *b"SO";
+const ORIGIN_SUBFIELD_LEN: usize = 24;
+
+// Largest magnitude of a 24 bit two's complement value
+const FIXED24_MAX: f64 = ((1 << 23) - 1) as f64;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExportOptions {
+    /// Recenters the cloud on its centroid before encoding, recording the offset in the file
+    pub rebase_origin: bool,
+    /// Picks the largest number of fractional bits that still fits the extent of the cloud
+    pub auto_fractional_bits: bool,
+}
+
+impl ExportOptions {
+    pub fn rebase_origin(mut self, rebase_origin: bool) -> ExportOptions {
+        self.rebase_origin = rebase_origin;
+        self
+    }
+
+    pub fn auto_fractional_bits(mut self, auto_fractional_bits: bool) -> ExportOptions {
+        self.auto_fractional_bits = auto_fractional_bits;
+        self
+    }
+}
+
+/// Where the cloud ended up once exported. Add `origin` to decoded positions to get back
+/// to the original coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExportInfo {
+    pub origin: [f64; 3],
+    pub fractional_bits: usize,
+}
+
+/// Most fractional bits which can represent coordinates up to `extent` in magnitude
+pub fn fractional_bits_for_extent(extent: f64) -> usize {
+    (0..=23).rev().find(|&bits| extent * (1u32 << bits) as f64 <= FIXED24_MAX).unwrap_or(0)
+}
+
+/// Largest coordinate magnitude fixed point positions can hold with the given fractional bits.
+/// Encoding anything further out wraps around.
+pub fn fixed24_extent(fractional_bits: usize) -> f64 {
+    FIXED24_MAX * fixed_point_step(fractional_bits.min(u32::MAX as usize) as u32) as f64
+}
+
+impl PackedGaussians {
+    /// Re-encodes positions as fixed point relative to `origin` with the given number of
+    /// fractional bits. This also converts float16 positions to fixed point.
+    pub fn reencode_positions(&mut self, origin: [f64; 3], fractional_bits: usize) {
+        let mut positions = Vec::with_capacity(self.num_points * 9);
+        for i in 0..self.num_points {
+            let p = self.unpack_position(i);
+            for j in 0..3 {
+                let relative = (p[j] as f64 - origin[j]) as f32;
+                positions.extend_from_slice(&encode_fixed24(relative, fractional_bits as u32));
+            }
+        }
+        self.positions = positions;
+        self.fractional_bits = fractional_bits;
+        self.position_encoding = PositionEncoding::FixedPoint24;
+    }
+
+    /// Applies the export options in place, returning the chosen origin and fractional bits
+    pub fn prepare_export(&mut self, options: &ExportOptions) -> ExportInfo {
+        let n = self.num_points;
+        let positions: Vec<[f32; 3]> = (0..n).map(|i| self.unpack_position(i)).collect();
+
+        let mut origin = [0.0f64; 3];
+        if options.rebase_origin && n > 0 {
+            for p in &positions {
+                for j in 0..3 {
+                    origin[j] += p[j] as f64;
+                }
+            }
+            origin = origin.map(|v| v / n as f64);
+        }
+
+        let fractional_bits = if options.auto_fractional_bits {
+            let extent = positions.iter()
+                .flat_map(|p| (0..3).map(move |j| (p[j] as f64 - origin[j]).abs()))
+                .fold(0.0, f64::max);
+            fractional_bits_for_extent(extent)
+        } else {
+            self.fractional_bits
+        };
+
+        if options.rebase_origin || options.auto_fractional_bits {
+            self.reencode_positions(origin, fractional_bits);
+        }
+        ExportInfo { origin, fractional_bits }
+    }
+}
+
+fn origin_extra_field(origin: [f64; 3]) -> Vec<u8> {
+    let mut extra = Vec::with_capacity(4 + ORIGIN_SUBFIELD_LEN);
+    extra.extend_from_slice(&ORIGIN_SUBFIELD_ID);
+    extra.extend_from_slice(&(ORIGIN_SUBFIELD_LEN as u16).to_le_bytes());
+    for v in origin {
+        extra.extend_from_slice(&v.to_le_bytes());
+    }
+    extra
+}
+
+fn origin_from_extra_field(mut extra: &[u8]) -> Option<[f64; 3]> {
+    while extra.len() >= 4 {
+        let len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        let data = extra.get(4..4 + len)?;
+        if extra[..2] == ORIGIN_SUBFIELD_ID && len == ORIGIN_SUBFIELD_LEN {
+            let component = |j: usize| f64::from_le_bytes(data[j * 8..j * 8 + 8].try_into().unwrap());
+            return Some([component(0), component(1), component(2)]);
+        }
+        extra = &extra[4 + len..];
+    }
+    None
+}
+
+/// Writes a copy of the cloud with the export options applied
+pub fn save_packed_gaussians_to_spz_buffer_with<W: io::Write>(gaussians: &PackedGaussians, writer: W, options: &ExportOptions) -> Result<ExportInfo, SpzError> {
+    let mut prepared = gaussians.clone();
+    // Files are saved in RUB, so the origin has to be found there too
+    if !matches!(prepared.coordinate_system, CoordinateSystem::Unspecified | CoordinateSystem::RUB) {
+        prepared.convert_coordinates(CoordinateSystem::RUB);
+    }
+    let info = prepared.prepare_export(options);
+
+    let mut builder = GzBuilder::new();
+    if options.rebase_origin {
+        builder = builder.extra(origin_extra_field(info.origin));
+    }
+    let mut gz_encoder = builder.write(writer, Compression::default());
+    save_packed_gaussians_to_decompressed_buffer(&prepared, &mut gz_encoder)?;
+    gz_encoder.finish()?;
+    Ok(info)
+}
+
+pub fn save_packed_gaussians_to_file_with(gaussians: &PackedGaussians, filename: &str, options: &ExportOptions) -> Result<ExportInfo, SpzError> {
+    let file = fs::File::create(filename)?;
+    let mut writer = io::BufWriter::new(file);
+    let info = save_packed_gaussians_to_spz_buffer_with(gaussians, &mut writer, options)?;
+    io::Write::flush(&mut writer)?;
+    Ok(info)
+}
+
+/// Loads a cloud along with the origin recorded by a rebasing export, which is zero for files
+/// written without one
+pub fn load_packed_gaussians_and_origin_from_spz_buffer<R: io::Read>(reader: R) -> Result<(PackedGaussians, [f64; 3]), SpzError> {
+    let mut gz_decoder = GzDecoder::new(reader);
+    let gaussians = load_packed_gaussians_from_decompressed_buffer(&mut gz_decoder).map_err(SpzError::through_gzip)?;
+    let origin = gz_decoder.header()
+        .and_then(|header| header.extra())
+        .and_then(origin_from_extra_field)
+        .unwrap_or([0.0; 3]);
+    Ok((gaussians, origin))
+}
+
+pub fn load_packed_gaussians_and_origin_from_file(filename: &str) -> Result<(PackedGaussians, [f64; 3]), SpzError> {
+    let file = fs::File::open(filename)?;
+    load_packed_gaussians_and_origin_from_spz_buffer(io::BufReader::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+    use crate::UnpackOptions;
+
+    fn rebased_positions(cloud: &PackedGaussians) -> Vec<[f64; 3]> {
+        let options = ExportOptions::default().rebase_origin(true).auto_fractional_bits(true);
+        let mut bytes = Vec::new();
+        save_packed_gaussians_to_spz_buffer_with(cloud, &mut bytes, &options).unwrap();
+        let (loaded, origin) = load_packed_gaussians_and_origin_from_spz_buffer(&bytes[..]).unwrap();
+        assert_eq!(loaded.num_points, cloud.num_points);
+        (0..loaded.num_points).map(|i| {
+            let p = loaded.unpack_position(i);
+            [0, 1, 2].map(|j| p[j] as f64 + origin[j])
+        }).collect()
+    }
+
+    fn assert_close(actual: &[[f64; 3]], expected: &[[f32; 3]], tolerance: f64) {
+        for (a, e) in actual.iter().zip(expected) {
+            for j in 0..3 {
+                assert!((a[j] - e[j] as f64).abs() <= tolerance, "{:?} vs {:?}", a, e);
+            }
+        }
+    }
+
+    #[test]
+    fn float16_clouds_rebase_to_fixed_point() {
+        let cloud = load_packed_gaussians_from_decompressed_buffer(&crate::selftest::KNOWN_V1_FILE[..]).unwrap();
+        assert_eq!(cloud.position_encoding, PositionEncoding::Float16);
+        let expected: Vec<[f32; 3]> = (0..cloud.num_points).map(|i| cloud.unpack_position(i)).collect();
+        // Both splats are 32752 from the centroid, leaving 8 fractional bits
+        assert_close(&rebased_positions(&cloud), &expected, 1.0 / 256.0);
+    }
+
+    #[test]
+    fn origins_are_recorded_in_rub() {
+        let mut cloud = random_cloud(200, 0, 3);
+        cloud.coordinate_system = CoordinateSystem::RDF;
+        let to_rub = UnpackOptions::default().to(CoordinateSystem::RUB);
+        let expected: Vec<[f32; 3]> = (0..cloud.num_points).map(|i| cloud.unpack_with(i, &to_rub).position).collect();
+        assert_close(&rebased_positions(&cloud), &expected, 1.0 / 1024.0);
+    }
+}
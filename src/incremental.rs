@@ -0,0 +1,197 @@
+// Push based decoding of .spz files, for callers which receive a file in chunks, such as async
+// runtimes reading from object storage or an HTTP response. Nothing here blocks or reads on its
+// own: each chunk is decompressed and copied into the cloud as it's pushed, so the caller can
+// await the next chunk between pushes and the compressed file is never held whole in memory.
+//
+//     let mut decoder = SpzDecoder::new();
+//     while let Some(chunk) = response.chunk().await? {
+//         decoder.push(&chunk)?;
+//     }
+//     let cloud = decoder.finish()?;
+
+use std::io::{self, Write};
+
+use flate2::write::GzDecoder;
+
+use crate::attributes::CustomAttributes;
+use crate::coordinates::CoordinateSystem;
+use crate::error::SpzError;
+use crate::{convert_smallest_three_rotations, read_header, LoadOptions, PackedGaussians, PackedGaussiansHeader, FIELD_NAMES, FLAG_ANTIALIASED};
+
+/// Receives the decompressed stream and fills in the cloud
+struct CloudWriter {
+    options: LoadOptions,
+    header: Vec<u8>,
+    cloud: Option<PackedGaussians>,
+    // Size the header gives each field in FIELD_NAMES. The fields start empty and grow as
+    // their bytes arrive, so memory use follows the data rather than the header's claims.
+    sizes: [usize; 6],
+    // Index into FIELD_NAMES of the field being filled
+    field: usize,
+    // Errors can only leave the Write impl as io::Error, so the typed error is kept here
+    error: Option<SpzError>,
+}
+
+impl CloudWriter {
+    fn field_mut(&mut self, field: usize) -> Option<&mut Vec<u8>> {
+        let cloud = self.cloud.as_mut()?;
+        Some(match field {
+            0 => &mut cloud.positions,
+            1 => &mut cloud.alphas,
+            2 => &mut cloud.colors,
+            3 => &mut cloud.scales,
+            4 => &mut cloud.rotations,
+            5 => &mut cloud.sh,
+            _ => return None,
+        })
+    }
+
+    fn start_cloud(&mut self) -> Result<(), SpzError> {
+        let header = read_header(&mut &self.header[..])?;
+        self.sizes = self.options.check_header(&header)?;
+        self.cloud = Some(PackedGaussians {
+            num_points: header.num_points as usize,
+            sh_degree: header.sh_degree as usize,
+            fractional_bits: header.fractional_bits as usize,
+            antialiased: (header.flags & FLAG_ANTIALIASED) != 0,
+            positions: Vec::new(),
+            scales: Vec::new(),
+            rotations: Vec::new(),
+            alphas: Vec::new(),
+            colors: Vec::new(),
+            sh: Vec::new(),
+            attributes: CustomAttributes::default(),
+            position_encoding: header.position_encoding(),
+            coordinate_system: CoordinateSystem::RUB,
+        });
+        Ok(())
+    }
+
+    fn consume(&mut self, mut data: &[u8]) -> Result<(), SpzError> {
+        if self.cloud.is_none() {
+            let wanted = PackedGaussiansHeader::SIZE - self.header.len();
+            let taken = wanted.min(data.len());
+            self.header.extend_from_slice(&data[..taken]);
+            data = &data[taken..];
+            if self.header.len() < PackedGaussiansHeader::SIZE {
+                return Ok(());
+            }
+            self.start_cloud()?;
+        }
+
+        // Bytes after the last field are ignored, as they are by the other loaders
+        loop {
+            self.skip_filled_fields();
+            let size = self.sizes.get(self.field).copied().unwrap_or(0);
+            let Some(buffer) = self.field_mut(self.field).filter(|_| !data.is_empty()) else {
+                return Ok(());
+            };
+            let taken = (size - buffer.len()).min(data.len());
+            buffer.extend_from_slice(&data[..taken]);
+            data = &data[taken..];
+        }
+    }
+
+    fn skip_filled_fields(&mut self) {
+        while let Some(len) = self.field_mut(self.field).map(|buffer| buffer.len()) {
+            if len != self.sizes[self.field] {
+                break;
+            }
+            self.field += 1;
+        }
+    }
+}
+
+impl Write for CloudWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self.consume(data) {
+            Ok(()) => Ok(data.len()),
+            Err(error) => {
+                let message = error.to_string();
+                self.error = Some(error);
+                Err(io::Error::other(message))
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Decodes a .spz file pushed to it in chunks of any size
+pub struct SpzDecoder {
+    decoder: GzDecoder<CloudWriter>,
+}
+
+impl Default for SpzDecoder {
+    fn default() -> SpzDecoder {
+        SpzDecoder::new_with(&LoadOptions::default())
+    }
+}
+
+impl SpzDecoder {
+    pub fn new() -> SpzDecoder {
+        SpzDecoder::default()
+    }
+
+    pub fn new_with(options: &LoadOptions) -> SpzDecoder {
+        let writer = CloudWriter { options: *options, header: Vec::new(), cloud: None, sizes: [0; 6], field: 0, error: None };
+        SpzDecoder { decoder: GzDecoder::new(writer) }
+    }
+
+    /// Number of splats in the file, once enough of it has been pushed to decode the header
+    pub fn num_points(&self) -> Option<usize> {
+        self.decoder.get_ref().cloud.as_ref().map(|cloud| cloud.num_points)
+    }
+
+    fn take_error(&mut self, error: io::Error) -> SpzError {
+        self.decoder.get_mut().error.take().unwrap_or(SpzError::Decompress(error))
+    }
+
+    /// Decodes the next chunk of the compressed file
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), SpzError> {
+        // Flushing hands the decompressed bytes on now rather than when flate2's buffer fills
+        self.decoder.write_all(chunk)
+            .and_then(|()| self.decoder.flush())
+            .map_err(|error| self.take_error(error))
+    }
+
+    /// Finishes decoding once the whole file has been pushed
+    pub fn finish(mut self) -> Result<PackedGaussians, SpzError> {
+        self.decoder.try_finish().map_err(|error| self.take_error(error))?;
+        let mut writer = self.decoder.finish().map_err(SpzError::Decompress)?;
+
+        if writer.cloud.is_none() {
+            return Err(SpzError::TruncatedField { field: "header", expected: PackedGaussiansHeader::SIZE, got: writer.header.len() });
+        }
+        writer.skip_filled_fields();
+        if let Some(buffer) = writer.field_mut(writer.field) {
+            let got = buffer.len();
+            return Err(SpzError::TruncatedField { field: FIELD_NAMES[writer.field], expected: writer.sizes[writer.field], got });
+        }
+        let Some(mut cloud) = writer.cloud else {
+            unreachable!("the cloud is created along with the header");
+        };
+        if read_header(&mut &writer.header[..])?.version == 3 {
+            cloud.rotations = convert_smallest_three_rotations(&cloud.rotations);
+        }
+        if writer.options.strict {
+            cloud.validate()?;
+        }
+        Ok(cloud)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selftest::{gzip, HOSTILE_HEADER};
+
+    #[test]
+    fn hostile_headers_are_truncated() {
+        let mut decoder = SpzDecoder::new();
+        let pushed = decoder.push(&gzip(&HOSTILE_HEADER)).and_then(|()| decoder.finish());
+        assert!(matches!(pushed, Err(SpzError::TruncatedField { field: "positions", .. })));
+    }
+}
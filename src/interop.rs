@@ -0,0 +1,82 @@
+// Attributes and transforms in the memory layouts of the common Rust math libraries, so renderer
+// integrations can convert with those libraries' own array constructors rather than shuffling
+// components by hand. The crate itself stores quaternions as w, x, y, z and matrices row major,
+// while glam and nalgebra both use x, y, z, w quaternions and column major matrices:
+//
+//     let position = glam::Vec3::from_array(cloud.unpack_position(i));
+//     let rotation = glam::Quat::from_array(cloud.rotation_xyzw(i));
+//     cloud.transform_column_major(&glam_matrix.to_cols_array_2d());
+//
+//     let rotation = nalgebra::Quaternion::from(nalgebra::Vector4::from(gaussian.rotation_xyzw()));
+//     cloud.transform_column_major(&isometry.to_homogeneous().into());
+//
+// Positions, scales and colors are plain [f32; 3] everywhere, which both libraries convert from
+// directly.
+
+use crate::math;
+use crate::transform::Mat4;
+use crate::{PackedGaussians, UnpackedGaussian};
+
+/// Quaternion from x, y, z, w order to the crate's w, x, y, z
+pub fn quat_from_xyzw(q: [f32; 4]) -> [f32; 4] {
+    [q[3], q[0], q[1], q[2]]
+}
+
+/// Quaternion from the crate's w, x, y, z order to x, y, z, w
+pub fn quat_to_xyzw(q: [f32; 4]) -> [f32; 4] {
+    [q[1], q[2], q[3], q[0]]
+}
+
+/// Row major matrix from its columns, as given by glam's `to_cols_array_2d` or a nalgebra
+/// `Matrix4` converted into arrays
+pub fn mat4_from_columns(columns: &[[f32; 4]; 4]) -> Mat4 {
+    std::array::from_fn(|i| std::array::from_fn(|j| columns[j][i]))
+}
+
+/// Columns of a row major matrix
+pub fn mat4_to_columns(matrix: &Mat4) -> [[f32; 4]; 4] {
+    std::array::from_fn(|j| std::array::from_fn(|i| matrix[i][j]))
+}
+
+impl PackedGaussians {
+    /// Rotation of a splat as x, y, z, w
+    pub fn rotation_xyzw(&self, i: usize) -> [f32; 4] {
+        quat_to_xyzw(self.unpack_rotation(i))
+    }
+
+    /// Applies an affine transform given by its columns, as [`transform`](PackedGaussians::transform)
+    pub fn transform_column_major(&mut self, columns: &[[f32; 4]; 4]) {
+        self.transform(&mat4_from_columns(columns));
+    }
+
+    /// Rotates the cloud by a unit quaternion in x, y, z, w order and then translates it, such as
+    /// a nalgebra `Isometry3` or a glam rotation and translation. Unlike a general transform this
+    /// leaves splat scales untouched.
+    pub fn transform_isometry(&mut self, rotation_xyzw: [f32; 4], translation: [f32; 3]) {
+        let rotation = math::quat_to_mat3(quat_from_xyzw(rotation_xyzw));
+        self.rotate(&rotation);
+        self.translate(translation);
+    }
+}
+
+impl UnpackedGaussian {
+    /// Rotation as x, y, z, w
+    pub fn rotation_xyzw(&self) -> [f32; 4] {
+        quat_to_xyzw(self.rotation)
+    }
+
+    /// Columns of the matrix taking the unit sphere to the splat's ellipsoid, rotating and
+    /// scaling it along its axes and then moving it to its position, for instanced rendering
+    pub fn model_matrix_columns(&self) -> [[f32; 4]; 4] {
+        let rotation = math::quat_to_mat3(self.rotation);
+        let scale = self.scale.map(f32::exp);
+        let mut columns = [[0.0; 4]; 4];
+        for (j, column) in columns.iter_mut().take(3).enumerate() {
+            for i in 0..3 {
+                column[i] = rotation[i][j] * scale[j];
+            }
+        }
+        columns[3] = [self.position[0], self.position[1], self.position[2], 1.0];
+        columns
+    }
+}
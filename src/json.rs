@@ -0,0 +1,402 @@
+// Minimal JSON reader/writer used for the manifest and debug outputs. We only need a small subset
+// of what a full JSON library offers and this keeps the crate free of extra dependencies.
+
+use std::fmt::Write;
+use std::io;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    /// NaN or an infinity. JSON has no literal for these, so they're written as the strings
+    /// "NaN", "Infinity" and "-Infinity", and parse back as strings.
+    NonFinite(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+impl JsonValue {
+    // Goes via the f32 string representation so that values like 0.1 are written as 0.1 rather
+    // than the nearest f64 to the f32 value.
+    pub fn from_f32(x: f32) -> JsonValue {
+        if x.is_finite() {
+            JsonValue::Number(x.to_string().parse().unwrap_or(x as f64))
+        } else {
+            JsonValue::Null
+        }
+    }
+
+    pub fn from_f32_slice(values: &[f32]) -> JsonValue {
+        JsonValue::Array(values.iter().map(|&v| JsonValue::from_f32(v)).collect())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(n) | JsonValue::NonFinite(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn to_json_string(&self) -> String {
+        let mut out = String::new();
+        self.write_to(&mut out, None, 0);
+        out
+    }
+
+    pub fn to_json_string_pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_to(&mut out, Some(2), 0);
+        out
+    }
+
+    fn write_to(&self, out: &mut String, indent: Option<usize>, depth: usize) {
+        let newline = |out: &mut String, depth: usize| {
+            if let Some(width) = indent {
+                out.push('\n');
+                out.extend(std::iter::repeat_n(' ', width * depth));
+            }
+        };
+
+        match self {
+            JsonValue::Null => out.push_str("null"),
+            JsonValue::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            JsonValue::Number(n) => {
+                if n.is_finite() {
+                    let _ = write!(out, "{}", n);
+                } else {
+                    out.push_str("null");
+                }
+            }
+            JsonValue::NonFinite(n) => write_string(out, non_finite_name(*n)),
+            JsonValue::String(s) => write_string(out, s),
+            JsonValue::Array(values) => {
+                // Arrays of plain values such as vectors and matrices are kept on one line
+                let inline = values.iter().all(|v| !matches!(v, JsonValue::Array(_) | JsonValue::Object(_)));
+                out.push('[');
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    if inline {
+                        if i > 0 && indent.is_some() {
+                            out.push(' ');
+                        }
+                    } else {
+                        newline(out, depth + 1);
+                    }
+                    v.write_to(out, indent, depth + 1);
+                }
+                if !values.is_empty() && !inline {
+                    newline(out, depth);
+                }
+                out.push(']');
+            }
+            JsonValue::Object(fields) => {
+                out.push('{');
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, depth + 1);
+                    write_string(out, k);
+                    out.push(':');
+                    if indent.is_some() {
+                        out.push(' ');
+                    }
+                    v.write_to(out, indent, depth + 1);
+                }
+                if !fields.is_empty() {
+                    newline(out, depth);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+pub(crate) fn non_finite_name(x: f64) -> &'static str {
+    if x.is_nan() {
+        "NaN"
+    } else if x > 0.0 {
+        "Infinity"
+    } else {
+        "-Infinity"
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+pub(crate) fn parse(text: &str) -> Result<JsonValue, io::Error> {
+    let mut parser = Parser { bytes: text.as_bytes(), pos: 0 };
+    let value = parser.parse_value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != parser.bytes.len() {
+        return Err(invalid_data("Trailing characters after JSON value"));
+    }
+    Ok(value)
+}
+
+// Deeply nested input is rejected rather than risking a stack overflow
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.bytes.len() && matches!(self.bytes[self.pos], b' ' | b'\t' | b'\n' | b'\r') {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), io::Error> {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err(invalid_data("Unexpected token in JSON"))
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<JsonValue, io::Error> {
+        if depth > MAX_DEPTH {
+            return Err(invalid_data("JSON nested too deeply"));
+        }
+
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'n') => self.expect("null").map(|_| JsonValue::Null),
+            Some(b't') => self.expect("true").map(|_| JsonValue::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| JsonValue::Bool(false)),
+            Some(b'"') => self.parse_string().map(JsonValue::String),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(values));
+                }
+                loop {
+                    values.push(self.parse_value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(JsonValue::Array(values));
+                        }
+                        _ => return Err(invalid_data("Expected ',' or ']' in JSON array")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some(b'"') {
+                        return Err(invalid_data("Expected key in JSON object"));
+                    }
+                    let key = self.parse_string()?;
+                    self.skip_whitespace();
+                    if self.peek() != Some(b':') {
+                        return Err(invalid_data("Expected ':' in JSON object"));
+                    }
+                    self.pos += 1;
+                    fields.push((key, self.parse_value(depth + 1)?));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(JsonValue::Object(fields));
+                        }
+                        _ => return Err(invalid_data("Expected ',' or '}' in JSON object")),
+                    }
+                }
+            }
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            _ => Err(invalid_data("Unexpected character in JSON")),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, io::Error> {
+        let start = self.pos;
+        while self.pos < self.bytes.len()
+            && matches!(self.bytes[self.pos], b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()
+            .and_then(|s| s.parse::<f64>().ok())
+            .map(JsonValue::Number)
+            .ok_or_else(|| invalid_data("Invalid number in JSON"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, io::Error> {
+        let digits = self.bytes.get(self.pos..self.pos + 4).ok_or_else(|| invalid_data("Truncated JSON escape"))?;
+        let value = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|s| u32::from_str_radix(s, 16).ok())
+            .ok_or_else(|| invalid_data("Invalid JSON escape"))?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn parse_string(&mut self) -> Result<String, io::Error> {
+        self.pos += 1; // Opening quote
+        let mut result = String::new();
+        loop {
+            let start = self.pos;
+            while self.pos < self.bytes.len() && !matches!(self.bytes[self.pos], b'"' | b'\\') {
+                self.pos += 1;
+            }
+            let chunk = std::str::from_utf8(&self.bytes[start..self.pos]).map_err(|_| invalid_data("Invalid UTF-8 in JSON"))?;
+            result.push_str(chunk);
+
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(result);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escape = self.peek().ok_or_else(|| invalid_data("Truncated JSON string"))?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => result.push('"'),
+                        b'\\' => result.push('\\'),
+                        b'/' => result.push('/'),
+                        b'b' => result.push('\u{8}'),
+                        b'f' => result.push('\u{c}'),
+                        b'n' => result.push('\n'),
+                        b'r' => result.push('\r'),
+                        b't' => result.push('\t'),
+                        b'u' => {
+                            let mut code = self.parse_hex4()?;
+                            if (0xd800..0xdc00).contains(&code) && self.bytes[self.pos..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                let low = self.parse_hex4()?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            result.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        _ => return Err(invalid_data("Invalid JSON escape")),
+                    }
+                }
+                _ => return Err(invalid_data("Unterminated JSON string")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> JsonValue {
+        JsonValue::String(s.to_string())
+    }
+
+    #[test]
+    fn numbers_are_written_in_their_shortest_form() {
+        let cases = [
+            (JsonValue::Number(1.0), "1"),
+            (JsonValue::Number(-0.0), "-0"),
+            (JsonValue::Number(0.5), "0.5"),
+            (JsonValue::from_f32(0.1), "0.1"),
+            (JsonValue::Number(f64::NAN), "null"),
+            (JsonValue::from_f32(f32::INFINITY), "null"),
+            (JsonValue::NonFinite(f64::NAN), "\"NaN\""),
+            (JsonValue::NonFinite(f64::NEG_INFINITY), "\"-Infinity\""),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(value.to_json_string(), expected);
+        }
+    }
+
+    #[test]
+    fn strings_are_escaped_and_parse_back() {
+        let text = "q\"\\\n\r\t\u{1}\u{1f}é😀";
+        let written = string(text).to_json_string();
+        assert_eq!(written, "\"q\\\"\\\\\\n\\r\\t\\u0001\\u001fé😀\"");
+        assert_eq!(parse(&written).unwrap(), string(text));
+        assert_eq!(parse(r#""\u00e9\ud83d\ude00\/\b\f""#).unwrap(), string("é😀/\u{8}\u{c}"));
+    }
+
+    #[test]
+    fn objects_and_arrays_are_written_compact_and_pretty() {
+        let value = JsonValue::Object(vec![
+            ("v".to_string(), JsonValue::Array(vec![JsonValue::Number(1.0), JsonValue::Bool(true), JsonValue::Null])),
+            ("o".to_string(), JsonValue::Object(vec![])),
+            ("a".to_string(), JsonValue::Array(vec![JsonValue::Array(vec![])])),
+        ]);
+        assert_eq!(value.to_json_string(), r#"{"v":[1,true,null],"o":{},"a":[[]]}"#);
+        assert_eq!(value.to_json_string_pretty(), "{\n  \"v\": [1, true, null],\n  \"o\": {},\n  \"a\": [\n    []\n  ]\n}");
+        assert_eq!(parse(&value.to_json_string_pretty()).unwrap(), value);
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        for text in ["", "[1,]", "{\"a\" 1}", "[1] 2", "\"open", "\"\\x\"", "nul", "-"] {
+            assert!(parse(text).is_err(), "{:?}", text);
+        }
+        assert!(parse(&"[".repeat(MAX_DEPTH + 2)).is_err());
+    }
+}
@@ -5,140 +5,533 @@
 
 use std::fs;
 use std::io;
-use std::mem;
 
 use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use attributes::CustomAttributes;
+use color::ColorMode;
+use coordinates::{AxisConversion, CoordinateSystem};
+use error::{read_field, read_field_vec, SpzError};
+use view::PackedGaussiansView;
+use codec::{
+    decode_fixed24, decode_quat3, decode_quat3_batch, decode_quat_smallest_three, encode_fixed24, encode_quat3, f32_to_half, half_to_f32,
+    quantize_alpha, quantize_color, quantize_scale, quantize_sh, unquantize_alpha, unquantize_alpha_batch,
+    unquantize_alpha_deterministic, unquantize_alpha_deterministic_batch, unquantize_color, unquantize_color_batch,
+    unquantize_scale, unquantize_sh,
+};
+
+pub mod attributes;
+pub mod bounds;
+pub mod builder;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod codec;
+pub mod color;
+pub mod columns;
+pub mod compare;
+pub mod concat;
+pub mod coordinates;
+pub mod covariance;
+pub mod diagnostics;
+pub mod document;
+pub mod dump;
+pub mod edit;
+pub mod error;
+pub mod events;
+pub mod export;
+pub mod incremental;
+pub mod interop;
+mod json;
+pub mod manifest;
+mod math;
+pub mod merge;
+pub mod ops;
+pub mod ply;
+pub mod preview;
+pub mod probes;
+pub mod prune;
+pub mod publish;
+pub mod query;
+pub mod repair;
+pub mod rng;
+pub mod scene;
+pub mod sdf;
+pub mod selftest;
+pub mod sh;
+pub mod sort;
+mod spatial;
+pub mod stream;
+#[cfg(test)]
+mod testing;
+pub mod tiled;
+pub mod transfer;
+pub mod transform;
+pub mod validate;
+pub mod view;
 
 const FLAG_ANTIALIASED: u8 = 0x1;
+// Marks files using SH degrees beyond the 3 supported by the reference implementation, so that
+// readers without experimental support reject them rather than misreading them
+const FLAG_EXPERIMENTAL_SH: u8 = 0x80;
+const KNOWN_FLAGS: u8 = FLAG_ANTIALIASED | FLAG_EXPERIMENTAL_SH;
+
+/// Fields of the decompressed stream after the header, in file order
+pub(crate) const FIELD_NAMES: [&str; 6] = ["positions", "alphas", "colors", "scales", "rotations", "sh"];
 
-// Scale factor for DC color components. To convert to RGB, we should multiply by 0.282, but it can
-// be useful to represent base colors that are out of range if the higher spherical harmonics bands
-// bring them back into range so we multiply by a smaller value.
-const COLOR_SCALE: f32 = 0.15;
+/// Highest SH degree which can be read and written. Degree 4 is experimental and only available
+/// with the `experimental` feature.
+#[cfg(not(feature = "experimental"))]
+pub const MAX_SH_DEGREE: usize = 3;
+#[cfg(feature = "experimental")]
+pub const MAX_SH_DEGREE: usize = 4;
 
-fn dim_for_degree(degree: usize) -> usize {
+/// Number of SH coefficients per color channel above degree 0, at [`MAX_SH_DEGREE`]
+pub const MAX_SH_DIM: usize = (MAX_SH_DEGREE + 1) * (MAX_SH_DEGREE + 1) - 1;
+
+/// Number of SH coefficients per color channel above degree 0. Unsupported degrees have none,
+/// they're rejected when files are loaded or saved.
+pub(crate) fn dim_for_degree(degree: usize) -> usize {
     match degree {
         0 => 0,
         1 => 3,
         2 => 8,
         3 => 15,
-        _ => {
-            eprintln!("[SPZ: ERROR] Unsupported SH degree: {}", degree);
-            0
+        #[cfg(feature = "experimental")]
+        4 => 24,
+        _ => 0,
+    }
+}
+
+fn unquantize_position(position: &[u8], uses_float16: bool, fractional_bits: u32) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    if uses_float16 {
+        for i in 0..3 {
+            result[i] = half_to_f32(u16::from_le_bytes([position[i * 2], position[i * 2 + 1]]));
+        }
+    } else {
+        for i in 0..3 {
+            result[i] = decode_fixed24(&position[i * 3..i * 3 + 3], fractional_bits);
         }
     }
+    result
 }
 
-fn half_to_f32(h: u16) -> f32 {
-    let sgn = (h >> 15) & 0x1;
-    let exponent = (h >> 10) & 0x1f;
-    let mantissa = h & 0x3ff;
+fn quantize_position(position: [f32; 3], uses_float16: bool, fractional_bits: u32) -> [u8; 9] {
+    let mut result = [0; 9];
+    if uses_float16 {
+        for (i, &x) in position.iter().enumerate() {
+            result[i * 2..i * 2 + 2].copy_from_slice(&f32_to_half(x).to_le_bytes());
+        }
+    } else {
+        for (i, &x) in position.iter().enumerate() {
+            result[i * 3..i * 3 + 3].copy_from_slice(&encode_fixed24(x, fractional_bits));
+        }
+    }
+    result
+}
+
+/// Options controlling how packed gaussians are decoded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UnpackOptions {
+    /// Guarantees bit identical results on every platform by avoiding the platform's
+    /// transcendental functions, at a small cost in speed. Useful when decoded data is hashed or
+    /// used for lockstep networking.
+    pub deterministic: bool,
+    /// Decodes all zero rotation bytes, which are usually left by a broken exporter, as the
+    /// identity rotation. Otherwise they decode to an arbitrary skewed orientation, as they do
+    /// in the reference implementation.
+    pub identity_for_zero_rotation: bool,
+    /// Convention the cloud was captured in. Files normally use the RUB convention of the
+    /// format, but some exporters write their own without converting.
+    pub from: CoordinateSystem,
+    /// Convention to decode into. Nothing is converted if either convention is unspecified.
+    pub to: CoordinateSystem,
+    /// Highest SH degree to decode, for renderers which don't evaluate the higher bands. The
+    /// coefficients above it aren't decoded, so they're zero in single splats and left out of
+    /// the arrays from `unpack_all`.
+    pub max_sh_degree: Option<usize>,
+    /// Form of the decoded base colors. Colors in any form other than the default SH DC
+    /// coefficients are for display, and don't pack back into the same bytes.
+    pub color_mode: ColorMode,
+}
+
+impl UnpackOptions {
+    pub fn deterministic(mut self, deterministic: bool) -> UnpackOptions {
+        self.deterministic = deterministic;
+        self
+    }
+
+    pub fn identity_for_zero_rotation(mut self, identity_for_zero_rotation: bool) -> UnpackOptions {
+        self.identity_for_zero_rotation = identity_for_zero_rotation;
+        self
+    }
+
+    pub fn from(mut self, from: CoordinateSystem) -> UnpackOptions {
+        self.from = from;
+        self
+    }
+
+    pub fn to(mut self, to: CoordinateSystem) -> UnpackOptions {
+        self.to = to;
+        self
+    }
+
+    pub fn max_sh_degree(mut self, max_sh_degree: usize) -> UnpackOptions {
+        self.max_sh_degree = Some(max_sh_degree);
+        self
+    }
+
+    pub fn color_mode(mut self, color_mode: ColorMode) -> UnpackOptions {
+        self.color_mode = color_mode;
+        self
+    }
+
+    /// SH degree of the decoded splats for a cloud of the given degree
+    fn sh_degree(&self, sh_degree: usize) -> usize {
+        self.max_sh_degree.map_or(sh_degree, |max| max.min(sh_degree))
+    }
+
+    fn conversion(&self) -> Option<AxisConversion> {
+        AxisConversion::new(self.from, self.to)
+    }
+
+    /// The options for a cloud declaring the convention it's stored in. Unless a source
+    /// convention was given, the declared one is used, converting to RUB unless another target
+    /// was given too.
+    fn for_stored(&self, stored: CoordinateSystem) -> UnpackOptions {
+        let mut options = *self;
+        if options.from == CoordinateSystem::Unspecified && stored != CoordinateSystem::Unspecified {
+            options.from = stored;
+            if options.to == CoordinateSystem::Unspecified {
+                options.to = CoordinateSystem::RUB;
+            }
+        }
+        options
+    }
 
-    let sign_mul = if sgn == 1 { -1.0 } else { 1.0 };
-    if exponent == 0 {
-        // Subnormal numbers (no exponent, 0 in the mantissa decimal).
-        return sign_mul * 2.0f32.powf(-14.0) * (mantissa as f32) / 1024.0;
+    fn decode_rotation(&self, bytes: &[u8]) -> [f32; 4] {
+        if self.identity_for_zero_rotation && bytes == [0, 0, 0] {
+            [1.0, 0.0, 0.0, 0.0]
+        } else {
+            decode_quat3(bytes)
+        }
     }
 
-    if exponent == 31 {
-        // Infinity or NaN.
-        if mantissa == 0 { 
-            return sign_mul * f32::INFINITY;
+    fn unquantize_alpha(&self, x: u8) -> f32 {
+        if self.deterministic {
+            unquantize_alpha_deterministic(x)
         } else {
-            return f32::NAN;
+            unquantize_alpha(x)
+        }
+    }
+
+    fn decode_rotation_batch(&self, bytes: &[u8], out: &mut [f32]) {
+        decode_quat3_batch(bytes, out);
+        if self.identity_for_zero_rotation {
+            for (r, packed) in out.chunks_exact_mut(4).zip(bytes.chunks_exact(3)) {
+                if packed == [0, 0, 0] {
+                    r.copy_from_slice(&[1.0, 0.0, 0.0, 0.0]);
+                }
+            }
+        }
+    }
+
+    fn decode_color(&self, color: &[u8]) -> [f32; 3] {
+        match self.color_mode {
+            ColorMode::ShDc => unquantize_colors(color),
+            mode => [0, 1, 2].map(|j| color::decode_color(mode, self.deterministic, color[j])),
+        }
+    }
+
+    fn decode_color_batch(&self, bytes: &[u8], out: &mut [f32]) {
+        match self.color_mode {
+            ColorMode::ShDc => unquantize_color_batch(bytes, out),
+            mode => {
+                let table = color::color_table(mode, self.deterministic);
+                for (x, &byte) in out.iter_mut().zip(bytes) {
+                    *x = table[byte as usize];
+                }
+            }
         }
     }
 
-    // non-zero exponent implies 1 in the mantissa decimal.
-    return sign_mul * 2.0f32.powf((exponent as f32) - 15.0)
-        * (1.0 + (mantissa as f32) / 1024.0);
+    fn unquantize_alpha_batch(&self, bytes: &[u8], out: &mut [f32]) {
+        if self.deterministic {
+            unquantize_alpha_deterministic_batch(bytes, out)
+        } else {
+            unquantize_alpha_batch(bytes, out)
+        }
+    }
 }
 
-fn unquantize_scale(x: u8) -> f32 {
-    x as f32 / 16.0 - 10.0
+/// Options for loading files from untrusted sources
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// Largest number of splats accepted, checked against the header before anything is
+    /// allocated so a corrupt header can't reserve gigabytes of memory
+    pub max_points: usize,
+    /// Largest decompressed size in bytes accepted, header included. As with `max_points` this
+    /// is checked against the header first, and decompression stops at the end of the fields
+    /// it describes, so a small file can't expand without bound.
+    pub max_decompressed_bytes: u64,
+    /// Rejects clouds which fail [`PackedGaussians::validate`]
+    pub strict: bool,
 }
 
-fn unquantize_alpha(x: u8) -> f32 {
-    inv_sigmoid(x as f32 / 255.0)
+impl Default for LoadOptions {
+    fn default() -> LoadOptions {
+        LoadOptions { max_points: usize::MAX, max_decompressed_bytes: u64::MAX, strict: false }
+    }
 }
 
-fn unquantize_sh(x: u8) -> f32 {
-    ((x as f32) - 128.0) / 128.0
+impl LoadOptions {
+    pub fn max_points(mut self, max_points: usize) -> LoadOptions {
+        self.max_points = max_points;
+        self
+    }
+
+    pub fn max_decompressed_bytes(mut self, max_decompressed_bytes: u64) -> LoadOptions {
+        self.max_decompressed_bytes = max_decompressed_bytes;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> LoadOptions {
+        self.strict = strict;
+        self
+    }
+
+    /// Checks a header against the limits before anything is allocated for it, returning the
+    /// sizes of its fields
+    pub(crate) fn check_header(&self, header: &PackedGaussiansHeader) -> Result<[usize; 6], SpzError> {
+        let num_points = header.num_points as usize;
+        if num_points > self.max_points {
+            return Err(SpzError::TooManyPoints { num_points, limit: self.max_points });
+        }
+        let size = header.decompressed_size();
+        let limit = self.max_decompressed_bytes.min(usize::MAX as u64);
+        if size > limit {
+            return Err(SpzError::TooLarge { size, limit });
+        }
+        if self.strict {
+            header.check_known_fields()?;
+        }
+        header.field_sizes().ok_or(SpzError::TooLarge { size, limit })
+    }
 }
 
-fn inv_sigmoid(x: f32) -> f32 { 
-    (x / (1.0 - x)).ln()
+fn unquantize_colors(color: &[u8]) -> [f32; 3] {
+    [unquantize_color(color[0]), unquantize_color(color[1]), unquantize_color(color[2])]
 }
 
-#[derive(Default)]
+#[derive(Clone, Debug, Default)]
 pub struct UnpackedGaussian {
     pub position: [f32; 3],
     pub rotation: [f32; 4],
     pub scale: [f32; 3],
     pub color: [f32; 3],
     pub alpha: f32,
-    pub sh_r: [f32; 15],
-    pub sh_g: [f32; 15],
-    pub sh_b: [f32; 15],
+    pub sh_r: [f32; MAX_SH_DIM],
+    pub sh_g: [f32; MAX_SH_DIM],
+    pub sh_b: [f32; MAX_SH_DIM],
+}
+
+/// Decoded splats with one contiguous array per attribute, so they can be uploaded to a GPU
+/// vertex buffer without further copying
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UnpackedGaussians {
+    pub num_points: usize,
+    pub sh_degree: usize,
+    pub antialiased: bool,
+    /// x, y, z for each splat
+    pub positions: Vec<f32>,
+    /// w, x, y, z for each splat
+    pub rotations: Vec<f32>,
+    /// Log scales along each axis for each splat
+    pub scales: Vec<f32>,
+    /// r, g, b for each splat
+    pub colors: Vec<f32>,
+    pub alphas: Vec<f32>,
+    /// SH coefficients for each splat, ordered by coefficient with the r, g and b values of
+    /// each coefficient next to each other, as in [`PackedGaussians::sh`]
+    pub sh: Vec<f32>,
+    /// 8 bit sRGB r, g, b and opacity a for each splat, only filled when unpacking with
+    /// [`ColorMode::Rgba8`] and empty otherwise
+    pub rgba8: Vec<u8>,
+}
+
+// Fewest splats worth handing to a thread of their own
+#[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+const MIN_PARALLEL_RUN: usize = 1 << 16;
+
+// Output arrays for a run of consecutive splats
+struct UnpackedRun<'a> {
+    positions: &'a mut [f32],
+    rotations: &'a mut [f32],
+    scales: &'a mut [f32],
+    colors: &'a mut [f32],
+    alphas: &'a mut [f32],
+    sh: &'a mut [f32],
+}
+
+impl UnpackedGaussians {
+    fn zeroed(num_points: usize, sh_degree: usize, antialiased: bool) -> UnpackedGaussians {
+        UnpackedGaussians {
+            num_points,
+            sh_degree,
+            antialiased,
+            positions: vec![0.0; num_points * 3],
+            rotations: vec![0.0; num_points * 4],
+            scales: vec![0.0; num_points * 3],
+            colors: vec![0.0; num_points * 3],
+            alphas: vec![0.0; num_points],
+            sh: vec![0.0; num_points * dim_for_degree(sh_degree) * 3],
+            rgba8: Vec::new(),
+        }
+    }
+
+    /// Splits the arrays into runs of up to `run_length` splats, along with the index of the
+    /// first splat in each
+    fn runs(&mut self, run_length: usize) -> Vec<(usize, UnpackedRun<'_>)> {
+        let sh_stride = dim_for_degree(self.sh_degree) * 3;
+        let mut rest = UnpackedRun {
+            positions: &mut self.positions,
+            rotations: &mut self.rotations,
+            scales: &mut self.scales,
+            colors: &mut self.colors,
+            alphas: &mut self.alphas,
+            sh: &mut self.sh,
+        };
+
+        let mut result = Vec::new();
+        let mut start = 0;
+        while start < self.num_points {
+            let count = run_length.min(self.num_points - start);
+            let (positions, positions_rest) = std::mem::take(&mut rest.positions).split_at_mut(count * 3);
+            let (rotations, rotations_rest) = std::mem::take(&mut rest.rotations).split_at_mut(count * 4);
+            let (scales, scales_rest) = std::mem::take(&mut rest.scales).split_at_mut(count * 3);
+            let (colors, colors_rest) = std::mem::take(&mut rest.colors).split_at_mut(count * 3);
+            let (alphas, alphas_rest) = std::mem::take(&mut rest.alphas).split_at_mut(count);
+            let (sh, sh_rest) = std::mem::take(&mut rest.sh).split_at_mut(count * sh_stride);
+            result.push((start, UnpackedRun { positions, rotations, scales, colors, alphas, sh }));
+            rest = UnpackedRun {
+                positions: positions_rest,
+                rotations: rotations_rest,
+                scales: scales_rest,
+                colors: colors_rest,
+                alphas: alphas_rest,
+                sh: sh_rest,
+            };
+            start += count;
+        }
+        result
+    }
+
+    pub fn at(&self, i: usize) -> UnpackedGaussian {
+        let mut result = UnpackedGaussian::default();
+        result.position.copy_from_slice(&self.positions[i * 3..i * 3 + 3]);
+        result.rotation.copy_from_slice(&self.rotations[i * 4..i * 4 + 4]);
+        result.scale.copy_from_slice(&self.scales[i * 3..i * 3 + 3]);
+        result.color.copy_from_slice(&self.colors[i * 3..i * 3 + 3]);
+        result.alpha = self.alphas[i];
+
+        let sh_dim = dim_for_degree(self.sh_degree);
+        let sh_start = i * sh_dim * 3;
+        for j in 0..sh_dim {
+            result.sh_r[j] = self.sh[sh_start + j * 3];
+            result.sh_g[j] = self.sh[sh_start + j * 3 + 1];
+            result.sh_b[j] = self.sh[sh_start + j * 3 + 2];
+        }
+        result
+    }
+
+    /// Base colors and opacities ready for display, r, g, b and a between 0 and 1 for each splat,
+    /// from colors unpacked as SH DC coefficients. Like the other arrays this is a plain `[f32]`
+    /// which can be viewed as a `Float32Array` in JavaScript and handed to WebGL without copying.
+    pub fn rgba(&self) -> Vec<f32> {
+        let mut result = Vec::with_capacity(self.num_points * 4);
+        for (color, &alpha) in self.colors.chunks_exact(3).zip(&self.alphas) {
+            result.extend(color.iter().map(|&c| color::dc_to_linear(c).clamp(0.0, 1.0)));
+            result.push(1.0 / (1.0 + (-alpha).exp()));
+        }
+        result
+    }
+
+    /// Scales along each axis in world units rather than log scales
+    pub fn linear_scales(&self) -> Vec<f32> {
+        self.scales.iter().map(|s| s.exp()).collect()
+    }
 }
 
 #[derive(Default)]
 pub struct PackedGaussian {
+    /// Three little endian 24 bit fixed point values, or for clouds with float16 positions
+    /// three little endian halves in the first 6 bytes
     pub position: [u8; 9],
     pub rotation: [u8; 3],
     pub scale: [u8; 3],
     pub color: [u8; 3],
     pub alpha: u8,
-    pub sh_r: [u8; 15],
-    pub sh_g: [u8; 15],
-    pub sh_b: [u8; 15],
+    pub sh_r: [u8; MAX_SH_DIM],
+    pub sh_g: [u8; MAX_SH_DIM],
+    pub sh_b: [u8; MAX_SH_DIM],
 }
 
 impl PackedGaussian {
-    pub fn unpack(&self, uses_float16: bool, fractional_bits: u32) -> UnpackedGaussian {
-        let mut result = UnpackedGaussian::default();
+    /// Quantizes a gaussian. Packing an unpacked gaussian gives back the same bytes, as long as
+    /// its rotation bytes encoded a valid rotation. SH coefficients keep all 8 bits, use
+    /// [`codec::quantize_sh`] beforehand to drop bits the way the reference encoder does.
+    pub fn pack(gaussian: &UnpackedGaussian, uses_float16: bool, fractional_bits: u32) -> PackedGaussian {
+        let mut result = PackedGaussian {
+            position: quantize_position(gaussian.position, uses_float16, fractional_bits),
+            rotation: encode_quat3(gaussian.rotation),
+            scale: gaussian.scale.map(quantize_scale),
+            color: gaussian.color.map(quantize_color),
+            alpha: quantize_alpha(gaussian.alpha),
+            ..Default::default()
+        };
 
-        if uses_float16 {
-            for i in 0..3 {
-                result.position[i] = half_to_f32(self.position[i * 2] as u16);
-            }
-        } else {
-            let scale = 1.0 / (1 << fractional_bits) as f32;
-            for i in 0..3 {
-                let mut fixed32: i32 = self.position[i * 3] as i32;
-                fixed32 |= (self.position[i * 3 + 1] as i32) << 8;
-                fixed32 |= (self.position[i * 3 + 2] as i32) << 16;
-                fixed32 |= if fixed32 & 0x800000 != 0 { 0xff000000u32 as i32 } else { 0 };
-                result.position[i] = fixed32 as f32 * scale;
-            }
+        for i in 0..MAX_SH_DIM {
+            result.sh_r[i] = quantize_sh(gaussian.sh_r[i], 8);
+            result.sh_g[i] = quantize_sh(gaussian.sh_g[i], 8);
+            result.sh_b[i] = quantize_sh(gaussian.sh_b[i], 8);
         }
 
-        for i in 0..3 {
-            result.scale[i] = unquantize_scale(self.scale[i]);
-        }
+        result
+    }
 
-        // Decode quaternion and store as w, x, y, z
-        let xyz = self.rotation.iter().map(|&r| r as f32 / 127.5 - 1.0).collect::<Vec<f32>>();
-        result.rotation[1..].clone_from_slice(&xyz);
-        result.rotation[0] = (1.0 - xyz.iter().map(|v| v * v).sum::<f32>()).sqrt().max(0.0);
+    pub fn unpack(&self, uses_float16: bool, fractional_bits: u32) -> UnpackedGaussian {
+        self.unpack_with(uses_float16, fractional_bits, &UnpackOptions::default())
+    }
 
-        result.alpha = unquantize_alpha(self.alpha);
+    pub fn unpack_with(&self, uses_float16: bool, fractional_bits: u32, options: &UnpackOptions) -> UnpackedGaussian {
+        let mut result = UnpackedGaussian {
+            position: unquantize_position(&self.position, uses_float16, fractional_bits),
+            rotation: options.decode_rotation(&self.rotation),
+            color: options.decode_color(&self.color),
+            alpha: options.unquantize_alpha(self.alpha),
+            ..Default::default()
+        };
 
         for i in 0..3 {
-            result.color[i] = (self.color[i] as f32 / 255.0 - 0.5) / COLOR_SCALE;
+            result.scale[i] = unquantize_scale(self.scale[i]);
         }
 
-        for i in 0..15 {
+        for i in 0..dim_for_degree(options.sh_degree(MAX_SH_DEGREE)) {
             result.sh_r[i] = unquantize_sh(self.sh_r[i]);
             result.sh_g[i] = unquantize_sh(self.sh_g[i]);
             result.sh_b[i] = unquantize_sh(self.sh_b[i]);
         }
 
+        if let Some(conversion) = options.conversion() {
+            conversion.convert(&mut result);
+        }
         result
     }
 }
 
-#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct PackedGaussiansHeader {
     pub magic: u32,
     pub version: u32,
@@ -163,6 +556,144 @@ impl Default for PackedGaussiansHeader {
     }
 }
 
+/// Revisions of the .spz format which can be read and written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatVersion {
+    /// Positions stored as float16
+    V1,
+    /// Positions stored as 24 bit fixed point
+    V2,
+    /// Rotations stored as the smallest three components of the quaternion in four bytes. They're
+    /// converted to the three byte encoding of earlier versions when loaded, which loses a little
+    /// precision, and clouds are saved as version 2.
+    V3,
+}
+
+impl FormatVersion {
+    pub fn from_u32(version: u32) -> Option<FormatVersion> {
+        match version {
+            1 => Some(FormatVersion::V1),
+            2 => Some(FormatVersion::V2),
+            3 => Some(FormatVersion::V3),
+            _ => None,
+        }
+    }
+
+    pub fn to_u32(self) -> u32 {
+        match self {
+            FormatVersion::V1 => 1,
+            FormatVersion::V2 => 2,
+            FormatVersion::V3 => 3,
+        }
+    }
+
+    pub fn uses_float16(self) -> bool {
+        self == FormatVersion::V1
+    }
+
+    pub fn position_encoding(self) -> PositionEncoding {
+        match self {
+            FormatVersion::V1 => PositionEncoding::Float16,
+            FormatVersion::V2 | FormatVersion::V3 => PositionEncoding::FixedPoint24,
+        }
+    }
+}
+
+/// How splat positions are packed, which follows from the format version a cloud was loaded from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    /// 24 bit signed fixed point with the cloud's fractional bits, used from version 2 on
+    #[default]
+    FixedPoint24,
+    /// float16, used by version 1
+    Float16,
+}
+
+impl PositionEncoding {
+    /// Bytes taken by the three coordinates of one splat
+    pub fn size(self) -> usize {
+        match self {
+            PositionEncoding::FixedPoint24 => 9,
+            PositionEncoding::Float16 => 6,
+        }
+    }
+}
+
+impl PackedGaussiansHeader {
+    /// Size of the header in bytes, at the start of the decompressed stream
+    pub const SIZE: usize = 16;
+
+    /// The format revision, or None for one this version of the crate can't read
+    pub fn format_version(&self) -> Option<FormatVersion> {
+        FormatVersion::from_u32(self.version)
+    }
+
+    /// How positions are packed in files of this header's version
+    pub fn position_encoding(&self) -> PositionEncoding {
+        if self.version == 1 { PositionEncoding::Float16 } else { PositionEncoding::FixedPoint24 }
+    }
+
+    /// Flag bits with no meaning to this version of the crate, which a newer writer may have set
+    pub fn unknown_flags(&self) -> u8 {
+        self.flags & !KNOWN_FLAGS
+    }
+
+    /// Fails on flag bits or a reserved byte this version of the crate doesn't understand, for
+    /// strict loading. The reserved byte is always 0, as in the reference implementation.
+    pub(crate) fn check_known_fields(&self) -> Result<(), SpzError> {
+        if self.unknown_flags() != 0 || self.reserved != 0 {
+            return Err(SpzError::UnknownHeaderFields { flags: self.unknown_flags(), reserved: self.reserved });
+        }
+        Ok(())
+    }
+
+    /// Sizes in bytes of the fields the header describes, in file order. Computed in u64 so
+    /// nothing a header declares can overflow.
+    fn field_sizes_u64(&self) -> [u64; 6] {
+        let n = self.num_points as u64;
+        let position_size = self.position_encoding().size() as u64;
+        let rotation_size = if self.version == 3 { 4 } else { 3 };
+        let sh_size = dim_for_degree(self.sh_degree as usize) as u64 * 3;
+        [n * position_size, n, n * 3, n * 3, n * rotation_size, n * sh_size]
+    }
+
+    /// Size in bytes of the header and the fields it describes, before compression
+    pub fn decompressed_size(&self) -> u64 {
+        PackedGaussiansHeader::SIZE as u64 + self.field_sizes_u64().iter().sum::<u64>()
+    }
+
+    /// Sizes in bytes of the fields in [`FIELD_NAMES`] order, or None if the cloud couldn't fit
+    /// in memory on this platform
+    pub(crate) fn field_sizes(&self) -> Option<[usize; 6]> {
+        usize::try_from(self.decompressed_size()).ok()?;
+        Some(self.field_sizes_u64().map(|size| size as usize))
+    }
+
+    /// Decodes the header fields, which are stored little endian
+    pub fn from_bytes(bytes: &[u8; PackedGaussiansHeader::SIZE]) -> PackedGaussiansHeader {
+        let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        PackedGaussiansHeader {
+            magic: u32_at(0),
+            version: u32_at(4),
+            num_points: u32_at(8),
+            sh_degree: bytes[12],
+            fractional_bits: bytes[13],
+            flags: bytes[14],
+            reserved: bytes[15],
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; PackedGaussiansHeader::SIZE] {
+        let mut bytes = [0; PackedGaussiansHeader::SIZE];
+        bytes[0..4].copy_from_slice(&self.magic.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.version.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.num_points.to_le_bytes());
+        bytes[12..].copy_from_slice(&[self.sh_degree, self.fractional_bits, self.flags, self.reserved]);
+        bytes
+    }
+}
+
+#[derive(Clone)]
 pub struct PackedGaussians {
     pub num_points: usize,
     pub sh_degree: usize,
@@ -174,43 +705,185 @@ pub struct PackedGaussians {
     pub alphas: Vec<u8>,
     pub colors: Vec<u8>,
     pub sh: Vec<u8>,
+    pub attributes: CustomAttributes,
+    /// How `positions` are packed, set from the version of a loaded file
+    pub position_encoding: PositionEncoding,
+    /// Axis convention the packed splats are in. It isn't saved: .spz files are always RUB, so
+    /// loaded clouds are RUB and saving converts other conventions to it. Splats are decoded
+    /// from it into RUB unless the unpack options say otherwise.
+    pub coordinate_system: CoordinateSystem,
 }
 
 impl PackedGaussians {
+    /// True for clouds with float16 positions, as in version 1 files
     pub fn uses_float16(&self) -> bool {
-        self.positions.len() == self.num_points * 3 * 2
+        self.position_encoding == PositionEncoding::Float16
+    }
+
+    /// The header this cloud is saved with
+    pub fn header(&self) -> PackedGaussiansHeader {
+        PackedGaussiansHeader {
+            version: if self.uses_float16() { 1 } else { 2 },
+            num_points: self.num_points as u32,
+            sh_degree: self.sh_degree as u8,
+            fractional_bits: self.fractional_bits as u8,
+            flags: if self.antialiased { FLAG_ANTIALIASED } else { 0 }
+                | if self.sh_degree > 3 { FLAG_EXPERIMENTAL_SH } else { 0 },
+            ..Default::default()
+        }
+    }
+
+    /// Borrows the packed data, which is how most decoding is done
+    pub fn view(&self) -> PackedGaussiansView<'_> {
+        PackedGaussiansView {
+            num_points: self.num_points,
+            sh_degree: self.sh_degree,
+            fractional_bits: self.fractional_bits,
+            antialiased: self.antialiased,
+            position_encoding: self.position_encoding,
+            coordinate_system: self.coordinate_system,
+            positions: &self.positions,
+            scales: &self.scales,
+            rotations: &self.rotations,
+            alphas: &self.alphas,
+            colors: &self.colors,
+            sh: &self.sh,
+        }
     }
 
     pub fn at(&self, i: usize) -> PackedGaussian {
-        let mut result = PackedGaussian::default();
-        let position_bits = if self.uses_float16() { 6 } else { 9 };
+        self.view().at(i)
+    }
 
+    /// Overwrites a splat with packed data, which must use the cloud's position encoding
+    pub fn set(&mut self, i: usize, gaussian: &PackedGaussian) {
+        let position_bits = self.position_encoding.size();
         let start3 = i * 3;
         let p_start = i * position_bits;
-        result.position.copy_from_slice(&self.positions[p_start..p_start + position_bits]);
-        result.scale.copy_from_slice(&self.scales[start3..start3 + 3]);
-        result.rotation.copy_from_slice(&self.rotations[start3..start3 + 3]);
-        result.color.copy_from_slice(&self.colors[start3..start3 + 3]);
-        result.alpha = self.alphas[i];
+        self.positions[p_start..p_start + position_bits].copy_from_slice(&gaussian.position[..position_bits]);
+        self.scales[start3..start3 + 3].copy_from_slice(&gaussian.scale);
+        self.rotations[start3..start3 + 3].copy_from_slice(&gaussian.rotation);
+        self.colors[start3..start3 + 3].copy_from_slice(&gaussian.color);
+        self.alphas[i] = gaussian.alpha;
 
         let sh_dim = dim_for_degree(self.sh_degree);
         let sh_start = i * sh_dim * 3;
         for j in 0..sh_dim {
-            result.sh_r[j] = self.sh[sh_start + j * 3];
-            result.sh_g[j] = self.sh[sh_start + j * 3 + 1];
-            result.sh_b[j] = self.sh[sh_start + j * 3 + 2];
+            self.sh[sh_start + j * 3] = gaussian.sh_r[j];
+            self.sh[sh_start + j * 3 + 1] = gaussian.sh_g[j];
+            self.sh[sh_start + j * 3 + 2] = gaussian.sh_b[j];
         }
-        for j in sh_dim..15 {
-            result.sh_r[j] = 128;
-            result.sh_g[j] = 128;
-            result.sh_b[j] = 128;
+    }
+
+    /// Appends a splat with packed data, which must use the cloud's position encoding. Custom
+    /// attribute channels get a zero for the new splat.
+    pub fn push(&mut self, gaussian: &PackedGaussian) {
+        let position_bits = self.position_encoding.size();
+        let sh_dim = dim_for_degree(self.sh_degree);
+        self.positions.extend_from_slice(&gaussian.position[..position_bits]);
+        self.scales.extend_from_slice(&gaussian.scale);
+        self.rotations.extend_from_slice(&gaussian.rotation);
+        self.colors.extend_from_slice(&gaussian.color);
+        self.alphas.push(gaussian.alpha);
+        for j in 0..sh_dim {
+            self.sh.extend_from_slice(&[gaussian.sh_r[j], gaussian.sh_g[j], gaussian.sh_b[j]]);
         }
+        self.attributes.push_zeros();
+        self.num_points += 1;
+    }
 
-        result
+    /// Quantizes a gaussian into a splat using the cloud's encoding
+    pub fn set_unpacked(&mut self, i: usize, gaussian: &UnpackedGaussian) {
+        let packed = PackedGaussian::pack(gaussian, self.uses_float16(), self.fractional_bits as u32);
+        self.set(i, &packed);
+    }
+
+    pub fn push_unpacked(&mut self, gaussian: &UnpackedGaussian) {
+        let packed = PackedGaussian::pack(gaussian, self.uses_float16(), self.fractional_bits as u32);
+        self.push(&packed);
+    }
+
+    /// Drops the SH coefficients above the given degree, returning the number of splats changed
+    pub fn truncate_sh(&mut self, sh_degree: usize) -> usize {
+        if sh_degree >= self.sh_degree {
+            return 0;
+        }
+        let old_stride = dim_for_degree(self.sh_degree) * 3;
+        let new_stride = dim_for_degree(sh_degree) * 3;
+        for i in 0..self.num_points {
+            self.sh.copy_within(i * old_stride..i * old_stride + new_stride, i * new_stride);
+        }
+        self.sh.truncate(self.num_points * new_stride);
+        self.sh_degree = sh_degree;
+        self.num_points
+    }
+
+    /// Builds a new cloud from the given splats, in the given order
+    pub fn select(&self, indices: &[usize]) -> PackedGaussians {
+        let position_bits = self.position_encoding.size();
+        let sh_stride = dim_for_degree(self.sh_degree) * 3;
+        let gather = |data: &[u8], stride: usize| -> Vec<u8> {
+            let mut result = Vec::with_capacity(indices.len() * stride);
+            for &i in indices {
+                result.extend_from_slice(&data[i * stride..(i + 1) * stride]);
+            }
+            result
+        };
+
+        PackedGaussians {
+            num_points: indices.len(),
+            sh_degree: self.sh_degree,
+            fractional_bits: self.fractional_bits,
+            antialiased: self.antialiased,
+            positions: gather(&self.positions, position_bits),
+            scales: gather(&self.scales, 3),
+            rotations: gather(&self.rotations, 3),
+            alphas: gather(&self.alphas, 1),
+            colors: gather(&self.colors, 3),
+            sh: gather(&self.sh, sh_stride),
+            attributes: self.attributes.select(indices),
+            position_encoding: self.position_encoding,
+            coordinate_system: self.coordinate_system,
+        }
     }
 
     pub fn unpack(&self, i: usize) -> UnpackedGaussian {
-        self.at(i).unpack(self.uses_float16(), self.fractional_bits as u32)
+        self.view().unpack(i)
+    }
+
+    pub fn unpack_with(&self, i: usize, options: &UnpackOptions) -> UnpackedGaussian {
+        self.view().unpack_with(i, options)
+    }
+
+    /// Decodes every splat in one pass over each attribute. With the `parallel` feature large
+    /// clouds are split into runs of splats decoded on separate threads.
+    pub fn unpack_all(&self) -> UnpackedGaussians {
+        self.unpack_all_with(&UnpackOptions::default())
+    }
+
+    pub fn unpack_all_with(&self, options: &UnpackOptions) -> UnpackedGaussians {
+        self.view().unpack_all_with(options)
+    }
+
+    pub fn unpack_position(&self, i: usize) -> [f32; 3] {
+        let position_bits = self.position_encoding.size();
+        let p_start = i * position_bits;
+        unquantize_position(&self.positions[p_start..p_start + position_bits], self.uses_float16(), self.fractional_bits as u32)
+    }
+
+    /// Re-encodes the position of a splat, keeping the cloud's position encoding
+    pub(crate) fn set_position(&mut self, i: usize, position: [f32; 3]) {
+        let position_bits = self.position_encoding.size();
+        let packed = quantize_position(position, self.uses_float16(), self.fractional_bits as u32);
+        self.positions[i * position_bits..(i + 1) * position_bits].copy_from_slice(&packed[..position_bits]);
+    }
+
+    pub fn unpack_rotation(&self, i: usize) -> [f32; 4] {
+        decode_quat3(&self.rotations[3*i..3*i + 3])
+    }
+
+    pub fn unpack_color(&self, i: usize) -> [f32; 3] {
+        unquantize_colors(&self.colors[3*i..3*i + 3])
     }
 
     pub fn unpack_scale(&self, i: usize) -> [f32; 3] {
@@ -222,57 +895,351 @@ impl PackedGaussians {
     }
 }
 
-pub fn load_packed_gaussians_from_decompressed_buffer<R: io::Read>(mut reader: R) -> Result<PackedGaussians, std::io::Error> {
-    let header: PackedGaussiansHeader = {   // From https://users.rust-lang.org/t/read-into-struct/30972/4
-        let mut h = [0u8; size_of::<PackedGaussiansHeader>()];
-        reader.read_exact(&mut h[..])?;
-        unsafe { mem::transmute(h) }
-    };
+/// Reads the header at the start of a decompressed .spz stream, checking that it's one this
+/// crate can decode
+pub(crate) fn read_header<R: io::Read>(reader: &mut R) -> Result<PackedGaussiansHeader, SpzError> {
+    let mut bytes = [0u8; PackedGaussiansHeader::SIZE];
+    read_field(reader, &mut bytes, "header", 0, PackedGaussiansHeader::SIZE)?;
+    let header = PackedGaussiansHeader::from_bytes(&bytes);
 
     if header.magic != PackedGaussiansHeader::default().magic {
-        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Header not found"));
+        return Err(SpzError::InvalidMagic);
     }
 
-    if header.version < 1 || header.version > 2 {
-        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Unsupported version"));
+    if header.format_version().is_none() {
+        return Err(SpzError::UnsupportedVersion(header.version));
     }
 
-    let num_points = header.num_points as usize;
-    let sh_dim = dim_for_degree(header.sh_degree as usize);
-    let uses_float16 = header.version == 1;
+    let sh_degree = header.sh_degree as usize;
+    if sh_degree > MAX_SH_DEGREE || (sh_degree > 3 && header.flags & FLAG_EXPERIMENTAL_SH == 0) {
+        return Err(SpzError::UnsupportedShDegree(header.sh_degree));
+    }
+
+    Ok(header)
+}
+
+/// Re-encodes the four byte rotations of a version 3 file with three bytes each
+pub(crate) fn convert_smallest_three_rotations(rotations: &[u8]) -> Vec<u8> {
+    rotations.chunks_exact(4).flat_map(|bytes| encode_quat3(decode_quat_smallest_three(bytes))).collect()
+}
+
+pub fn load_packed_gaussians_from_decompressed_buffer<R: io::Read>(reader: R) -> Result<PackedGaussians, SpzError> {
+    load_packed_gaussians_from_decompressed_buffer_with(reader, &LoadOptions::default())
+}
+
+pub fn load_packed_gaussians_from_decompressed_buffer_with<R: io::Read>(mut reader: R, options: &LoadOptions) -> Result<PackedGaussians, SpzError> {
+    let header = read_header(&mut reader)?;
+    let sizes = options.check_header(&header)?;
 
-    let mut result = PackedGaussians {
-        num_points,
+    // Each field only grows as its data arrives, rather than being allocated at the size the
+    // header claims
+    let [positions, alphas, colors, scales, rotations, sh] = sizes;
+    let positions = read_field_vec(&mut reader, "positions", positions)?;
+    let alphas = read_field_vec(&mut reader, "alphas", alphas)?;
+    let colors = read_field_vec(&mut reader, "colors", colors)?;
+    let scales = read_field_vec(&mut reader, "scales", scales)?;
+    let rotations = read_field_vec(&mut reader, "rotations", rotations)?;
+    let sh = read_field_vec(&mut reader, "sh", sh)?;
+    let rotations = if header.version == 3 { convert_smallest_three_rotations(&rotations) } else { rotations };
+    let result = PackedGaussians {
+        num_points: header.num_points as usize,
         sh_degree: header.sh_degree as usize,
         fractional_bits: header.fractional_bits as usize,
         antialiased: (header.flags & FLAG_ANTIALIASED) != 0,
-        positions: vec![0; num_points * 3 * if uses_float16 { 2 } else { 3 }],
-        scales: vec![0; num_points * 3],
-        rotations: vec![0; num_points * 3],
-        alphas: vec![0; num_points],
-        colors: vec![0; num_points * 3],
-        sh: vec![0; num_points * sh_dim * 3],
+        positions,
+        scales,
+        rotations,
+        alphas,
+        colors,
+        sh,
+        attributes: CustomAttributes::default(),
+        position_encoding: header.position_encoding(),
+        coordinate_system: CoordinateSystem::RUB,
     };
 
-    reader.read_exact(&mut result.positions)?;
-    reader.read_exact(&mut result.alphas)?;
-    reader.read_exact(&mut result.colors)?;
-    reader.read_exact(&mut result.scales)?;
-    reader.read_exact(&mut result.rotations)?;
-    reader.read_exact(&mut result.sh)?;
-
+    if options.strict {
+        result.validate()?;
+    }
     Ok(result)
 }
 
-pub fn load_packed_gaussians_from_spz_buffer<R: io::Read>(reader: R) -> Result<PackedGaussians, std::io::Error> {
+pub fn load_packed_gaussians_from_spz_buffer<R: io::Read>(reader: R) -> Result<PackedGaussians, SpzError> {
+    load_packed_gaussians_from_spz_buffer_with(reader, &LoadOptions::default())
+}
+
+pub fn load_packed_gaussians_from_spz_buffer_with<R: io::Read>(reader: R, options: &LoadOptions) -> Result<PackedGaussians, SpzError> {
 
     let gz_decoder = GzDecoder::new(reader);
-    load_packed_gaussians_from_decompressed_buffer(gz_decoder)
+    load_packed_gaussians_from_decompressed_buffer_with(gz_decoder, options).map_err(SpzError::through_gzip)
+}
+
+/// Loads a .spz file already held in memory, such as one fetched by a browser
+pub fn load_packed_gaussians_from_bytes(bytes: &[u8]) -> Result<PackedGaussians, SpzError> {
+    load_packed_gaussians_from_spz_buffer_with(bytes, &LoadOptions::default())
+}
+
+pub fn load_packed_gaussians_from_bytes_with(bytes: &[u8], options: &LoadOptions) -> Result<PackedGaussians, SpzError> {
+    load_packed_gaussians_from_spz_buffer_with(bytes, options)
+}
+
+pub fn load_packed_gaussians_from_file(filename: &str) -> Result<PackedGaussians, SpzError> {
+    load_packed_gaussians_from_file_with(filename, &LoadOptions::default())
 }
 
-pub fn load_packed_gaussians_from_file(filename: &String) -> Result<PackedGaussians, std::io::Error> {
+pub fn load_packed_gaussians_from_file_with(filename: &str, options: &LoadOptions) -> Result<PackedGaussians, SpzError> {
 
     let file = fs::File::open(filename)?;
     let reader = io::BufReader::new(file);
-    load_packed_gaussians_from_spz_buffer(reader)
-}
\ No newline at end of file
+    load_packed_gaussians_from_spz_buffer_with(reader, options)
+}
+
+/// Writes the header and fields of a cloud without compressing them. The cloud's fields have to
+/// match its number of splats, and its SH degree has to be one which can be saved. Anything else
+/// is written as it is, so loading and saving a file gives back the same packed bytes.
+pub fn save_packed_gaussians_to_decompressed_buffer<W: io::Write>(gaussians: &PackedGaussians, mut writer: W) -> Result<(), SpzError> {
+    if gaussians.sh_degree > MAX_SH_DEGREE {
+        return Err(SpzError::UnsupportedShDegree(gaussians.sh_degree.min(u8::MAX as usize) as u8));
+    }
+    if gaussians.num_points > u32::MAX as usize {
+        return Err(SpzError::TooManyPoints { num_points: gaussians.num_points, limit: u32::MAX as usize });
+    }
+    if gaussians.fractional_bits > u8::MAX as usize {
+        return Err(SpzError::InvalidFractionalBits(gaussians.fractional_bits));
+    }
+    gaussians.check_field_lengths()?;
+    if !matches!(gaussians.coordinate_system, CoordinateSystem::Unspecified | CoordinateSystem::RUB) {
+        let mut converted = gaussians.clone();
+        converted.convert_coordinates(CoordinateSystem::RUB);
+        return save_packed_gaussians_to_decompressed_buffer(&converted, writer);
+    }
+
+    writer.write_all(&gaussians.header().to_bytes())?;
+
+    writer.write_all(&gaussians.positions)?;
+    writer.write_all(&gaussians.alphas)?;
+    writer.write_all(&gaussians.colors)?;
+    writer.write_all(&gaussians.scales)?;
+    writer.write_all(&gaussians.rotations)?;
+    writer.write_all(&gaussians.sh)?;
+    Ok(())
+}
+
+/// Writes a cloud as a .spz file, the gzip compressed form of
+/// [`save_packed_gaussians_to_decompressed_buffer`]
+pub fn write_packed_gaussians_to_spz_buffer<W: io::Write>(gaussians: &PackedGaussians, writer: W) -> Result<(), SpzError> {
+    let mut gz_encoder = GzEncoder::new(writer, Compression::default());
+    save_packed_gaussians_to_decompressed_buffer(gaussians, &mut gz_encoder)?;
+    gz_encoder.finish()?;
+    Ok(())
+}
+
+/// Same as [`write_packed_gaussians_to_spz_buffer`], named to match the other savers
+pub fn save_packed_gaussians_to_spz_buffer<W: io::Write>(gaussians: &PackedGaussians, writer: W) -> Result<(), SpzError> {
+    write_packed_gaussians_to_spz_buffer(gaussians, writer)
+}
+
+pub fn save_packed_gaussians_to_file(gaussians: &PackedGaussians, filename: &str) -> Result<(), SpzError> {
+
+    let file = fs::File::create(filename)?;
+    let mut writer = io::BufWriter::new(file);
+    write_packed_gaussians_to_spz_buffer(gaussians, &mut writer)?;
+    io::Write::flush(&mut writer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+
+    fn spz_bytes(gaussians: &PackedGaussians) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_packed_gaussians_to_spz_buffer(gaussians, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn save_and_load_round_trips_packed_bytes() {
+        for sh_degree in 0..=3 {
+            let cloud = random_cloud(300, sh_degree, sh_degree as u64);
+            let loaded = load_packed_gaussians_from_bytes(&spz_bytes(&cloud)).unwrap();
+            assert_eq!(loaded.header(), cloud.header());
+            assert_eq!(loaded.positions, cloud.positions);
+            assert_eq!(loaded.alphas, cloud.alphas);
+            assert_eq!(loaded.colors, cloud.colors);
+            assert_eq!(loaded.scales, cloud.scales);
+            assert_eq!(loaded.rotations, cloud.rotations);
+            assert_eq!(loaded.sh, cloud.sh);
+            // Saving what was loaded writes the same file again
+            assert_eq!(spz_bytes(&loaded), spz_bytes(&cloud));
+        }
+    }
+
+    #[test]
+    fn saving_converts_other_conventions_to_rub() {
+        let mut cloud = random_cloud(200, 2, 5);
+        cloud.coordinate_system = CoordinateSystem::RDF;
+        let bytes = spz_bytes(&cloud);
+        let loaded = load_packed_gaussians_from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.coordinate_system, CoordinateSystem::RUB);
+        assert_eq!(loaded.header().reserved, 0);
+        let mut converted = cloud.clone();
+        converted.convert_coordinates(CoordinateSystem::RUB);
+        assert_eq!(spz_bytes(&converted), bytes);
+
+        // Decoding either cloud gives the same splats in RUB
+        let options = UnpackOptions::default();
+        for i in 0..cloud.num_points {
+            let (a, b) = (cloud.unpack_with(i, &options), loaded.unpack_with(i, &options));
+            assert_eq!(a.position, b.position);
+            assert_eq!(a.scale, b.scale);
+            for j in 0..dim_for_degree(2) {
+                assert!((a.sh_r[j] - b.sh_r[j]).abs() <= 1.0 / 128.0);
+            }
+        }
+    }
+
+    #[test]
+    fn float16_clouds_keep_their_encoding_when_empty() {
+        let mut cloud = random_cloud(4, 0, 3);
+        cloud.position_encoding = PositionEncoding::Float16;
+        cloud.positions.truncate(4 * 6);
+        let loaded = load_packed_gaussians_from_bytes(&spz_bytes(&cloud)).unwrap();
+        assert_eq!((loaded.header().version, loaded.position_encoding), (1, PositionEncoding::Float16));
+
+        let empty = loaded.select(&[]);
+        assert!(empty.uses_float16());
+        let loaded = load_packed_gaussians_from_bytes(&spz_bytes(&empty)).unwrap();
+        assert_eq!((loaded.header().version, loaded.num_points), (1, 0));
+    }
+
+    #[test]
+    fn version_3_rotations_are_converted_on_load() {
+        let cloud = random_cloud(50, 1, 8);
+        let mut decompressed = Vec::new();
+        save_packed_gaussians_to_decompressed_buffer(&cloud, &mut decompressed).unwrap();
+        // Swaps the three byte rotations for four byte ones, which start after the positions,
+        // alphas, colors and scales
+        let start = PackedGaussiansHeader::SIZE + 50 * (9 + 1 + 3 + 3);
+        let smallest_three: Vec<u8> = cloud.rotations.chunks_exact(3)
+            .flat_map(|bytes| codec::encode_quat_smallest_three(decode_quat3(bytes)))
+            .collect();
+        decompressed[4] = 3;
+        decompressed.splice(start..start + 150, smallest_three.iter().copied());
+        let expected = convert_smallest_three_rotations(&smallest_three);
+
+        let loaded = load_packed_gaussians_from_decompressed_buffer(&decompressed[..]).unwrap();
+        assert_eq!(loaded.rotations, expected);
+        assert_eq!(loaded.header().version, 2);
+        assert_eq!((loaded.positions, loaded.sh), (cloud.positions, cloud.sh));
+
+        let mut compressed = Vec::new();
+        let mut encoder = GzEncoder::new(&mut compressed, Compression::default());
+        io::Write::write_all(&mut encoder, &decompressed).unwrap();
+        encoder.finish().unwrap();
+        let mut decoder = incremental::SpzDecoder::new();
+        decoder.push(&compressed).unwrap();
+        assert_eq!(decoder.finish().unwrap().rotations, expected);
+        let streamed: Vec<UnpackedGaussian> = stream::SpzReader::new(io::Cursor::new(&compressed)).unwrap().map(Result::unwrap).collect();
+        for (i, gaussian) in streamed.iter().enumerate() {
+            assert_eq!(gaussian.rotation, decode_quat3(&expected[i * 3..i * 3 + 3]));
+        }
+
+        assert!(matches!(PackedGaussiansView::from_decompressed_bytes(&decompressed), Err(SpzError::UnsupportedVersion(3))));
+    }
+
+    #[test]
+    fn strict_loading_rejects_a_reserved_byte() {
+        let mut bytes = Vec::new();
+        save_packed_gaussians_to_decompressed_buffer(&random_cloud(3, 0, 2), &mut bytes).unwrap();
+        bytes[15] = 4;
+        assert!(load_packed_gaussians_from_decompressed_buffer(&bytes[..]).is_ok());
+        let strict = LoadOptions::default().strict(true);
+        let result = load_packed_gaussians_from_decompressed_buffer_with(&bytes[..], &strict);
+        assert!(matches!(result, Err(SpzError::UnknownHeaderFields { flags: 0, reserved: 4 })));
+    }
+
+    #[test]
+    fn save_rejects_fields_not_matching_the_splat_count() {
+        let mut cloud = random_cloud(10, 1, 1);
+        cloud.colors.pop();
+        let error = write_packed_gaussians_to_spz_buffer(&cloud, Vec::new()).unwrap_err();
+        assert!(matches!(error, SpzError::InvalidFieldLength { field: "colors", expected: 30, got: 29 }), "{:?}", error);
+
+        let mut cloud = random_cloud(10, 1, 1);
+        cloud.num_points = 11;
+        assert!(matches!(write_packed_gaussians_to_spz_buffer(&cloud, Vec::new()), Err(SpzError::InvalidFieldLength { .. })));
+
+        let mut cloud = random_cloud(10, 1, 1);
+        cloud.sh_degree = MAX_SH_DEGREE + 1;
+        assert!(matches!(write_packed_gaussians_to_spz_buffer(&cloud, Vec::new()), Err(SpzError::UnsupportedShDegree(_))));
+    }
+
+    #[test]
+    fn headers_decode_little_endian_and_round_trip() {
+        for (bytes, expected) in crate::selftest::KNOWN_HEADERS {
+            let header = PackedGaussiansHeader::from_bytes(&bytes);
+            let fields = [header.magic, header.version, header.num_points, header.sh_degree as u32,
+                header.fractional_bits as u32, header.flags as u32, header.reserved as u32];
+            assert_eq!(fields, expected, "{:02x?}", bytes);
+            assert_eq!(header.to_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn byte_swapped_headers_are_rejected() {
+        let (bytes, _) = crate::selftest::KNOWN_HEADERS[2];
+        assert!(matches!(load_packed_gaussians_from_decompressed_buffer(&bytes[..]), Err(SpzError::InvalidMagic)));
+    }
+
+    #[test]
+    fn version_1_float16_positions_agree_across_decode_paths() {
+        use crate::selftest::{KNOWN_V1_FILE, KNOWN_V1_POSITIONS};
+        let gaussians = load_packed_gaussians_from_decompressed_buffer(&KNOWN_V1_FILE[..]).unwrap();
+        assert_eq!(gaussians.position_encoding, PositionEncoding::Float16);
+        assert_eq!(gaussians.num_points, 2);
+        let unpacked = gaussians.unpack_all();
+        for (i, expected) in KNOWN_V1_POSITIONS.into_iter().enumerate() {
+            let expected = expected.map(f32::from_bits);
+            assert_eq!(gaussians.unpack_position(i), expected);
+            assert_eq!(gaussians.unpack(i).position, expected);
+            assert_eq!(unpacked.positions[i * 3..i * 3 + 3], expected);
+        }
+    }
+
+    #[test]
+    fn hostile_headers_are_truncated_without_allocating_their_claim() {
+        use crate::selftest::HOSTILE_HEADER;
+        let loaded = load_packed_gaussians_from_decompressed_buffer(&HOSTILE_HEADER[..]);
+        assert!(matches!(loaded, Err(SpzError::TruncatedField { field: "positions", .. })));
+        let limited = LoadOptions::default().max_decompressed_bytes(1 << 20);
+        let loaded = load_packed_gaussians_from_decompressed_buffer_with(&HOSTILE_HEADER[..], &limited);
+        assert!(matches!(loaded, Err(SpzError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn large_fractional_bits_decode_without_overflow() {
+        let mut file = crate::selftest::KNOWN_FILE;
+        for (bits, expected) in [(40, 0x3591a2b0), (255, 0)] {
+            file[13] = bits;
+            let gaussians = load_packed_gaussians_from_decompressed_buffer(&file[..]).unwrap();
+            assert_eq!(gaussians.unpack_position(0)[2].to_bits(), expected, "{} bits", bits);
+        }
+    }
+
+    #[test]
+    fn pushed_splats_extend_attribute_channels() {
+        use crate::attributes::{AttributeData, MATERIAL_ATTRIBUTE};
+        let mut cloud = random_cloud(2, 0, 4);
+        cloud.set_material_ids(vec![3, 4]).unwrap();
+        cloud.set_timestamps(vec![1.5, 2.5]).unwrap();
+        let extra = cloud.at(1);
+        cloud.push(&extra);
+        cloud.push_unpacked(&cloud.unpack(0));
+        assert_eq!(cloud.material_ids(), Some(&[3, 4, 0, 0][..]));
+        assert_eq!(cloud.timestamps(), Some(&[1.5, 2.5, 0.0, 0.0][..]));
+
+        let selected = cloud.select(&[3, 0]);
+        assert_eq!(selected.attributes.get(MATERIAL_ATTRIBUTE), Some(&AttributeData::U32(vec![0, 3])));
+    }
+}
@@ -0,0 +1,271 @@
+// Versioned scene manifest describing a multi-asset splat experience. A manifest lists each splat
+// asset with its placement in the scene and a chain of levels of detail, where each level is split
+// into one or more tiles. Clients fetch the manifest first and then ask it which payloads to stream.
+
+use std::fs;
+use std::io;
+
+use crate::json::{self, JsonValue};
+
+pub const MANIFEST_VERSION: u32 = 1;
+
+const IDENTITY_TRANSFORM: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 1.0, 0.0,
+    0.0, 0.0, 0.0, 1.0,
+];
+
+/// A single independently fetchable .spz payload. Bounds are in the asset's local space.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestTile {
+    pub uri: String,
+    pub num_points: usize,
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+/// One level of detail for an asset. The level is used while the viewer is at most
+/// `max_distance` away from the asset, with levels ordered from finest to coarsest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestLod {
+    pub max_distance: f32,
+    pub tiles: Vec<ManifestTile>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestAsset {
+    pub name: String,
+    /// Column major local to world transform
+    pub transform: [f32; 16],
+    pub lods: Vec<ManifestLod>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneManifest {
+    pub version: u32,
+    pub assets: Vec<ManifestAsset>,
+}
+
+/// A payload picked by [`SceneManifest::resolve_payloads`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedPayload<'a> {
+    pub asset: &'a str,
+    pub lod: usize,
+    pub tile: &'a ManifestTile,
+}
+
+impl Default for SceneManifest {
+    fn default() -> SceneManifest {
+        SceneManifest {
+            version: MANIFEST_VERSION,
+            assets: Vec::new(),
+        }
+    }
+}
+
+impl ManifestAsset {
+    pub fn new(name: &str) -> ManifestAsset {
+        ManifestAsset {
+            name: name.to_string(),
+            transform: IDENTITY_TRANSFORM,
+            lods: Vec::new(),
+        }
+    }
+
+    fn transform_point(&self, p: [f32; 3]) -> [f32; 3] {
+        let m = &self.transform;
+        let mut result = [0.0; 3];
+        for (i, r) in result.iter_mut().enumerate() {
+            *r = m[i] * p[0] + m[4 + i] * p[1] + m[8 + i] * p[2] + m[12 + i];
+        }
+        result
+    }
+
+    /// World space bounds of the given local space box
+    pub fn world_bounds(&self, min: [f32; 3], max: [f32; 3]) -> ([f32; 3], [f32; 3]) {
+        let mut world_min = [f32::INFINITY; 3];
+        let mut world_max = [f32::NEG_INFINITY; 3];
+        for corner in 0..8 {
+            let p = [
+                if corner & 1 == 0 { min[0] } else { max[0] },
+                if corner & 2 == 0 { min[1] } else { max[1] },
+                if corner & 4 == 0 { min[2] } else { max[2] },
+            ];
+            let w = self.transform_point(p);
+            for i in 0..3 {
+                world_min[i] = world_min[i].min(w[i]);
+                world_max[i] = world_max[i].max(w[i]);
+            }
+        }
+        (world_min, world_max)
+    }
+
+    /// World space bounds over all tiles of all levels of detail
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        let mut result: Option<([f32; 3], [f32; 3])> = None;
+        for tile in self.lods.iter().flat_map(|lod| lod.tiles.iter()) {
+            let (tile_min, tile_max) = self.world_bounds(tile.min, tile.max);
+            result = Some(match result {
+                None => (tile_min, tile_max),
+                Some((min, max)) => (
+                    [min[0].min(tile_min[0]), min[1].min(tile_min[1]), min[2].min(tile_min[2])],
+                    [max[0].max(tile_max[0]), max[1].max(tile_max[1]), max[2].max(tile_max[2])],
+                ),
+            });
+        }
+        result
+    }
+
+    /// Picks the finest level of detail whose `max_distance` covers the viewer, falling back to
+    /// the coarsest level when the viewer is further away than all of them.
+    pub fn select_lod(&self, viewer_position: [f32; 3]) -> Option<usize> {
+        if self.lods.is_empty() {
+            return None;
+        }
+
+        let distance = match self.bounds() {
+            Some((min, max)) => distance_to_box(viewer_position, min, max),
+            None => 0.0,
+        };
+
+        Some(self.lods.iter()
+            .position(|lod| distance <= lod.max_distance)
+            .unwrap_or(self.lods.len() - 1))
+    }
+}
+
+fn distance_to_box(p: [f32; 3], min: [f32; 3], max: [f32; 3]) -> f32 {
+    let mut d2 = 0.0;
+    for i in 0..3 {
+        let d = (min[i] - p[i]).max(0.0).max(p[i] - max[i]);
+        d2 += d * d;
+    }
+    d2.sqrt()
+}
+
+impl SceneManifest {
+    /// Returns the tiles that should be streamed for a viewer at the given world position
+    pub fn resolve_payloads(&self, viewer_position: [f32; 3]) -> Vec<ResolvedPayload<'_>> {
+        let mut result = Vec::new();
+        for asset in &self.assets {
+            if let Some(lod) = asset.select_lod(viewer_position) {
+                for tile in &asset.lods[lod].tiles {
+                    result.push(ResolvedPayload { asset: &asset.name, lod, tile });
+                }
+            }
+        }
+        result
+    }
+
+    pub fn to_json(&self) -> String {
+        let assets = self.assets.iter().map(|asset| {
+            let lods = asset.lods.iter().map(|lod| {
+                let tiles = lod.tiles.iter().map(|tile| JsonValue::Object(vec![
+                    ("uri".to_string(), JsonValue::String(tile.uri.clone())),
+                    ("num_points".to_string(), JsonValue::Number(tile.num_points as f64)),
+                    ("min".to_string(), JsonValue::from_f32_slice(&tile.min)),
+                    ("max".to_string(), JsonValue::from_f32_slice(&tile.max)),
+                ])).collect();
+                JsonValue::Object(vec![
+                    ("max_distance".to_string(), max_distance_to_json(lod.max_distance)),
+                    ("tiles".to_string(), JsonValue::Array(tiles)),
+                ])
+            }).collect();
+            JsonValue::Object(vec![
+                ("name".to_string(), JsonValue::String(asset.name.clone())),
+                ("transform".to_string(), JsonValue::from_f32_slice(&asset.transform)),
+                ("lods".to_string(), JsonValue::Array(lods)),
+            ])
+        }).collect();
+
+        JsonValue::Object(vec![
+            ("version".to_string(), JsonValue::Number(self.version as f64)),
+            ("assets".to_string(), JsonValue::Array(assets)),
+        ]).to_json_string_pretty()
+    }
+
+    pub fn from_json(text: &str) -> Result<SceneManifest, io::Error> {
+        let root = json::parse(text)?;
+
+        let version = root.get("version").and_then(JsonValue::as_f64)
+            .ok_or_else(|| invalid_manifest("Missing manifest version"))? as u32;
+        if version < 1 || version > MANIFEST_VERSION {
+            return Err(invalid_manifest("Unsupported manifest version"));
+        }
+
+        let mut manifest = SceneManifest { version, assets: Vec::new() };
+        for asset_json in array_field(&root, "assets")? {
+            let mut asset = ManifestAsset::new(string_field(asset_json, "name")?);
+            if asset_json.get("transform").is_some() {
+                asset.transform = f32_array_field(asset_json, "transform")?;
+            }
+
+            for lod_json in array_field(asset_json, "lods")? {
+                let max_distance = match lod_json.get("max_distance") {
+                    None | Some(JsonValue::Null) => f32::INFINITY,
+                    Some(v) => v.as_f64().ok_or_else(|| invalid_manifest("Invalid max_distance"))? as f32,
+                };
+
+                let mut tiles = Vec::new();
+                for tile_json in array_field(lod_json, "tiles")? {
+                    tiles.push(ManifestTile {
+                        uri: string_field(tile_json, "uri")?.to_string(),
+                        num_points: tile_json.get("num_points").and_then(JsonValue::as_f64).unwrap_or(0.0) as usize,
+                        min: f32_array_field(tile_json, "min")?,
+                        max: f32_array_field(tile_json, "max")?,
+                    });
+                }
+                asset.lods.push(ManifestLod { max_distance, tiles });
+            }
+            manifest.assets.push(asset);
+        }
+
+        Ok(manifest)
+    }
+}
+
+// Infinity isn't representable in JSON so an unbounded distance is written as null
+fn max_distance_to_json(max_distance: f32) -> JsonValue {
+    if max_distance.is_finite() {
+        JsonValue::from_f32(max_distance)
+    } else {
+        JsonValue::Null
+    }
+}
+
+fn invalid_manifest(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn array_field<'a>(value: &'a JsonValue, key: &str) -> Result<&'a [JsonValue], io::Error> {
+    value.get(key).and_then(JsonValue::as_array)
+        .ok_or_else(|| invalid_manifest(&format!("Missing or invalid manifest field '{}'", key)))
+}
+
+fn string_field<'a>(value: &'a JsonValue, key: &str) -> Result<&'a str, io::Error> {
+    value.get(key).and_then(JsonValue::as_str)
+        .ok_or_else(|| invalid_manifest(&format!("Missing or invalid manifest field '{}'", key)))
+}
+
+fn f32_array_field<const N: usize>(value: &JsonValue, key: &str) -> Result<[f32; N], io::Error> {
+    let values = array_field(value, key)?;
+    if values.len() != N {
+        return Err(invalid_manifest(&format!("Manifest field '{}' should have {} elements", key, N)));
+    }
+
+    let mut result = [0.0; N];
+    for (r, v) in result.iter_mut().zip(values) {
+        *r = v.as_f64().ok_or_else(|| invalid_manifest(&format!("Manifest field '{}' should be numeric", key)))? as f32;
+    }
+    Ok(result)
+}
+
+pub fn load_manifest_from_file(filename: &str) -> Result<SceneManifest, io::Error> {
+    let text = fs::read_to_string(filename)?;
+    SceneManifest::from_json(&text)
+}
+
+pub fn save_manifest_to_file(manifest: &SceneManifest, filename: &str) -> Result<(), io::Error> {
+    fs::write(filename, manifest.to_json())
+}
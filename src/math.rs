@@ -0,0 +1,177 @@
+// Small vector, matrix and quaternion helpers shared by the geometric operations. Matrices are
+// row major 3x3 arrays and quaternions are stored as w, x, y, z to match UnpackedGaussian.
+
+pub(crate) type Vec3 = [f32; 3];
+pub(crate) type Mat3 = [[f32; 3]; 3];
+pub(crate) type Quat = [f32; 4];
+
+pub(crate) fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(crate) fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+pub(crate) fn length(a: Vec3) -> f32 {
+    dot(a, a).sqrt()
+}
+
+pub(crate) fn normalize(a: Vec3) -> Vec3 {
+    let len = length(a);
+    if len > 0.0 { [a[0] / len, a[1] / len, a[2] / len] } else { a }
+}
+
+pub(crate) fn quat_normalize(q: Quat) -> Quat {
+    let norm = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
+    if norm > 0.0 {
+        [q[0] / norm, q[1] / norm, q[2] / norm, q[3] / norm]
+    } else {
+        [1.0, 0.0, 0.0, 0.0]
+    }
+}
+
+/// Hamilton product, the rotation `b` followed by `a`
+pub(crate) fn quat_mul(a: Quat, b: Quat) -> Quat {
+    [
+        a[0] * b[0] - a[1] * b[1] - a[2] * b[2] - a[3] * b[3],
+        a[0] * b[1] + a[1] * b[0] + a[2] * b[3] - a[3] * b[2],
+        a[0] * b[2] - a[1] * b[3] + a[2] * b[0] + a[3] * b[1],
+        a[0] * b[3] + a[1] * b[2] - a[2] * b[1] + a[3] * b[0],
+    ]
+}
+
+/// Rotation matrix for a (not necessarily normalized) quaternion. The columns are the rotated
+/// x, y and z axes.
+pub(crate) fn quat_to_mat3(q: Quat) -> Mat3 {
+    let [w, x, y, z] = quat_normalize(q);
+    [
+        [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+        [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+        [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)],
+    ]
+}
+
+pub(crate) fn mat3_mul_vec3(m: &Mat3, v: Vec3) -> Vec3 {
+    [dot(m[0], v), dot(m[1], v), dot(m[2], v)]
+}
+
+pub(crate) fn mat3_transpose_mul_vec3(m: &Mat3, v: Vec3) -> Vec3 {
+    [
+        m[0][0] * v[0] + m[1][0] * v[1] + m[2][0] * v[2],
+        m[0][1] * v[0] + m[1][1] * v[1] + m[2][1] * v[2],
+        m[0][2] * v[0] + m[1][2] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+pub(crate) fn mat3_determinant(m: &Mat3) -> f32 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+/// Quaternion for a rotation matrix, with w >= 0
+pub(crate) fn mat3_to_quat(m: &Mat3) -> Quat {
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    let q = if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        [0.25 * s, (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s]
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+        [(m[2][1] - m[1][2]) / s, 0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s]
+    } else if m[1][1] > m[2][2] {
+        let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+        [(m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s]
+    } else {
+        let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+        [(m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s]
+    };
+    let q = quat_normalize(q);
+    if q[0] < 0.0 { [-q[0], -q[1], -q[2], -q[3]] } else { q }
+}
+
+/// Covariance R * S * S * R^T for log scales and a rotation
+pub(crate) fn covariance_from_scale_rotation(log_scale: Vec3, rotation: Quat) -> Mat3 {
+    let r = quat_to_mat3(rotation);
+    let s2 = log_scale.map(|s| (2.0 * s).exp());
+    let mut result = [[0.0; 3]; 3];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = r[i][0] * s2[0] * r[j][0] + r[i][1] * s2[1] * r[j][1] + r[i][2] * s2[2] * r[j][2];
+        }
+    }
+    result
+}
+
+/// Eigen decomposition of a symmetric matrix using cyclic Jacobi rotations. Returns the
+/// eigenvalues and a rotation matrix (determinant +1) whose columns are the eigenvectors.
+pub(crate) fn symmetric_eigen(m: &Mat3) -> (Vec3, Mat3) {
+    let mut a = [[0.0f64; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            a[i][j] = 0.5 * (m[i][j] as f64 + m[j][i] as f64);
+        }
+    }
+    let mut v = [[1.0f64, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    for _ in 0..32 {
+        let off = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        let diag = a[0][0] * a[0][0] + a[1][1] * a[1][1] + a[2][2] * a[2][2];
+        if off <= 1e-30 * diag.max(1e-300) {
+            break;
+        }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+
+            for row in a.iter_mut() {
+                let akp = row[p];
+                let akq = row[q];
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            a[p] = [0, 1, 2].map(|k| c * row_p[k] - s * row_q[k]);
+            a[q] = [0, 1, 2].map(|k| s * row_p[k] + c * row_q[k]);
+            for row in v.iter_mut() {
+                let vkp = row[p];
+                let vkq = row[q];
+                row[p] = c * vkp - s * vkq;
+                row[q] = s * vkp + c * vkq;
+            }
+        }
+    }
+
+    let values = [a[0][0] as f32, a[1][1] as f32, a[2][2] as f32];
+    let mut vectors = [[0.0f32; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            vectors[i][j] = v[i][j] as f32;
+        }
+    }
+    if mat3_determinant(&vectors) < 0.0 {
+        for row in vectors.iter_mut() {
+            row[2] = -row[2];
+        }
+    }
+    (values, vectors)
+}
+
+/// Log scales and rotation for a covariance matrix, the inverse of
+/// [`covariance_from_scale_rotation`]. Eigenvalues are clamped to `min_variance` so degenerate
+/// covariances still give finite scales.
+pub(crate) fn scale_rotation_from_covariance(covariance: &Mat3, min_variance: f32) -> (Vec3, Quat) {
+    let (values, vectors) = symmetric_eigen(covariance);
+    let log_scale = values.map(|v| 0.5 * v.max(min_variance).ln());
+    (log_scale, mat3_to_quat(&vectors))
+}
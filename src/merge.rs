@@ -0,0 +1,172 @@
+// Energy conserving combination of gaussians, for operations such as decimation which replace
+// several splats by one. Each gaussian is weighted by its mass, opacity times the square root of
+// the determinant of its covariance, which is proportional to the total density it contributes to
+// the scene. The merged gaussian matches the combined mean and covariance (moment matching) and
+// keeps the combined mass, so merged regions stay as bright as the originals. This is why naive
+// averaging, which often shrinks total mass, visibly darkens merged areas.
+
+use crate::math;
+use crate::UnpackedGaussian;
+
+// Keeps merged opacities strictly inside (0, 1) so the inverse sigmoid stays finite
+const MIN_OPACITY: f32 = 1e-6;
+const MAX_OPACITY: f32 = 1.0 - 1e-6;
+
+// Lower bound on merged variances, in squared world units
+const MIN_VARIANCE: f32 = 1e-20;
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+fn inv_sigmoid(x: f32) -> f32 {
+    (x / (1.0 - x)).ln()
+}
+
+fn mass(g: &UnpackedGaussian) -> f32 {
+    // sqrt(det(covariance)) is the product of the three axis lengths
+    sigmoid(g.alpha) * (g.scale[0] + g.scale[1] + g.scale[2]).exp()
+}
+
+/// Merges two gaussians into one. The mean and covariance of the result match the mass weighted
+/// mixture of the inputs, colors and SH coefficients are mass weighted averages, and the opacity
+/// is chosen to preserve total mass. The opacity is capped at that of the two inputs stacked on
+/// top of each other, so merging coincident splats behaves like compositing them.
+pub fn merge_two(a: &UnpackedGaussian, b: &UnpackedGaussian) -> UnpackedGaussian {
+    let mass_a = mass(a);
+    let mass_b = mass(b);
+    let total = mass_a + mass_b;
+    let (wa, wb) = if total > 0.0 { (mass_a / total, mass_b / total) } else { (0.5, 0.5) };
+
+    let position = [0, 1, 2].map(|i| wa * a.position[i] + wb * b.position[i]);
+
+    let cov_a = math::covariance_from_scale_rotation(a.scale, a.rotation);
+    let cov_b = math::covariance_from_scale_rotation(b.scale, b.rotation);
+    let da = math::sub(a.position, position);
+    let db = math::sub(b.position, position);
+    let mut covariance = [[0.0f32; 3]; 3];
+    for (i, row) in covariance.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = wa * (cov_a[i][j] + da[i] * da[j]) + wb * (cov_b[i][j] + db[i] * db[j]);
+        }
+    }
+    let (scale, rotation) = math::scale_rotation_from_covariance(&covariance, MIN_VARIANCE);
+
+    let opacity_a = sigmoid(a.alpha);
+    let opacity_b = sigmoid(b.alpha);
+    let stacked = 1.0 - (1.0 - opacity_a) * (1.0 - opacity_b);
+    let volume = (scale[0] + scale[1] + scale[2]).exp();
+    let opacity = if volume > 0.0 { total / volume } else { stacked };
+    let opacity = opacity.min(stacked).clamp(MIN_OPACITY, MAX_OPACITY);
+
+    let blend = |x: f32, y: f32| wa * x + wb * y;
+    let mut result = UnpackedGaussian {
+        position,
+        rotation,
+        scale,
+        color: [0, 1, 2].map(|i| blend(a.color[i], b.color[i])),
+        alpha: inv_sigmoid(opacity),
+        ..Default::default()
+    };
+    for j in 0..result.sh_r.len() {
+        result.sh_r[j] = blend(a.sh_r[j], b.sh_r[j]);
+        result.sh_g[j] = blend(a.sh_g[j], b.sh_g[j]);
+        result.sh_b[j] = blend(a.sh_b[j], b.sh_b[j]);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn splat(position: [f32; 3], log_scale: f32, opacity: f32, color: [f32; 3]) -> UnpackedGaussian {
+        UnpackedGaussian {
+            position,
+            rotation: [1.0, 0.0, 0.0, 0.0],
+            scale: [log_scale; 3],
+            color,
+            alpha: inv_sigmoid(opacity),
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32, what: &str) {
+        assert!((actual - expected).abs() <= 1e-4 * expected.abs().max(1.0), "{}: {} != {}", what, actual, expected);
+    }
+
+    #[test]
+    fn disjoint_splats_match_the_mixture_moments() {
+        let a = splat([-5.0, 0.0, 0.0], 0.0, 0.2, [1.0, 0.0, 0.0]);
+        let b = splat([5.0, 0.0, 0.0], 0.0, 0.2, [0.0, 0.0, 1.0]);
+        let merged = merge_two(&a, &b);
+
+        for (j, expected) in [0.0, 0.0, 0.0].into_iter().enumerate() {
+            assert_close(merged.position[j], expected, "position");
+        }
+        // Each input has unit variance, and the means are 5 from the merged one along x
+        let covariance = math::covariance_from_scale_rotation(merged.scale, merged.rotation);
+        for (i, row) in covariance.iter().enumerate() {
+            for (j, &v) in row.iter().enumerate() {
+                let expected = if i != j { 0.0 } else if i == 0 { 26.0 } else { 1.0 };
+                assert_close(v, expected, "covariance");
+            }
+        }
+        // Total mass is kept while the opacity stays below the composited one
+        assert_close(mass(&merged), mass(&a) + mass(&b), "mass");
+        assert_close(merged.color[0], 0.5, "red");
+        assert_close(merged.color[2], 0.5, "blue");
+    }
+
+    #[test]
+    fn heavier_splat_wins_where_they_overlap() {
+        let a = splat([1.0, 2.0, 3.0], 0.5, 0.9, [1.0, 0.0, 0.0]);
+        let b = splat([1.0, 2.0, 3.0], -0.5, 0.1, [0.0, 0.0, 1.0]);
+        let merged = merge_two(&a, &b);
+        let wa = mass(&a) / (mass(&a) + mass(&b));
+
+        assert!(wa > 0.95);
+        for j in 0..3 {
+            assert_close(merged.position[j], a.position[j], "position");
+        }
+        assert_close(merged.color[0], wa, "red");
+        assert_close(merged.color[2], 1.0 - wa, "blue");
+        // The merged extent lies between the two, closer to the heavier splat
+        assert!(merged.scale.iter().all(|&s| s > 0.3 && s < 0.5), "{:?}", merged.scale);
+    }
+
+    #[test]
+    fn coincident_splats_composite() {
+        let a = splat([0.0, 1.0, 0.0], -1.0, 0.3, [0.2, 0.4, 0.6]);
+        let merged = merge_two(&a, &a);
+
+        for j in 0..3 {
+            assert_close(merged.position[j], a.position[j], "position");
+            assert_close(merged.scale[j], a.scale[j], "scale");
+            assert_close(merged.color[j], a.color[j], "color");
+        }
+        // Twice the mass would need an opacity of 0.6, more than the 0.51 of drawing both
+        assert_close(sigmoid(merged.alpha), 1.0 - 0.7 * 0.7, "opacity");
+    }
+
+    #[test]
+    fn sh_bands_missing_from_one_side_blend_as_zero() {
+        let mut a = splat([0.0; 3], 0.0, 0.5, [0.0; 3]);
+        a.sh_r[..3].copy_from_slice(&[0.5, -0.5, 0.25]);
+        let b = splat([0.0; 3], 0.0, 0.5, [0.0; 3]);
+        let merged = merge_two(&a, &b);
+
+        for (j, expected) in [0.25, -0.25, 0.125].into_iter().enumerate() {
+            assert_close(merged.sh_r[j], expected, "sh");
+        }
+        assert!(merged.sh_g.iter().chain(&merged.sh_b).all(|&c| c == 0.0));
+    }
+
+    #[test]
+    fn transparent_splats_merge_to_finite_values() {
+        let a = splat([0.0; 3], -10.0, 0.0, [0.0; 3]);
+        let b = splat([1.0; 3], -10.0, 0.0, [1.0; 3]);
+        let merged = merge_two(&a, &b);
+        assert!(merged.alpha.is_finite() && merged.position.iter().chain(&merged.scale).all(|v| v.is_finite()), "{:?}", merged);
+    }
+}
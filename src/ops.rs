@@ -0,0 +1,320 @@
+// Registry of named operations on clouds, so processing steps can be chosen by name from the
+// command line or a job description. Downstream crates can register their own operations
+// alongside the built in ones and run them through the same pipeline.
+//
+// Steps are written as `name` or `name:key=value,key=value`, for example
+// `clamp-needles:max-ratio=8`.
+
+use std::io;
+
+use crate::diagnostics::{FLOATER_DISTANCE_FACTOR, NEEDLE_RATIO};
+use crate::events::EventLog;
+use crate::PackedGaussians;
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Named arguments to an operation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OpArgs {
+    values: Vec<(String, String)>,
+}
+
+impl OpArgs {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.values.iter_mut().find(|(k, _)| k == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.values.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.values.iter().map(|(k, _)| k.as_str())
+    }
+
+    /// Parses a numeric argument, using `default` if it isn't given
+    pub fn get_f32(&self, name: &str, default: f32) -> Result<f32, io::Error> {
+        match self.get(name) {
+            Some(v) => v.parse().map_err(|_| invalid_input(format!("Argument '{}' should be a number, not '{}'", name, v))),
+            None => Ok(default),
+        }
+    }
+}
+
+/// An operation and the arguments to run it with
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpStep {
+    pub name: String,
+    pub args: OpArgs,
+}
+
+impl OpStep {
+    pub fn parse(spec: &str) -> Result<OpStep, io::Error> {
+        let (name, rest) = match spec.split_once(':') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (spec, None),
+        };
+        if name.is_empty() {
+            return Err(invalid_input(format!("Missing operation name in '{}'", spec)));
+        }
+
+        let mut args = OpArgs::default();
+        for pair in rest.into_iter().flat_map(|r| r.split(',')).filter(|p| !p.is_empty()) {
+            let (k, v) = pair.split_once('=')
+                .ok_or_else(|| invalid_input(format!("Expected key=value but found '{}'", pair)))?;
+            args.set(k.trim(), v.trim());
+        }
+        Ok(OpStep { name: name.to_string(), args })
+    }
+}
+
+pub trait Op: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// One line summary, listing any arguments
+    fn description(&self) -> &str;
+
+    /// Names of the arguments the operation reads. Steps passing any other argument are rejected.
+    fn arguments(&self) -> &[&str];
+
+    /// Applies the operation in place, returning the number of splats changed or removed
+    fn apply(&self, cloud: &mut PackedGaussians, args: &OpArgs) -> Result<usize, io::Error>;
+}
+
+/// Operation backed by a plain function, used for the built in operations
+pub struct FnOp {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub arguments: &'static [&'static str],
+    pub function: fn(&mut PackedGaussians, &OpArgs) -> Result<usize, io::Error>,
+}
+
+impl Op for FnOp {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn arguments(&self) -> &[&str] {
+        self.arguments
+    }
+
+    fn apply(&self, cloud: &mut PackedGaussians, args: &OpArgs) -> Result<usize, io::Error> {
+        (self.function)(cloud, args)
+    }
+}
+
+fn remove_where(cloud: &mut PackedGaussians, remove: &[usize]) -> usize {
+    let mut removed = vec![false; cloud.num_points];
+    for &i in remove {
+        removed[i] = true;
+    }
+    cloud.retain_indices(|i| !removed[i])
+}
+
+fn builtins() -> Vec<FnOp> {
+    vec![
+        FnOp {
+            name: "clamp-needles",
+            description: "Shrinks needle-like splats (max-ratio)",
+            arguments: &["max-ratio"],
+            function: |cloud, args| Ok(cloud.clamp_anisotropy(args.get_f32("max-ratio", NEEDLE_RATIO)?)),
+        },
+        FnOp {
+            name: "remove-floaters",
+            description: "Removes splats far from the bulk of the cloud (factor)",
+            arguments: &["factor"],
+            function: |cloud, args| {
+                let floaters = cloud.floater_indices(args.get_f32("factor", FLOATER_DISTANCE_FACTOR)?);
+                Ok(remove_where(cloud, &floaters))
+            },
+        },
+        FnOp {
+            name: "remove-transparent",
+            description: "Removes splats with opacity at or below a threshold (min-opacity)",
+            arguments: &["min-opacity"],
+            function: |cloud, args| {
+                let limit = (args.get_f32("min-opacity", 0.0)? * 255.0).round();
+                let remove: Vec<usize> = (0..cloud.num_points).filter(|&i| cloud.alphas[i] as f32 <= limit).collect();
+                Ok(remove_where(cloud, &remove))
+            },
+        },
+        FnOp {
+            name: "remove-large",
+            description: "Removes splats with an axis longer than a threshold in world units (max-scale)",
+            arguments: &["max-scale"],
+            function: |cloud, args| Ok(cloud.prune_large(args.get_f32("max-scale", 1.0)?)),
+        },
+        FnOp {
+            name: "crop",
+            description: "Removes splats outside a box (min-x, min-y, min-z, max-x, max-y, max-z)",
+            arguments: &["min-x", "min-y", "min-z", "max-x", "max-y", "max-z"],
+            function: |cloud, args| {
+                let min = [args.get_f32("min-x", f32::NEG_INFINITY)?, args.get_f32("min-y", f32::NEG_INFINITY)?, args.get_f32("min-z", f32::NEG_INFINITY)?];
+                let max = [args.get_f32("max-x", f32::INFINITY)?, args.get_f32("max-y", f32::INFINITY)?, args.get_f32("max-z", f32::INFINITY)?];
+                Ok(cloud.prune_outside(min, max))
+            },
+        },
+        FnOp {
+            name: "sort-morton",
+            description: "Reorders splats along a Z-order curve so nearby splats are stored together",
+            arguments: &[],
+            function: |cloud, _| {
+                cloud.sort_morton();
+                Ok(cloud.num_points)
+            },
+        },
+        FnOp {
+            name: "sort-distance",
+            description: "Reorders splats from nearest to furthest from a point (x, y, z)",
+            arguments: &["x", "y", "z"],
+            function: |cloud, args| {
+                cloud.sort_by_distance([args.get_f32("x", 0.0)?, args.get_f32("y", 0.0)?, args.get_f32("z", 0.0)?]);
+                Ok(cloud.num_points)
+            },
+        },
+        FnOp {
+            name: "drop-sh",
+            description: "Removes view dependent color",
+            arguments: &[],
+            function: |cloud, _| Ok(cloud.truncate_sh(0)),
+        },
+        FnOp {
+            name: "blur",
+            description: "Convolves every splat with an isotropic gaussian (sigma)",
+            arguments: &["sigma"],
+            function: |cloud, args| Ok(cloud.blur(args.get_f32("sigma", 0.01)?)),
+        },
+        FnOp {
+            name: "feather",
+            description: "Fades out splats near the boundary of the cloud (width)",
+            arguments: &["width"],
+            function: |cloud, args| Ok(cloud.feather_boundary(args.get_f32("width", 0.1)?)),
+        },
+    ]
+}
+
+pub struct OpRegistry {
+    ops: Vec<Box<dyn Op>>,
+}
+
+impl Default for OpRegistry {
+    fn default() -> OpRegistry {
+        OpRegistry::with_builtins()
+    }
+}
+
+impl OpRegistry {
+    pub fn empty() -> OpRegistry {
+        OpRegistry { ops: Vec::new() }
+    }
+
+    pub fn with_builtins() -> OpRegistry {
+        let mut registry = OpRegistry::empty();
+        for op in builtins() {
+            registry.register(Box::new(op));
+        }
+        registry
+    }
+
+    /// Adds an operation, replacing any existing operation with the same name
+    pub fn register(&mut self, op: Box<dyn Op>) {
+        match self.ops.iter().position(|o| o.name() == op.name()) {
+            Some(index) => self.ops[index] = op,
+            None => self.ops.push(op),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Op> {
+        self.ops.iter().find(|o| o.name() == name).map(|o| o.as_ref())
+    }
+
+    pub fn ops(&self) -> impl Iterator<Item = &dyn Op> {
+        self.ops.iter().map(|o| o.as_ref())
+    }
+
+    /// Runs the steps in order, logging each to `log` if given. Every name and argument is
+    /// checked before anything is applied, so a typo doesn't leave the cloud half processed.
+    pub fn run(&self, cloud: &mut PackedGaussians, steps: &[OpStep], mut log: Option<&mut EventLog>) -> Result<(), io::Error> {
+        let ops = steps.iter()
+            .map(|step| {
+                let op = self.get(&step.name).ok_or_else(|| invalid_input(format!("Unknown operation '{}'", step.name)))?;
+                match step.args.names().find(|name| !op.arguments().contains(name)) {
+                    Some(name) => Err(invalid_input(format!("Unknown argument '{}' for operation '{}'", name, step.name))),
+                    None => Ok(op),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for (op, step) in ops.into_iter().zip(steps) {
+            let affected = op.apply(cloud, &step.args)?;
+            if let Some(log) = log.as_deref_mut() {
+                log.operation_applied(op.name(), affected)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+
+    #[test]
+    fn steps_parse_names_and_arguments() {
+        let step = OpStep::parse("crop: min-x = -1,max-z=2,").unwrap();
+        assert_eq!(step.name, "crop");
+        assert_eq!((step.args.get("min-x"), step.args.get("max-z"), step.args.get("max-x")), (Some("-1"), Some("2"), None));
+        assert_eq!(step.args.get_f32("min-x", 0.0).unwrap(), -1.0);
+        assert_eq!(step.args.get_f32("max-x", 5.0).unwrap(), 5.0);
+
+        let step = OpStep::parse("drop-sh").unwrap();
+        assert_eq!((step.name.as_str(), step.args.names().count()), ("drop-sh", 0));
+
+        assert!(OpStep::parse(":x=1").is_err());
+        assert!(OpStep::parse("blur:sigma").is_err());
+        assert!(OpStep::parse("blur:sigma=wide").unwrap().args.get_f32("sigma", 0.0).is_err());
+    }
+
+    #[test]
+    fn registering_an_existing_name_replaces_it() {
+        let mut registry = OpRegistry::with_builtins();
+        let count = registry.ops().count();
+        registry.register(Box::new(FnOp {
+            name: "drop-sh",
+            description: "Does nothing",
+            arguments: &[],
+            function: |_, _| Ok(7),
+        }));
+        assert_eq!(registry.ops().count(), count);
+        assert_eq!(registry.get("drop-sh").unwrap().description(), "Does nothing");
+
+        let mut cloud = random_cloud(10, 2, 3);
+        let sh = cloud.sh.clone();
+        registry.run(&mut cloud, &[OpStep::parse("drop-sh").unwrap()], None).unwrap();
+        assert_eq!((cloud.sh_degree, cloud.sh), (2, sh));
+    }
+
+    #[test]
+    fn unknown_names_and_arguments_are_rejected_before_anything_runs() {
+        let registry = OpRegistry::with_builtins();
+        for typo in ["dropsh", "clamp-needles:max_ratio=8"] {
+            let mut cloud = random_cloud(10, 2, 3);
+            let steps = [OpStep::parse("drop-sh").unwrap(), OpStep::parse(typo).unwrap()];
+            let error = registry.run(&mut cloud, &steps, None).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(cloud.sh_degree, 2, "{}", typo);
+        }
+
+        let mut cloud = random_cloud(10, 2, 3);
+        registry.run(&mut cloud, &[OpStep::parse("clamp-needles:max-ratio=8").unwrap()], None).unwrap();
+    }
+}
@@ -0,0 +1,395 @@
+// Import and export of gaussian splat .ply files, as written by the Inria 3D gaussian splatting
+// code and tools following its layout such as nerfstudio. Only binary little endian files are
+// supported. Splats are quantized with the same functions as the reference implementation.
+//
+// Training code writes .ply files in the RDF convention of COLMAP, while .spz is RUB, so splats
+// are converted between the two on the way in and out unless the options name another
+// convention. Rotations read back from an exported file keep their packed bytes.
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::attributes::CustomAttributes;
+use crate::codec::{decode_quat3, encode_quat3, quantize_sh, SH1_BITS, SH_REST_BITS};
+use crate::coordinates::{AxisConversion, CoordinateSystem};
+use crate::export::{fixed24_extent, fractional_bits_for_extent};
+use crate::{
+    dim_for_degree, PackedGaussian, PackedGaussians, PositionEncoding, UnpackOptions, UnpackedGaussian, MAX_SH_DEGREE,
+};
+
+// Header lines are short, so anything longer than this isn't a .ply header
+const MAX_HEADER_LINE: usize = 1024;
+
+// Caps the memory reserved up front from the vertex count in the header
+const MAX_RESERVED_POINTS: usize = 1 << 20;
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlyOptions {
+    /// Fractional bits of the fixed point positions. Loading fails if a vertex is further out
+    /// than they can hold, see [`fixed24_extent`].
+    pub fractional_bits: usize,
+    /// Bits kept for each degree 1 SH coefficient
+    pub sh1_bits: u32,
+    /// Bits kept for each SH coefficient above degree 1
+    pub sh_rest_bits: u32,
+    /// Axis convention of the .ply file. Imported splats are converted from it to RUB, and
+    /// exported ones to it from the cloud's convention if it has one. Unspecified leaves splats
+    /// as they are.
+    pub coordinate_system: CoordinateSystem,
+}
+
+impl Default for PlyOptions {
+    fn default() -> PlyOptions {
+        PlyOptions {
+            fractional_bits: 12,
+            sh1_bits: SH1_BITS,
+            sh_rest_bits: SH_REST_BITS,
+            coordinate_system: CoordinateSystem::RDF,
+        }
+    }
+}
+
+/// Packs a rotation, keeping the bytes it was decoded from if it came from a packed splat. Those
+/// don't always hold a unit quaternion, so normalizing would change them.
+fn encode_rotation(q: [f32; 4]) -> [u8; 3] {
+    let bytes = [q[1], q[2], q[3]].map(|v| (v * 127.5 + 127.5).round().clamp(0.0, 255.0) as u8);
+    if decode_quat3(&bytes) == q { bytes } else { encode_quat3(q) }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<ScalarType> {
+        Some(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+
+    fn read(&self, b: &[u8]) -> f32 {
+        match self {
+            ScalarType::I8 => b[0] as i8 as f32,
+            ScalarType::U8 => b[0] as f32,
+            ScalarType::I16 => i16::from_le_bytes([b[0], b[1]]) as f32,
+            ScalarType::U16 => u16::from_le_bytes([b[0], b[1]]) as f32,
+            ScalarType::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+            ScalarType::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32,
+            ScalarType::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            ScalarType::F64 => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32,
+        }
+    }
+}
+
+struct Header {
+    num_points: usize,
+    // Name, type and byte offset of each vertex property
+    properties: Vec<(String, ScalarType, usize)>,
+    stride: usize,
+}
+
+impl Header {
+    fn property(&self, name: &str) -> Option<(ScalarType, usize)> {
+        self.properties.iter().find(|(n, _, _)| n == name).map(|&(_, t, offset)| (t, offset))
+    }
+
+    fn required(&self, name: &str) -> Result<(ScalarType, usize), io::Error> {
+        self.property(name).ok_or_else(|| invalid_data(format!("Missing vertex property '{}'", name)))
+    }
+}
+
+fn next_line<R: BufRead>(reader: &mut R, line: &mut String) -> Result<(), io::Error> {
+    line.clear();
+    let read = io::Read::take(reader, MAX_HEADER_LINE as u64).read_line(line)?;
+    if read == 0 || !line.ends_with('\n') {
+        return Err(invalid_data("Truncated .ply header".to_string()));
+    }
+    Ok(())
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> Result<Header, io::Error> {
+    let mut line = String::new();
+    next_line(reader, &mut line)?;
+    if line.trim_end() != "ply" {
+        return Err(invalid_data("Not a .ply file".to_string()));
+    }
+
+    let mut num_points = None;
+    let mut in_vertex = false;
+    let mut properties = Vec::new();
+    let mut stride = 0;
+    loop {
+        next_line(reader, &mut line)?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["format", format, _] if *format != "binary_little_endian" => {
+                return Err(invalid_data(format!("Unsupported .ply format '{}'", format)));
+            }
+            ["element", name, count] => {
+                if *name == "vertex" {
+                    let count = count.parse().map_err(|_| invalid_data(format!("Invalid vertex count '{}'", count)))?;
+                    num_points = Some(count);
+                    in_vertex = true;
+                } else if num_points.is_none() {
+                    return Err(invalid_data(format!("Unsupported element '{}' before the vertices", name)));
+                } else {
+                    in_vertex = false;
+                }
+            }
+            ["property", "list", ..] if in_vertex => {
+                return Err(invalid_data("Unsupported list property on vertices".to_string()));
+            }
+            ["property", type_name, name] if in_vertex => {
+                let scalar = ScalarType::parse(type_name)
+                    .ok_or_else(|| invalid_data(format!("Unsupported property type '{}'", type_name)))?;
+                properties.push((name.to_string(), scalar, stride));
+                stride += scalar.size();
+            }
+            _ => {}
+        }
+    }
+
+    let num_points = num_points.ok_or_else(|| invalid_data("No vertex element in .ply file".to_string()))?;
+    Ok(Header { num_points, properties, stride })
+}
+
+pub fn load_gaussians_from_ply_buffer_with<R: BufRead>(mut reader: R, options: &PlyOptions) -> Result<PackedGaussians, io::Error> {
+    let header = read_header(&mut reader)?;
+
+    let field = |names: &[&str]| -> Result<Vec<(ScalarType, usize)>, io::Error> {
+        names.iter().map(|name| header.required(name)).collect()
+    };
+    let position = field(&["x", "y", "z"])?;
+    let scale = field(&["scale_0", "scale_1", "scale_2"])?;
+    let rotation = field(&["rot_0", "rot_1", "rot_2", "rot_3"])?;
+    let color = field(&["f_dc_0", "f_dc_1", "f_dc_2"])?;
+    let alpha = header.required("opacity")?;
+
+    // SH coefficients are stored with all of the red coefficients first, then green, then blue
+    let num_rest = (0..).take_while(|j| header.property(&format!("f_rest_{}", j)).is_some()).count();
+    let sh_degree = (0..=MAX_SH_DEGREE).find(|&d| dim_for_degree(d) * 3 == num_rest)
+        .ok_or_else(|| invalid_data(format!("Unsupported number of SH coefficients: {}", num_rest)))?;
+    let sh_dim = dim_for_degree(sh_degree);
+    let rest = (0..num_rest).map(|j| header.required(&format!("f_rest_{}", j))).collect::<Result<Vec<_>, _>>()?;
+
+    let conversion = AxisConversion::new(options.coordinate_system, CoordinateSystem::RUB);
+    let reserved = header.num_points.min(MAX_RESERVED_POINTS);
+    let mut result = PackedGaussians {
+        num_points: 0,
+        sh_degree,
+        fractional_bits: options.fractional_bits,
+        antialiased: false,
+        positions: Vec::with_capacity(reserved * 9),
+        scales: Vec::with_capacity(reserved * 3),
+        rotations: Vec::with_capacity(reserved * 3),
+        alphas: Vec::with_capacity(reserved),
+        colors: Vec::with_capacity(reserved * 3),
+        sh: Vec::with_capacity(reserved * sh_dim * 3),
+        attributes: CustomAttributes::default(),
+        position_encoding: PositionEncoding::FixedPoint24,
+        coordinate_system: match options.coordinate_system {
+            CoordinateSystem::Unspecified => CoordinateSystem::Unspecified,
+            _ => CoordinateSystem::RUB,
+        },
+    };
+
+    let extent = fixed24_extent(options.fractional_bits);
+    let mut row = vec![0u8; header.stride];
+    let value = |row: &[u8], (scalar, offset): (ScalarType, usize)| scalar.read(&row[offset..]);
+    for i in 0..header.num_points {
+        reader.read_exact(&mut row)?;
+        let mut g = UnpackedGaussian {
+            position: [0, 1, 2].map(|j| value(&row, position[j])),
+            rotation: [0, 1, 2, 3].map(|j| value(&row, rotation[j])),
+            scale: [0, 1, 2].map(|j| value(&row, scale[j])),
+            color: [0, 1, 2].map(|j| value(&row, color[j])),
+            alpha: value(&row, alpha),
+            ..Default::default()
+        };
+        for j in 0..sh_dim {
+            g.sh_r[j] = value(&row, rest[j]);
+            g.sh_g[j] = value(&row, rest[sh_dim + j]);
+            g.sh_b[j] = value(&row, rest[2 * sh_dim + j]);
+        }
+        // Fixed point positions wrap around past the extent rather than saturating
+        let reach = g.position.iter().fold(0.0, |reach: f64, &x| reach.max((x as f64).abs()));
+        if reach > extent {
+            return Err(invalid_data(format!("Vertex {} at {:?} is beyond the {} that {} fractional bits can hold, use {} or fewer",
+                i, g.position, extent, options.fractional_bits, fractional_bits_for_extent(reach))));
+        }
+        if let Some(conversion) = &conversion {
+            conversion.convert(&mut g);
+        }
+
+        let mut packed = PackedGaussian::pack(&g, false, options.fractional_bits as u32);
+        packed.rotation = encode_rotation(g.rotation);
+        for j in 0..sh_dim {
+            let bits = if j < 3 { options.sh1_bits } else { options.sh_rest_bits };
+            packed.sh_r[j] = quantize_sh(g.sh_r[j], bits);
+            packed.sh_g[j] = quantize_sh(g.sh_g[j], bits);
+            packed.sh_b[j] = quantize_sh(g.sh_b[j], bits);
+        }
+        result.push(&packed);
+    }
+
+    Ok(result)
+}
+
+pub fn load_gaussians_from_ply_buffer<R: BufRead>(reader: R) -> Result<PackedGaussians, io::Error> {
+    load_gaussians_from_ply_buffer_with(reader, &PlyOptions::default())
+}
+
+pub fn load_gaussians_from_ply_file(filename: &str) -> Result<PackedGaussians, io::Error> {
+    load_gaussians_from_ply_file_with(filename, &PlyOptions::default())
+}
+
+pub fn load_gaussians_from_ply_file_with(filename: &str, options: &PlyOptions) -> Result<PackedGaussians, io::Error> {
+    let file = fs::File::open(filename)?;
+    load_gaussians_from_ply_buffer_with(io::BufReader::new(file), options)
+}
+
+pub fn export_to_ply<W: Write>(gaussians: &PackedGaussians, writer: W) -> Result<(), io::Error> {
+    export_to_ply_with(gaussians, writer, &PlyOptions::default())
+}
+
+/// Writes every splat as a float property row, in the property order used by the original 3D
+/// gaussian splatting code so viewers which expect that layout can read the file. Normals aren't
+/// stored in .spz and are written as zero. Only the options' coordinate system is used.
+pub fn export_to_ply_with<W: Write>(gaussians: &PackedGaussians, mut writer: W, options: &PlyOptions) -> Result<(), io::Error> {
+    let sh_dim = dim_for_degree(gaussians.sh_degree);
+    let mut properties: Vec<String> = ["x", "y", "z", "nx", "ny", "nz", "f_dc_0", "f_dc_1", "f_dc_2"]
+        .iter().map(|name| name.to_string()).collect();
+    properties.extend((0..sh_dim * 3).map(|j| format!("f_rest_{}", j)));
+    properties.extend(["opacity", "scale_0", "scale_1", "scale_2", "rot_0", "rot_1", "rot_2", "rot_3"].iter().map(|name| name.to_string()));
+
+    let mut header = format!("ply\nformat binary_little_endian 1.0\nelement vertex {}\n", gaussians.num_points);
+    for name in &properties {
+        header.push_str(&format!("property float {}\n", name));
+    }
+    header.push_str("end_header\n");
+    writer.write_all(header.as_bytes())?;
+
+    let unpack_options = UnpackOptions::default().to(options.coordinate_system);
+    let mut row = Vec::with_capacity(properties.len() * 4);
+    for i in 0..gaussians.num_points {
+        let g = gaussians.unpack_with(i, &unpack_options);
+        let alpha = [g.alpha];
+        let values = g.position.iter()
+            .chain(&[0.0; 3])
+            .chain(&g.color)
+            .chain(&g.sh_r[..sh_dim])
+            .chain(&g.sh_g[..sh_dim])
+            .chain(&g.sh_b[..sh_dim])
+            .chain(&alpha)
+            .chain(&g.scale)
+            .chain(&g.rotation);
+        row.clear();
+        for v in values {
+            row.extend_from_slice(&v.to_le_bytes());
+        }
+        writer.write_all(&row)?;
+    }
+    Ok(())
+}
+
+pub fn export_to_ply_file(gaussians: &PackedGaussians, filename: &str) -> Result<(), io::Error> {
+    export_to_ply_file_with(gaussians, filename, &PlyOptions::default())
+}
+
+pub fn export_to_ply_file_with(gaussians: &PackedGaussians, filename: &str, options: &PlyOptions) -> Result<(), io::Error> {
+    let file = fs::File::create(filename)?;
+    let mut writer = io::BufWriter::new(file);
+    export_to_ply_with(gaussians, &mut writer, options)?;
+    writer.flush()
+}
+
+pub(crate) fn has_ply_extension(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ply"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+
+    fn export(cloud: &PackedGaussians, options: &PlyOptions) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        export_to_ply_with(cloud, &mut bytes, options).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn exported_files_use_the_rdf_convention() {
+        let mut cloud = random_cloud(20, 1, 13);
+        cloud.coordinate_system = CoordinateSystem::RUB;
+        let bytes = export(&cloud, &PlyOptions::default());
+        let data = &bytes[bytes.len() - 20 * 4 * (9 + 9 + 8)..];
+        for i in 0..20 {
+            let row = &data[i * 4 * 26..];
+            let float = |j: usize| f32::from_le_bytes([row[j * 4], row[j * 4 + 1], row[j * 4 + 2], row[j * 4 + 3]]);
+            let [x, y, z] = cloud.unpack_position(i);
+            assert_eq!([float(0), float(1), float(2)], [x, -y, -z]);
+        }
+    }
+
+    #[test]
+    fn round_trips_keep_positions_rotations_and_scales() {
+        let mut cloud = random_cloud(500, 1, 14);
+        cloud.coordinate_system = CoordinateSystem::RUB;
+        for system in [CoordinateSystem::RDF, CoordinateSystem::RUB, CoordinateSystem::Unspecified] {
+            let options = PlyOptions { coordinate_system: system, ..Default::default() };
+            let imported = load_gaussians_from_ply_buffer_with(&export(&cloud, &options)[..], &options).unwrap();
+            assert_eq!(imported.num_points, 500);
+            assert_eq!(imported.positions, cloud.positions, "{:?}", system);
+            assert_eq!(imported.rotations, cloud.rotations, "{:?}", system);
+            assert_eq!(imported.scales, cloud.scales, "{:?}", system);
+            assert_eq!(imported.colors, cloud.colors, "{:?}", system);
+        }
+    }
+
+    #[test]
+    fn coordinates_beyond_the_fractional_bits_are_an_error() {
+        // Positions of up to 2^15 with 8 fractional bits
+        let mut cloud = random_cloud(50, 0, 15);
+        cloud.coordinate_system = CoordinateSystem::RUB;
+        cloud.fractional_bits = 8;
+        let bytes = export(&cloud, &PlyOptions::default());
+        let error = load_gaussians_from_ply_buffer(&bytes[..]).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let options = PlyOptions { fractional_bits: 8, ..Default::default() };
+        let imported = load_gaussians_from_ply_buffer_with(&bytes[..], &options).unwrap();
+        assert_eq!(imported.positions, cloud.positions);
+    }
+}
@@ -0,0 +1,254 @@
+// Simple CPU renderer for thumbnails and previews. Each splat is drawn as an isotropic gaussian
+// footprint sized from its largest scale and composited front to back. This is nowhere near a
+// proper rasterizer but is plenty for checking what a file contains without a GPU.
+
+use crate::math::{cross, dot, normalize, sub};
+use crate::sh::SH_C0;
+use crate::PackedGaussians;
+
+const MAX_FOOTPRINT_RADIUS: f32 = 64.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PreviewCamera {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    pub up: [f32; 3],
+    /// Vertical field of view in radians
+    pub fov_y: f32,
+}
+
+/// Fades splat opacity linearly from fully opaque at `start` to invisible at `end`, measured as
+/// distance along the view direction. This stops distant geometry from accumulating into a
+/// white-out in large outdoor scenes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthFade {
+    pub start: f32,
+    pub end: f32,
+}
+
+/// Exponential fog blending splat colors towards `color` with distance
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fog {
+    pub color: [f32; 3],
+    pub density: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PreviewOptions {
+    pub width: usize,
+    pub height: usize,
+    pub background: [f32; 3],
+    pub depth_fade: Option<DepthFade>,
+    pub fog: Option<Fog>,
+}
+
+impl Default for PreviewOptions {
+    fn default() -> PreviewOptions {
+        PreviewOptions {
+            width: 256,
+            height: 256,
+            background: [0.0, 0.0, 0.0],
+            depth_fade: None,
+            fog: None,
+        }
+    }
+}
+
+/// 8 bit RGBA image, stored row by row from the top left
+#[derive(Clone, Debug, PartialEq)]
+pub struct PreviewImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl PreviewCamera {
+    pub fn look_at(position: [f32; 3], target: [f32; 3], up: [f32; 3]) -> PreviewCamera {
+        PreviewCamera {
+            position,
+            target,
+            up,
+            fov_y: 60.0f32.to_radians(),
+        }
+    }
+
+    /// Camera looking at the center of the given bounds from far enough away to fit them in view
+    pub fn framing(min: [f32; 3], max: [f32; 3]) -> PreviewCamera {
+        let center = [(min[0] + max[0]) * 0.5, (min[1] + max[1]) * 0.5, (min[2] + max[2]) * 0.5];
+        let half_extent = sub(max, center);
+        let radius = dot(half_extent, half_extent).sqrt().max(1e-3);
+        let mut camera = PreviewCamera::look_at(center, center, [0.0, 1.0, 0.0]);
+        let distance = radius / (camera.fov_y * 0.5).sin();
+        camera.position = [center[0], center[1], center[2] + distance];
+        camera
+    }
+
+    // Right, up and forward basis vectors
+    fn basis(&self) -> ([f32; 3], [f32; 3], [f32; 3]) {
+        let forward = normalize(sub(self.target, self.position));
+        let right = normalize(cross(forward, self.up));
+        let up = cross(right, forward);
+        (right, up, forward)
+    }
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+pub fn render_preview(cloud: &PackedGaussians, camera: &PreviewCamera, options: &PreviewOptions) -> PreviewImage {
+    let width = options.width;
+    let height = options.height;
+    if width == 0 || height == 0 {
+        return PreviewImage { width, height, pixels: Vec::new() };
+    }
+
+    let (right, up, forward) = camera.basis();
+    let focal = height as f32 * 0.5 / (camera.fov_y * 0.5).tan();
+
+    // Project everything up front so splats can be sorted front to back
+    struct Projected {
+        index: usize,
+        x: f32,
+        y: f32,
+        depth: f32,
+        radius: f32,
+    }
+    let mut projected = Vec::new();
+    for i in 0..cloud.num_points {
+        let d = sub(cloud.unpack_position(i), camera.position);
+        let depth = dot(d, forward);
+        if depth <= 1e-3 {
+            continue;
+        }
+
+        let x = width as f32 * 0.5 + focal * dot(d, right) / depth;
+        let y = height as f32 * 0.5 - focal * dot(d, up) / depth;
+        let scale = cloud.unpack_scale(i);
+        let sigma = scale[0].max(scale[1]).max(scale[2]).exp();
+        let radius = (3.0 * sigma * focal / depth).clamp(0.5, MAX_FOOTPRINT_RADIUS);
+        if x + radius < 0.0 || y + radius < 0.0 || x - radius >= width as f32 || y - radius >= height as f32 {
+            continue;
+        }
+        projected.push(Projected { index: i, x, y, depth, radius });
+    }
+    projected.sort_by(|a, b| a.depth.total_cmp(&b.depth));
+
+    let mut color = vec![0.0f32; width * height * 3];
+    let mut transmittance = vec![1.0f32; width * height];
+
+    for p in &projected {
+        let mut opacity = sigmoid(cloud.unpack_alpha(p.index));
+        if let Some(fade) = options.depth_fade {
+            let t = ((p.depth - fade.start) / (fade.end - fade.start).max(1e-6)).clamp(0.0, 1.0);
+            opacity *= 1.0 - t;
+        }
+        if opacity <= 1.0 / 255.0 {
+            continue;
+        }
+
+        let dc = cloud.unpack_color(p.index);
+        let mut rgb = dc.map(|v| (0.5 + SH_C0 * v).clamp(0.0, 1.0));
+        if let Some(fog) = options.fog {
+            let f = 1.0 - (-fog.density * p.depth).exp();
+            for (c, fog_c) in rgb.iter_mut().zip(fog.color) {
+                *c += (fog_c - *c) * f;
+            }
+        }
+
+        let sigma = p.radius / 3.0;
+        let inv_two_sigma2 = 0.5 / (sigma * sigma);
+        let x0 = (p.x - p.radius).floor().max(0.0) as usize;
+        let y0 = (p.y - p.radius).floor().max(0.0) as usize;
+        let x1 = ((p.x + p.radius).ceil() as usize).min(width - 1);
+        let y1 = ((p.y + p.radius).ceil() as usize).min(height - 1);
+        for py in y0..=y1 {
+            for px in x0..=x1 {
+                let pixel = py * width + px;
+                let t = transmittance[pixel];
+                if t < 1e-3 {
+                    continue;
+                }
+
+                let dx = px as f32 + 0.5 - p.x;
+                let dy = py as f32 + 0.5 - p.y;
+                let a = (opacity * (-(dx * dx + dy * dy) * inv_two_sigma2).exp()).min(0.99);
+                for c in 0..3 {
+                    color[pixel * 3 + c] += rgb[c] * a * t;
+                }
+                transmittance[pixel] = t * (1.0 - a);
+            }
+        }
+    }
+
+    let background = match options.fog {
+        Some(fog) => fog.color,
+        None => options.background,
+    };
+
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for (pixel, &t) in transmittance.iter().enumerate() {
+        for c in 0..3 {
+            let v = color[pixel * 3 + c] + background[c] * t;
+            pixels.push((v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8);
+        }
+        pixels.push(255);
+    }
+
+    PreviewImage { width, height, pixels }
+}
+
+/// Faces in +X, -X, +Y, -Y, +Z, -Z order, each laid out as expected by the usual graphics APIs
+/// for cubemap uploads (rows top to bottom, texture u to the right).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cubemap {
+    pub resolution: usize,
+    pub faces: Vec<PreviewImage>,
+}
+
+// View direction and the world direction at the top of each face
+const CUBEMAP_FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+];
+
+impl PackedGaussians {
+    /// Renders a reflection probe cubemap from `position` with the preview renderer
+    pub fn bake_cubemap(&self, position: [f32; 3], resolution: usize) -> Cubemap {
+        self.bake_cubemap_with(position, resolution, &PreviewOptions::default())
+    }
+
+    /// As [`PackedGaussians::bake_cubemap`] but with control over the background, fog and
+    /// depth fade. The width and height in `options` are ignored.
+    pub fn bake_cubemap_with(&self, position: [f32; 3], resolution: usize, options: &PreviewOptions) -> Cubemap {
+        let options = PreviewOptions {
+            width: resolution,
+            height: resolution,
+            ..options.clone()
+        };
+
+        let faces = CUBEMAP_FACES.iter().map(|(direction, up)| {
+            let mut camera = PreviewCamera::look_at(position, [
+                position[0] + direction[0],
+                position[1] + direction[1],
+                position[2] + direction[2],
+            ], *up);
+            camera.fov_y = std::f32::consts::FRAC_PI_2;
+
+            // Cubemap faces are indexed as seen from outside the cube, so the view from the
+            // center needs mirroring horizontally
+            let mut image = render_preview(self, &camera, &options);
+            for row in image.pixels.chunks_exact_mut(resolution.max(1) * 4) {
+                let pixels: Vec<[u8; 4]> = row.chunks_exact(4).rev().map(|p| [p[0], p[1], p[2], p[3]]).collect();
+                row.copy_from_slice(pixels.as_flattened());
+            }
+            image
+        }).collect();
+
+        Cubemap { resolution, faces }
+    }
+}
@@ -0,0 +1,122 @@
+// Irradiance volume baking, so virtual objects composited into a captured scene can be lit
+// consistently with it. Each splat is treated as a small emitter whose radiance towards a probe
+// comes from its view dependent SH color, weighted by its opacity and the solid angle it
+// subtends. The incoming radiance at each probe is projected onto first order SH and convolved
+// with a cosine lobe to give irradiance.
+//
+// Occlusion between splats isn't traced. Instead, when the splats around a probe would cover
+// more than the full sphere their contributions are scaled down to cover it exactly, which keeps
+// probes inside dense geometry from being wildly over bright.
+
+use std::f32::consts::PI;
+
+use crate::math;
+use crate::sh::{self, SH_C0, SH_C1};
+use crate::PackedGaussians;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProbeGrid {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    /// Number of probes along x, y and z
+    pub counts: [usize; 3],
+}
+
+impl ProbeGrid {
+    pub fn num_probes(&self) -> usize {
+        self.counts[0] * self.counts[1] * self.counts[2]
+    }
+
+    /// Probes are ordered with x varying fastest, then y, then z, and span the grid bounds
+    /// inclusively. An axis with a single probe puts it in the middle.
+    pub fn probe_position(&self, index: usize) -> [f32; 3] {
+        let coords = [
+            index % self.counts[0],
+            (index / self.counts[0]) % self.counts[1],
+            index / (self.counts[0] * self.counts[1]),
+        ];
+        [0, 1, 2].map(|j| {
+            if self.counts[j] > 1 {
+                self.min[j] + (self.max[j] - self.min[j]) * coords[j] as f32 / (self.counts[j] - 1) as f32
+            } else {
+                0.5 * (self.min[j] + self.max[j])
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IrradianceVolume {
+    pub grid: ProbeGrid,
+    /// First order SH irradiance per probe, with the cosine convolution already applied, as
+    /// RGB triples for the (0, 0), (1, -1), (1, 0) and (1, 1) basis functions
+    pub coefficients: Vec<[[f32; 3]; 4]>,
+}
+
+impl IrradianceVolume {
+    /// Irradiance arriving at a surface with the given unit normal, placed at a probe
+    pub fn irradiance(&self, probe: usize, normal: [f32; 3]) -> [f32; 3] {
+        let c = &self.coefficients[probe];
+        let basis = [SH_C0, -SH_C1 * normal[1], SH_C1 * normal[2], -SH_C1 * normal[0]];
+        [0, 1, 2].map(|k| (0..4).map(|j| c[j][k] * basis[j]).sum::<f32>().max(0.0))
+    }
+}
+
+// Cosine lobe convolution factors for bands 0 and 1
+const COSINE_BAND0: f32 = PI;
+const COSINE_BAND1: f32 = 2.0 * PI / 3.0;
+
+impl PackedGaussians {
+    pub fn bake_irradiance_probes(&self, grid: &ProbeGrid) -> IrradianceVolume {
+        let num_probes = grid.num_probes();
+        let positions: Vec<[f32; 3]> = (0..num_probes).map(|i| grid.probe_position(i)).collect();
+        let mut radiance = vec![[[0.0f32; 3]; 4]; num_probes];
+        let mut coverage = vec![0.0f32; num_probes];
+
+        for i in 0..self.num_points {
+            let g = self.unpack(i);
+            let opacity = self.alphas[i] as f32 / 255.0;
+            if opacity <= 0.0 {
+                continue;
+            }
+            let s = g.scale.map(f32::exp);
+            let area = PI * (s[0] * s[1] * s[2]).powf(2.0 / 3.0);
+
+            for (p, probe) in positions.iter().enumerate() {
+                let d = math::sub(g.position, *probe);
+                let distance2 = math::dot(d, d);
+                if distance2 <= 0.0 {
+                    continue;
+                }
+                // Splats right on top of a probe can't cover more than a hemisphere
+                let solid_angle = (opacity * area / distance2).min(2.0 * PI);
+                let direction = math::normalize(d);
+                let rgb = sh::evaluate_color(&g, self.sh_degree, direction).map(|c| c.max(0.0));
+
+                // Light arrives at the probe travelling opposite to the direction towards the
+                // splat, but it's projected onto the direction it comes from
+                let basis = [SH_C0, -SH_C1 * direction[1], SH_C1 * direction[2], -SH_C1 * direction[0]];
+                for (j, b) in basis.iter().enumerate() {
+                    for k in 0..3 {
+                        radiance[p][j][k] += rgb[k] * b * solid_angle;
+                    }
+                }
+                coverage[p] += solid_angle;
+            }
+        }
+
+        let coefficients = radiance.iter().zip(&coverage).map(|(r, &covered)| {
+            let normalization = if covered > 4.0 * PI { 4.0 * PI / covered } else { 1.0 };
+            let mut c = *r;
+            for (j, band) in c.iter_mut().enumerate() {
+                let convolution = if j == 0 { COSINE_BAND0 } else { COSINE_BAND1 };
+                for v in band.iter_mut() {
+                    *v *= normalization * convolution;
+                }
+            }
+            c
+        }).collect();
+
+        IrradianceVolume { grid: *grid, coefficients }
+    }
+}
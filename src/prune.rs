@@ -0,0 +1,78 @@
+// Removal of unwanted splats, compacting the cloud in place. The pruners test the packed bytes
+// directly so trimming a large cloud doesn't decode anything it doesn't need to, while `retain`
+// hands each splat to the caller fully decoded. Both see splats in the cloud's own axis
+// convention, so a box given to `prune_outside` means the same as one tested in `retain`.
+
+use crate::codec::unquantize_scale;
+use crate::{PackedGaussians, UnpackOptions, UnpackedGaussian};
+
+impl PackedGaussians {
+    /// Keeps the splats whose index passes the test, returning the number removed
+    pub(crate) fn retain_indices(&mut self, mut keep: impl FnMut(usize) -> bool) -> usize {
+        let kept: Vec<usize> = (0..self.num_points).filter(|&i| keep(i)).collect();
+        let removed = self.num_points - kept.len();
+        if removed > 0 {
+            *self = self.select(&kept);
+        }
+        removed
+    }
+
+    /// Keeps the splats for which `keep` returns true, in order, returning the number removed.
+    /// Splats are decoded in the cloud's `coordinate_system` without converting them.
+    pub fn retain(&mut self, mut keep: impl FnMut(&UnpackedGaussian) -> bool) -> usize {
+        let view = self.view();
+        let options = UnpackOptions::default().from(self.coordinate_system).to(self.coordinate_system);
+        let flags: Vec<bool> = (0..self.num_points).map(|i| keep(&view.unpack_with(i, &options))).collect();
+        self.retain_indices(|i| flags[i])
+    }
+
+    /// Removes splats with an opacity, between 0 and 1, at or below `max_alpha`
+    pub fn prune_transparent(&mut self, max_alpha: f32) -> usize {
+        // Alpha bytes are the opacity after the sigmoid, scaled to 0 to 255
+        let keep: Vec<bool> = self.alphas.iter().map(|&a| a as f32 / 255.0 > max_alpha).collect();
+        self.retain_indices(|i| keep[i])
+    }
+
+    /// Removes splats whose largest axis, in world units, is longer than `max_scale`
+    pub fn prune_large(&mut self, max_scale: f32) -> usize {
+        let max_log_scale = max_scale.ln();
+        let keep: Vec<bool> = self.scales.chunks_exact(3)
+            .map(|scale| scale.iter().all(|&s| unquantize_scale(s) <= max_log_scale))
+            .collect();
+        self.retain_indices(|i| keep[i])
+    }
+
+    /// Removes splats whose centers are outside the axis aligned box from `min` to `max`, in the
+    /// cloud's `coordinate_system`
+    pub fn prune_outside(&mut self, min: [f32; 3], max: [f32; 3]) -> usize {
+        let keep: Vec<bool> = (0..self.num_points)
+            .map(|i| {
+                let p = self.unpack_position(i);
+                (0..3).all(|j| p[j] >= min[j] && p[j] <= max[j])
+            })
+            .collect();
+        self.retain_indices(|i| keep[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coordinates::CoordinateSystem;
+    use crate::testing::random_cloud;
+
+    #[test]
+    fn retain_and_prune_outside_use_the_same_frame() {
+        let mut cloud = random_cloud(200, 0, 7);
+        cloud.coordinate_system = CoordinateSystem::RDF;
+        let (min, max) = ([-4096.0, 0.0, -4096.0], [4096.0, 4096.0, 4096.0]);
+
+        let mut pruned = cloud.clone();
+        let mut retained = cloud.clone();
+        let removed = pruned.prune_outside(min, max);
+        let expected = retained.retain(|g| (0..3).all(|j| g.position[j] >= min[j] && g.position[j] <= max[j]));
+        assert!(removed > 0 && removed < 200);
+        assert_eq!(removed, expected);
+        assert_eq!(pruned.positions, retained.positions);
+        assert_eq!(cloud.compute_bounds().unwrap().0[1], (0..200).map(|i| cloud.unpack_position(i)[1]).fold(f32::INFINITY, f32::min));
+    }
+}
@@ -0,0 +1,196 @@
+// End to end publishing of captures as a web ready dataset. Each capture is cleaned, turned Y up
+// if it looks like it was exported Z up, split into square tiles on the ground plane and written
+// out at several levels of detail, with a manifest listing everything for the viewer.
+//
+// Coarser levels keep a random subset of the splats, with the same seed every run so outputs
+// can be cached, and are blurred so that the thinned out splats blend into each other.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::diagnostics::Fix;
+use crate::events::EventLog;
+use crate::manifest::{save_manifest_to_file, ManifestAsset, ManifestLod, ManifestTile, SceneManifest};
+use crate::ply::{has_ply_extension, load_gaussians_from_ply_file};
+use crate::rng::SplitMix64;
+use crate::{load_packed_gaussians_from_file, save_packed_gaussians_to_file, PackedGaussians};
+
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PublishOptions {
+    /// Edge length of the square tiles on the ground plane, in world units
+    pub tile_size: f32,
+    /// Number of levels of detail, each keeping half the splats of the one before
+    pub lod_count: usize,
+    /// Viewing distance up to which the finest level is used. Each coarser level covers twice
+    /// the distance of the one before, and the coarsest covers everything beyond.
+    pub lod_distance: f32,
+    /// Blur added at the first coarser level, doubling at each level after
+    pub lod_blur: f32,
+    pub clean: bool,
+    pub align_up_axis: bool,
+    pub seed: u64,
+}
+
+impl Default for PublishOptions {
+    fn default() -> PublishOptions {
+        PublishOptions {
+            tile_size: 10.0,
+            lod_count: 3,
+            lod_distance: 20.0,
+            lod_blur: 0.01,
+            clean: true,
+            align_up_axis: true,
+            seed: 0,
+        }
+    }
+}
+
+fn log_operation(log: &mut Option<&mut EventLog>, name: &str, affected: usize) -> Result<(), io::Error> {
+    match log {
+        Some(log) => log.operation_applied(name, affected),
+        None => Ok(()),
+    }
+}
+
+/// Groups splats by the tile of the ground plane they fall in
+fn tile_indices(cloud: &PackedGaussians, tile_size: f32) -> BTreeMap<(i32, i32), Vec<usize>> {
+    let mut tiles: BTreeMap<(i32, i32), Vec<usize>> = BTreeMap::new();
+    for i in 0..cloud.num_points {
+        let p = cloud.unpack_position(i);
+        let key = ((p[0] / tile_size).floor() as i32, (p[2] / tile_size).floor() as i32);
+        tiles.entry(key).or_default().push(i);
+    }
+    tiles
+}
+
+/// Publishes one cloud as an asset, writing its tiles under `output_dir/name`
+pub fn publish_asset(
+    mut cloud: PackedGaussians,
+    name: &str,
+    output_dir: &str,
+    options: &PublishOptions,
+    mut log: Option<&mut EventLog>,
+) -> Result<ManifestAsset, io::Error> {
+    if options.clean {
+        for fix in [Fix::ResetZeroRotations, Fix::RemoveTransparent, Fix::RemoveFloaters, Fix::ClampNeedles] {
+            let affected = cloud.apply_fix(fix);
+            log_operation(&mut log, fix.flag().trim_start_matches("--"), affected)?;
+        }
+    }
+    if options.align_up_axis && cloud.looks_z_up() {
+        cloud.convert_z_up_to_y_up();
+        log_operation(&mut log, "z-up-to-y-up", cloud.num_points)?;
+    }
+
+    let mut asset = ManifestAsset::new(name);
+    let mut rng = SplitMix64::new(options.seed);
+    let mut level = cloud;
+    for lod in 0..options.lod_count.max(1) {
+        if lod > 0 {
+            level = level.random_sample(level.num_points / 2, &mut rng);
+            let affected = level.blur(options.lod_blur * 2f32.powi(lod as i32 - 1));
+            log_operation(&mut log, "blur", affected)?;
+        }
+
+        let lod_dir = format!("{}/lod{}", name, lod);
+        fs::create_dir_all(Path::new(output_dir).join(&lod_dir))?;
+        let mut tiles = Vec::new();
+        for ((x, z), indices) in tile_indices(&level, options.tile_size) {
+            let tile = level.select(&indices);
+            let uri = format!("{}/tile_{}_{}.spz", lod_dir, x, z);
+            let path = Path::new(output_dir).join(&uri).to_string_lossy().into_owned();
+            match log.as_deref_mut() {
+                Some(log) => log.save_file(&tile, &path)?,
+                None => save_packed_gaussians_to_file(&tile, &path)?,
+            }
+            let (min, max) = tile.compute_bounds().unwrap_or_default();
+            tiles.push(ManifestTile { uri, num_points: tile.num_points, min, max });
+        }
+
+        let max_distance = if lod + 1 == options.lod_count.max(1) {
+            f32::INFINITY
+        } else {
+            options.lod_distance * 2f32.powi(lod as i32)
+        };
+        asset.lods.push(ManifestLod { max_distance, tiles });
+    }
+    Ok(asset)
+}
+
+/// Publishes every .spz and .ply capture in `input_dir` to `output_dir`, naming each asset after its
+/// file, and writes the manifest alongside the tiles
+pub fn publish_folder(
+    input_dir: &str,
+    output_dir: &str,
+    options: &PublishOptions,
+    mut log: Option<&mut EventLog>,
+) -> Result<SceneManifest, io::Error> {
+    let mut inputs: Vec<_> = fs::read_dir(input_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "spz") || has_ply_extension(&path.to_string_lossy()))
+        .collect();
+    inputs.sort();
+
+    let mut manifest = SceneManifest::default();
+    for path in inputs {
+        let filename = path.to_string_lossy().into_owned();
+        let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let cloud = match log.as_deref_mut() {
+            Some(log) => log.load_file(&filename)?,
+            None if has_ply_extension(&filename) => load_gaussians_from_ply_file(&filename)?,
+            None => load_packed_gaussians_from_file(&filename)?,
+        };
+        manifest.assets.push(publish_asset(cloud, &name, output_dir, options, log.as_deref_mut())?);
+    }
+
+    fs::create_dir_all(output_dir)?;
+    let manifest_path = Path::new(output_dir).join(MANIFEST_FILENAME);
+    save_manifest_to_file(&manifest, &manifest_path.to_string_lossy())?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::load_manifest_from_file;
+    use crate::testing::random_cloud;
+
+    #[test]
+    fn publishing_a_folder_writes_every_tile_and_the_manifest() {
+        let root = std::env::temp_dir().join(format!("spz_rs_publish_{}", std::process::id()));
+        let (input_dir, output_dir) = (root.join("captures"), root.join("site"));
+        fs::create_dir_all(&input_dir).unwrap();
+        let mut cloud = random_cloud(400, 0, 60);
+        cloud.coordinate_system = crate::coordinates::CoordinateSystem::RUB;
+        save_packed_gaussians_to_file(&cloud, &input_dir.join("garden.spz").to_string_lossy()).unwrap();
+        fs::write(input_dir.join("notes.txt"), "not a capture").unwrap();
+
+        let options = PublishOptions { tile_size: 2048.0, lod_count: 2, clean: false, align_up_axis: false, ..Default::default() };
+        let published = publish_folder(&input_dir.to_string_lossy(), &output_dir.to_string_lossy(), &options, None);
+        let written = load_manifest_from_file(&output_dir.join(MANIFEST_FILENAME).to_string_lossy());
+        let tile_counts: Vec<Vec<usize>> = published.as_ref().map(|manifest| manifest.assets[0].lods.iter().map(|lod| {
+            lod.tiles.iter().map(|tile| load_packed_gaussians_from_file(&output_dir.join(&tile.uri).to_string_lossy())
+                .map_or(0, |tile| tile.num_points)).collect()
+        }).collect()).unwrap_or_default();
+        fs::remove_dir_all(&root).unwrap();
+
+        let manifest = published.unwrap();
+        assert_eq!(written.unwrap(), manifest);
+        assert_eq!(manifest.assets.len(), 1);
+        let asset = &manifest.assets[0];
+        assert_eq!(asset.name, "garden");
+        assert_eq!(asset.lods.len(), 2);
+        assert_eq!(asset.lods[0].max_distance, options.lod_distance);
+        assert_eq!(asset.lods[1].max_distance, f32::INFINITY);
+        for (lod, (expected, counts)) in asset.lods.iter().zip([400, 200].into_iter().zip(&tile_counts)) {
+            // Positions lie within 2048 units of the origin, so one tile per quadrant
+            assert_eq!(lod.tiles.len(), 4);
+            assert_eq!(lod.tiles.iter().map(|tile| tile.num_points).sum::<usize>(), expected);
+            assert_eq!(&lod.tiles.iter().map(|tile| tile.num_points).collect::<Vec<_>>(), counts);
+        }
+    }
+}
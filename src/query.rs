@@ -0,0 +1,239 @@
+// Lazy query layer over a packed cloud. Filters are evaluated directly against the packed bytes,
+// decoding only the fields they reference, and the selected attributes are only unquantized for
+// splats which pass every filter.
+//
+//     let result = cloud.query()
+//         .filter(Field::Alpha.gt(0.1))
+//         .select(&[Attribute::Position, Attribute::Color])
+//         .collect();
+
+use crate::codec::unquantize_sh;
+use crate::{dim_for_degree, PackedGaussians};
+
+/// Scalar values that filters can be expressed over
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    PositionX,
+    PositionY,
+    PositionZ,
+    ScaleX,
+    ScaleY,
+    ScaleZ,
+    /// Largest of the three log scales
+    MaxScale,
+    ColorR,
+    ColorG,
+    ColorB,
+    /// Opacity after the sigmoid, in the range 0 to 1
+    Alpha,
+}
+
+/// Attribute groups that can be selected for output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attribute {
+    Position,
+    Rotation,
+    Scale,
+    Color,
+    Alpha,
+    Sh,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predicate {
+    Gt(Field, f32),
+    Ge(Field, f32),
+    Lt(Field, f32),
+    Le(Field, f32),
+    /// Inclusive range
+    Between(Field, f32, f32),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Field {
+    pub fn gt(self, value: f32) -> Predicate {
+        Predicate::Gt(self, value)
+    }
+
+    pub fn ge(self, value: f32) -> Predicate {
+        Predicate::Ge(self, value)
+    }
+
+    pub fn lt(self, value: f32) -> Predicate {
+        Predicate::Lt(self, value)
+    }
+
+    pub fn le(self, value: f32) -> Predicate {
+        Predicate::Le(self, value)
+    }
+
+    pub fn between(self, min: f32, max: f32) -> Predicate {
+        Predicate::Between(self, min, max)
+    }
+
+    fn evaluate(self, cloud: &PackedGaussians, i: usize) -> f32 {
+        match self {
+            Field::PositionX => cloud.unpack_position(i)[0],
+            Field::PositionY => cloud.unpack_position(i)[1],
+            Field::PositionZ => cloud.unpack_position(i)[2],
+            Field::ScaleX => cloud.unpack_scale(i)[0],
+            Field::ScaleY => cloud.unpack_scale(i)[1],
+            Field::ScaleZ => cloud.unpack_scale(i)[2],
+            Field::MaxScale => {
+                let s = cloud.unpack_scale(i);
+                s[0].max(s[1]).max(s[2])
+            }
+            Field::ColorR => cloud.unpack_color(i)[0],
+            Field::ColorG => cloud.unpack_color(i)[1],
+            Field::ColorB => cloud.unpack_color(i)[2],
+            // Alphas are stored as quantized opacities so there's no need to round trip through
+            // the inverse sigmoid
+            Field::Alpha => cloud.alphas[i] as f32 / 255.0,
+        }
+    }
+}
+
+impl Predicate {
+    pub fn and(self, other: Predicate) -> Predicate {
+        Predicate::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Predicate) -> Predicate {
+        Predicate::Or(Box::new(self), Box::new(other))
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Predicate {
+        Predicate::Not(Box::new(self))
+    }
+
+    fn matches(&self, cloud: &PackedGaussians, i: usize) -> bool {
+        match self {
+            Predicate::Gt(f, v) => f.evaluate(cloud, i) > *v,
+            Predicate::Ge(f, v) => f.evaluate(cloud, i) >= *v,
+            Predicate::Lt(f, v) => f.evaluate(cloud, i) < *v,
+            Predicate::Le(f, v) => f.evaluate(cloud, i) <= *v,
+            Predicate::Between(f, min, max) => {
+                let x = f.evaluate(cloud, i);
+                x >= *min && x <= *max
+            }
+            Predicate::And(a, b) => a.matches(cloud, i) && b.matches(cloud, i),
+            Predicate::Or(a, b) => a.matches(cloud, i) || b.matches(cloud, i),
+            Predicate::Not(p) => !p.matches(cloud, i),
+        }
+    }
+}
+
+/// Output of [`Query::collect`]. Attributes which weren't selected are left empty, the others
+/// are tightly packed with one entry per matching splat, in the same order as `indices`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryResult {
+    pub indices: Vec<usize>,
+    pub positions: Vec<f32>,
+    /// Quaternions stored as w, x, y, z
+    pub rotations: Vec<f32>,
+    pub scales: Vec<f32>,
+    pub colors: Vec<f32>,
+    /// Alphas before the sigmoid, matching [`crate::UnpackedGaussian::alpha`]
+    pub alphas: Vec<f32>,
+    /// `sh_dim` coefficients per splat, each stored as r, g, b
+    pub sh: Vec<f32>,
+    pub sh_dim: usize,
+}
+
+impl QueryResult {
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+pub struct Query<'a> {
+    cloud: &'a PackedGaussians,
+    filters: Vec<Predicate>,
+    selection: Vec<Attribute>,
+}
+
+impl<'a> Query<'a> {
+    pub fn new(cloud: &'a PackedGaussians) -> Query<'a> {
+        Query {
+            cloud,
+            filters: Vec::new(),
+            selection: Vec::new(),
+        }
+    }
+
+    /// Adds a filter. Multiple filters must all match for a splat to be kept.
+    pub fn filter(mut self, predicate: Predicate) -> Query<'a> {
+        self.filters.push(predicate);
+        self
+    }
+
+    /// Chooses the attributes decoded by [`Query::collect`]. Nothing is decoded if this isn't
+    /// called, which is useful when only the matching indices are needed.
+    pub fn select(mut self, attributes: &[Attribute]) -> Query<'a> {
+        self.selection = attributes.to_vec();
+        self
+    }
+
+    fn is_selected(&self, attribute: Attribute) -> bool {
+        self.selection.contains(&attribute)
+    }
+
+    pub fn indices(&self) -> Vec<usize> {
+        (0..self.cloud.num_points)
+            .filter(|&i| self.filters.iter().all(|p| p.matches(self.cloud, i)))
+            .collect()
+    }
+
+    pub fn count(&self) -> usize {
+        (0..self.cloud.num_points)
+            .filter(|&i| self.filters.iter().all(|p| p.matches(self.cloud, i)))
+            .count()
+    }
+
+    pub fn collect(self) -> QueryResult {
+        let cloud = self.cloud;
+        let sh_dim = dim_for_degree(cloud.sh_degree);
+        let mut result = QueryResult {
+            indices: self.indices(),
+            sh_dim,
+            ..Default::default()
+        };
+
+        for &i in &result.indices {
+            if self.is_selected(Attribute::Position) {
+                result.positions.extend_from_slice(&cloud.unpack_position(i));
+            }
+            if self.is_selected(Attribute::Rotation) {
+                result.rotations.extend_from_slice(&cloud.unpack_rotation(i));
+            }
+            if self.is_selected(Attribute::Scale) {
+                result.scales.extend_from_slice(&cloud.unpack_scale(i));
+            }
+            if self.is_selected(Attribute::Color) {
+                result.colors.extend_from_slice(&cloud.unpack_color(i));
+            }
+            if self.is_selected(Attribute::Alpha) {
+                result.alphas.push(cloud.unpack_alpha(i));
+            }
+            if self.is_selected(Attribute::Sh) {
+                let sh_start = i * sh_dim * 3;
+                result.sh.extend(cloud.sh[sh_start..sh_start + sh_dim * 3].iter().map(|&x| unquantize_sh(x)));
+            }
+        }
+
+        result
+    }
+}
+
+impl PackedGaussians {
+    pub fn query(&self) -> Query<'_> {
+        Query::new(self)
+    }
+}
@@ -0,0 +1,132 @@
+// Repairs for common training artifacts, applied in place to the packed data
+
+use std::io;
+
+use crate::codec::{encode_quat3, SCALE_STEPS_PER_UNIT};
+use crate::PackedGaussians;
+
+/// How to handle splats whose rotation bytes are all zero
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZeroRotationPolicy {
+    /// Leaves them as they are. The count is still returned, so callers can warn about them.
+    #[default]
+    Keep,
+    /// Replaces them by the identity rotation
+    Identity,
+    /// Fails if there are any
+    Error,
+}
+
+impl PackedGaussians {
+    /// Largest of the three axis lengths divided by the smallest
+    pub fn anisotropy(&self, i: usize) -> f32 {
+        let s = &self.scales[3 * i..3 * i + 3];
+        let steps = s.iter().max().unwrap() - s.iter().min().unwrap();
+        (steps as f32 / SCALE_STEPS_PER_UNIT).exp()
+    }
+
+    /// Number of splats whose anisotropy exceeds `max_ratio`
+    pub fn count_anisotropic(&self, max_ratio: f32) -> usize {
+        let limit = anisotropy_limit_steps(max_ratio);
+        self.scales.chunks_exact(3)
+            .filter(|s| s.iter().max().unwrap() - s.iter().min().unwrap() > limit)
+            .count()
+    }
+
+    /// Number of splats whose rotation bytes are all zero. These don't decode to a valid
+    /// rotation and usually come from exporters which didn't write rotations at all.
+    pub fn count_zero_rotations(&self) -> usize {
+        self.rotations.chunks_exact(3).filter(|r| *r == [0, 0, 0]).count()
+    }
+
+    /// Applies a policy to splats with all zero rotation bytes, returning how many were found
+    pub fn handle_zero_rotations(&mut self, policy: ZeroRotationPolicy) -> Result<usize, io::Error> {
+        let count = self.count_zero_rotations();
+        if count == 0 {
+            return Ok(0);
+        }
+        match policy {
+            ZeroRotationPolicy::Keep => {}
+            ZeroRotationPolicy::Identity => {
+                let identity = encode_quat3([1.0, 0.0, 0.0, 0.0]);
+                for r in self.rotations.chunks_exact_mut(3).filter(|r| *r == [0, 0, 0]) {
+                    r.copy_from_slice(&identity);
+                }
+            }
+            ZeroRotationPolicy::Error => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                    format!("{} splats have all zero rotation bytes", count)));
+            }
+        }
+        Ok(count)
+    }
+
+    /// Shrinks the long axes of needle-like splats so that no axis is more than `max_ratio`
+    /// times the length of the shortest, returning the number of splats changed. Needles are a
+    /// frequent training artifact and cause shimmering when the view moves.
+    pub fn clamp_anisotropy(&mut self, max_ratio: f32) -> usize {
+        let limit = anisotropy_limit_steps(max_ratio);
+        let mut affected = 0;
+        for s in self.scales.chunks_exact_mut(3) {
+            let min = *s.iter().min().unwrap();
+            let max_allowed = min.saturating_add(limit);
+            if s.iter().any(|&v| v > max_allowed) {
+                for v in s.iter_mut() {
+                    *v = (*v).min(max_allowed);
+                }
+                affected += 1;
+            }
+        }
+        affected
+    }
+}
+
+// Works in quantized steps, rounding down, so clamped splats are guaranteed to be within the
+// requested ratio once decoded
+fn anisotropy_limit_steps(max_ratio: f32) -> u8 {
+    let steps = (max_ratio.max(1.0).ln() * SCALE_STEPS_PER_UNIT).floor();
+    steps.min(u8::MAX as f32) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+
+    fn with_zero_rotations() -> PackedGaussians {
+        let mut cloud = random_cloud(20, 0, 8);
+        for i in [2, 5, 11] {
+            cloud.rotations[i * 3..i * 3 + 3].fill(0);
+        }
+        cloud
+    }
+
+    #[test]
+    fn keep_leaves_zero_rotations_and_counts_them() {
+        let mut cloud = with_zero_rotations();
+        let rotations = cloud.rotations.clone();
+        assert_eq!(cloud.handle_zero_rotations(ZeroRotationPolicy::Keep).unwrap(), 3);
+        assert_eq!(cloud.rotations, rotations);
+    }
+
+    #[test]
+    fn identity_replaces_only_zero_rotations() {
+        let mut cloud = with_zero_rotations();
+        let rotations = cloud.rotations.clone();
+        assert_eq!(cloud.handle_zero_rotations(ZeroRotationPolicy::Identity).unwrap(), 3);
+        assert_eq!(cloud.count_zero_rotations(), 0);
+        for i in 0..cloud.num_points {
+            let expected = if [2, 5, 11].contains(&i) { encode_quat3([1.0, 0.0, 0.0, 0.0]) } else { [0, 1, 2].map(|j| rotations[i * 3 + j]) };
+            assert_eq!(cloud.rotations[i * 3..i * 3 + 3], expected, "{}", i);
+        }
+    }
+
+    #[test]
+    fn error_fails_only_if_there_are_zero_rotations() {
+        let mut cloud = with_zero_rotations();
+        let error = cloud.handle_zero_rotations(ZeroRotationPolicy::Error).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        cloud.handle_zero_rotations(ZeroRotationPolicy::Identity).unwrap();
+        assert_eq!(cloud.handle_zero_rotations(ZeroRotationPolicy::Error).unwrap(), 0);
+    }
+}
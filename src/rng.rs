@@ -0,0 +1,70 @@
+// Seedable random numbers for the stochastic operations in the crate. Every such operation takes
+// a RandomSource rather than drawing from a global generator, so a pipeline given the same seed
+// produces the same output, which is needed for caching results. Other generators, such as
+// those from the rand crate, can be used by implementing RandomSource on top of them.
+
+use crate::PackedGaussians;
+
+pub trait RandomSource {
+    fn next_u64(&mut self) -> u64;
+
+    /// Uniformly distributed in [0, 1)
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniformly distributed in [0, n). `n` must be non zero.
+    fn below(&mut self, n: usize) -> usize {
+        // Multiply and shift rather than taking a remainder, which avoids most of the bias
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+impl<R: RandomSource + ?Sized> RandomSource for &mut R {
+    fn next_u64(&mut self) -> u64 {
+        (**self).next_u64()
+    }
+}
+
+/// Small fast generator with a 64 bit state. Its output is fixed for a given seed across
+/// platforms and releases of the crate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+}
+
+impl RandomSource for SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl PackedGaussians {
+    /// Picks `count` splats uniformly at random, keeping them in their original order. The whole
+    /// cloud is returned if it has no more than `count` splats.
+    pub fn random_sample<R: RandomSource>(&self, count: usize, rng: &mut R) -> PackedGaussians {
+        if count >= self.num_points {
+            return self.clone();
+        }
+
+        // Partial Fisher-Yates shuffle
+        let mut indices: Vec<usize> = (0..self.num_points).collect();
+        for i in 0..count {
+            let j = i + rng.below(self.num_points - i);
+            indices.swap(i, j);
+        }
+        indices.truncate(count);
+        indices.sort_unstable();
+        self.select(&indices)
+    }
+}
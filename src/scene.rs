@@ -0,0 +1,155 @@
+// Shared scene for applications showing the same cloud in several viewports. The cloud and
+// everything derived from it (bounds, per view depth orderings, GPU upload buffers) live behind a
+// reference counted handle, so each view reuses the work done by the others. Derived data is
+// computed lazily and thrown away whenever the cloud is modified through the scene.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard};
+
+use crate::PackedGaussians;
+
+/// Number of f32 values per splat in [`SharedScene::gpu_blob`]
+pub const GPU_BLOB_STRIDE: usize = 14;
+
+pub type ViewId = u64;
+
+struct SortedView {
+    camera_position: [f32; 3],
+    indices: Arc<Vec<u32>>,
+}
+
+#[derive(Default)]
+struct SceneCaches {
+    bounds: Option<Option<([f32; 3], [f32; 3])>>,
+    sorted_views: HashMap<ViewId, SortedView>,
+    gpu_blob: Option<Arc<Vec<f32>>>,
+}
+
+struct SceneInner {
+    cloud: RwLock<PackedGaussians>,
+    caches: Mutex<SceneCaches>,
+    generation: AtomicU64,
+}
+
+/// Cloning a `SharedScene` is cheap and yields another handle to the same scene
+#[derive(Clone)]
+pub struct SharedScene {
+    inner: Arc<SceneInner>,
+}
+
+impl SharedScene {
+    pub fn new(cloud: PackedGaussians) -> SharedScene {
+        SharedScene {
+            inner: Arc::new(SceneInner {
+                cloud: RwLock::new(cloud),
+                caches: Mutex::new(SceneCaches::default()),
+                generation: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    fn caches(&self) -> MutexGuard<'_, SceneCaches> {
+        self.inner.caches.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn cloud(&self) -> RwLockReadGuard<'_, PackedGaussians> {
+        self.inner.cloud.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Modifies the cloud and invalidates all derived data
+    pub fn modify<T>(&self, f: impl FnOnce(&mut PackedGaussians) -> T) -> T {
+        let mut cloud = self.inner.cloud.write().unwrap_or_else(|e| e.into_inner());
+        let result = f(&mut cloud);
+        self.invalidate();
+        result
+    }
+
+    /// Throws away all derived data. Only needed if the cloud has been changed by some means
+    /// other than [`SharedScene::modify`].
+    pub fn invalidate(&self) {
+        *self.caches() = SceneCaches::default();
+        self.inner.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Incremented every time the scene is invalidated, so that viewers holding their own
+    /// derived data (e.g. uploaded GPU buffers) can tell when to refresh it.
+    pub fn generation(&self) -> u64 {
+        self.inner.generation.load(Ordering::SeqCst)
+    }
+
+    pub fn num_handles(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+
+    /// Axis aligned bounds of the splat centers, or None for an empty cloud
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        if let Some(bounds) = self.caches().bounds {
+            return bounds;
+        }
+
+        // The read lock is held until the result is cached so that a concurrent modification
+        // can't be overwritten with stale data
+        let cloud = self.cloud();
+        let bounds = cloud.compute_bounds();
+        self.caches().bounds = Some(bounds);
+        bounds
+    }
+
+    /// Splat indices sorted back to front for the given view, as needed for alpha blending. The
+    /// ordering is cached per view and only recomputed when that view's camera moves or the scene
+    /// is modified.
+    pub fn sorted_indices(&self, view: ViewId, camera_position: [f32; 3]) -> Arc<Vec<u32>> {
+        if let Some(sorted) = self.caches().sorted_views.get(&view) {
+            if sorted.camera_position == camera_position {
+                return sorted.indices.clone();
+            }
+        }
+
+        let cloud = self.cloud();
+        let indices = {
+            let depths: Vec<f32> = (0..cloud.num_points).map(|i| {
+                let p = cloud.unpack_position(i);
+                let d = [p[0] - camera_position[0], p[1] - camera_position[1], p[2] - camera_position[2]];
+                d[0] * d[0] + d[1] * d[1] + d[2] * d[2]
+            }).collect();
+
+            let mut indices: Vec<u32> = (0..cloud.num_points as u32).collect();
+            indices.sort_by(|&a, &b| depths[b as usize].total_cmp(&depths[a as usize]));
+            Arc::new(indices)
+        };
+
+        self.caches().sorted_views.insert(view, SortedView { camera_position, indices: indices.clone() });
+        indices
+    }
+
+    /// Drops the cached ordering for a view which has been closed
+    pub fn remove_view(&self, view: ViewId) {
+        self.caches().sorted_views.remove(&view);
+    }
+
+    /// Interleaved decoded splat data ready for upload to a GPU vertex buffer. Each splat takes
+    /// [`GPU_BLOB_STRIDE`] floats laid out as position (3), rotation as w, x, y, z (4),
+    /// log scale (3), color (3) and alpha before the sigmoid (1).
+    pub fn gpu_blob(&self) -> Arc<Vec<f32>> {
+        if let Some(blob) = &self.caches().gpu_blob {
+            return blob.clone();
+        }
+
+        let cloud = self.cloud();
+        let blob = {
+            let mut blob = Vec::with_capacity(cloud.num_points * GPU_BLOB_STRIDE);
+            for i in 0..cloud.num_points {
+                blob.extend_from_slice(&cloud.unpack_position(i));
+                blob.extend_from_slice(&cloud.unpack_rotation(i));
+                blob.extend_from_slice(&cloud.unpack_scale(i));
+                blob.extend_from_slice(&cloud.unpack_color(i));
+                blob.push(cloud.unpack_alpha(i));
+            }
+            Arc::new(blob)
+        };
+
+        self.caches().gpu_blob = Some(blob.clone());
+        blob
+    }
+}
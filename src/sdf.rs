@@ -0,0 +1,134 @@
+// Approximate signed distance field built from a splat cloud, for engine effects such as soft
+// particles and collision queries around a captured scene. Each sufficiently opaque splat is
+// treated as a solid ellipsoid at SURFACE_SIGMAS standard deviations and the field is the
+// truncated distance to the union of those ellipsoids. Distances are approximated by scaling the
+// distance in the splat's normalized frame, which is exact along the splat's axes.
+
+use crate::math::{self, Vec3};
+use crate::PackedGaussians;
+
+const SURFACE_SIGMAS: f32 = 2.0;
+
+// Splats more transparent than this don't contribute a surface
+const MIN_OPACITY: f32 = 0.1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SdfVolume {
+    /// Number of voxels along x, y and z
+    pub resolution: [usize; 3],
+    /// World position of the center of voxel (0, 0, 0)
+    pub origin: [f32; 3],
+    pub voxel_size: f32,
+    /// Distances are clamped to the range -truncation to truncation
+    pub truncation: f32,
+    /// Signed distances with x varying fastest, then y, then z. Negative inside.
+    pub distances: Vec<f32>,
+}
+
+impl SdfVolume {
+    pub fn get(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.distances[(z * self.resolution[1] + y) * self.resolution[0] + x]
+    }
+
+    /// Raw little endian f32 texels in the same order as `distances`, ready to upload as an R32F
+    /// 3D texture.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.distances.iter().flat_map(|d| d.to_le_bytes()).collect()
+    }
+
+    /// Texels remapped from -truncation..truncation to 0..255, for an R8 3D texture
+    pub fn to_r8_bytes(&self) -> Vec<u8> {
+        let scale = if self.truncation > 0.0 { 127.5 / self.truncation } else { 0.0 };
+        self.distances.iter()
+            .map(|d| (d * scale + 127.5).round().clamp(0.0, 255.0) as u8)
+            .collect()
+    }
+}
+
+fn ellipsoid_distance(p: Vec3, center: Vec3, rotation: &math::Mat3, radii: Vec3) -> f32 {
+    let d = math::sub(p, center);
+    let world_length = math::length(d);
+    let local = math::mat3_transpose_mul_vec3(rotation, d);
+    let normalized_length = math::length([local[0] / radii[0], local[1] / radii[1], local[2] / radii[2]]);
+    if normalized_length <= 0.0 {
+        return -radii[0].min(radii[1]).min(radii[2]);
+    }
+    world_length * (1.0 - 1.0 / normalized_length)
+}
+
+impl PackedGaussians {
+    /// Builds a signed distance field with `resolution` voxels along the longest axis of the
+    /// cloud's bounds. The volume is padded by `truncation` on every side.
+    pub fn to_sdf(&self, resolution: usize, truncation: f32) -> SdfVolume {
+        let resolution = resolution.max(1);
+        let truncation = truncation.max(0.0);
+
+        let mut surfaces = Vec::new();
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for i in 0..self.num_points {
+            if (self.alphas[i] as f32) / 255.0 < MIN_OPACITY {
+                continue;
+            }
+
+            let center = self.unpack_position(i);
+            let radii = self.unpack_scale(i).map(|s| s.exp() * SURFACE_SIGMAS);
+            let extent = radii[0].max(radii[1]).max(radii[2]);
+            for j in 0..3 {
+                min[j] = min[j].min(center[j] - extent);
+                max[j] = max[j].max(center[j] + extent);
+            }
+            surfaces.push((center, math::quat_to_mat3(self.unpack_rotation(i)), radii, extent));
+        }
+
+        if surfaces.is_empty() {
+            return SdfVolume {
+                resolution: [1, 1, 1],
+                origin: [0.0; 3],
+                voxel_size: 1.0,
+                truncation,
+                distances: vec![truncation],
+            };
+        }
+
+        for j in 0..3 {
+            min[j] -= truncation;
+            max[j] += truncation;
+        }
+        let longest = (max[0] - min[0]).max(max[1] - min[1]).max(max[2] - min[2]).max(1e-6);
+        let voxel_size = longest / resolution as f32;
+        let dims = [0, 1, 2].map(|j| (((max[j] - min[j]) / voxel_size).ceil() as usize).max(1));
+        let origin = [0, 1, 2].map(|j| min[j] + 0.5 * voxel_size);
+        let mut distances = vec![truncation; dims[0] * dims[1] * dims[2]];
+
+        // Splat each surface into the voxels within reach of it rather than testing every voxel
+        // against every splat
+        for (center, rotation, radii, extent) in &surfaces {
+            let reach = extent + truncation;
+            let lo = [0, 1, 2].map(|j| (((center[j] - reach - origin[j]) / voxel_size).floor().max(0.0)) as usize);
+            let hi = [0, 1, 2].map(|j| (((center[j] + reach - origin[j]) / voxel_size).ceil().max(0.0) as usize).min(dims[j] - 1));
+            for z in lo[2]..=hi[2] {
+                for y in lo[1]..=hi[1] {
+                    for x in lo[0]..=hi[0] {
+                        let p = [
+                            origin[0] + x as f32 * voxel_size,
+                            origin[1] + y as f32 * voxel_size,
+                            origin[2] + z as f32 * voxel_size,
+                        ];
+                        let d = ellipsoid_distance(p, *center, rotation, *radii).clamp(-truncation, truncation);
+                        let texel = &mut distances[(z * dims[1] + y) * dims[0] + x];
+                        *texel = texel.min(d);
+                    }
+                }
+            }
+        }
+
+        SdfVolume {
+            resolution: dims,
+            origin,
+            voxel_size,
+            truncation,
+            distances,
+        }
+    }
+}
@@ -0,0 +1,235 @@
+// Runtime self test which decodes built in known vectors and compares the results with values
+// recorded on a reference little endian target. This lets embedders on unusual targets, such as
+// consoles or big endian machines, or builds using fast math style floating point options,
+// detect decode problems at startup rather than after shipping corrupted scenes.
+
+use std::io::Write;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::codec::{
+    decode_fixed24, decode_quat3, f32_to_half, half_to_f32, unquantize_alpha, unquantize_alpha_deterministic,
+    unquantize_color, unquantize_scale, unquantize_sh,
+};
+use crate::error::SpzError;
+use crate::incremental::SpzDecoder;
+use crate::view::PackedGaussiansView;
+use crate::{load_packed_gaussians_from_decompressed_buffer, load_packed_gaussians_from_decompressed_buffer_with, LoadOptions, PackedGaussiansHeader};
+
+// Relative tolerance for results which depend on the platform's exp and ln
+const LIBM_TOLERANCE: f32 = 1e-4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfTestFailure {
+    pub check: &'static str,
+    pub input: String,
+    pub expected: f32,
+    pub actual: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SelfTestReport {
+    pub checks: usize,
+    pub failures: Vec<SelfTestFailure>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    // Expected values are given as bits so they're exact
+    fn check(&mut self, check: &'static str, input: String, expected_bits: u32, actual: f32, tolerance: f32) {
+        self.checks += 1;
+        let expected = f32::from_bits(expected_bits);
+        let matches = if tolerance > 0.0 {
+            (actual - expected).abs() <= tolerance * expected.abs().max(1.0)
+        } else {
+            actual.to_bits() == expected_bits
+        };
+        if !matches {
+            self.failures.push(SelfTestFailure { check, input, expected, actual });
+        }
+    }
+
+    // Integers are compared exactly, and only converted to f32 for the report
+    fn check_u32(&mut self, check: &'static str, input: String, expected: u32, actual: u32) {
+        self.checks += 1;
+        if actual != expected {
+            self.failures.push(SelfTestFailure { check, input, expected: expected as f32, actual: actual as f32 });
+        }
+    }
+}
+
+// Headers with the fields they decode to, as magic, version, number of points, SH degree,
+// fractional bits, flags and the reserved byte
+pub(crate) const KNOWN_HEADERS: [([u8; 16], [u32; 7]); 3] = [
+    ([0x4e, 0x47, 0x53, 0x50, 0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00],
+        [0x5053474e, 2, 1, 0, 12, 0, 0]),
+    ([0x4e, 0x47, 0x53, 0x50, 0x01, 0x00, 0x00, 0x00, 0x56, 0x34, 0x12, 0x00, 0x03, 0x0a, 0x01, 0x00],
+        [0x5053474e, 1, 0x123456, 3, 10, 1, 0]),
+    ([0x50, 0x53, 0x47, 0x4e, 0x00, 0x00, 0x00, 0x02, 0xff, 0xff, 0xff, 0xff, 0x80, 0x17, 0x80, 0x7f],
+        [0x4e475350, 0x02000000, 0xffffffff, 0x80, 0x17, 0x80, 0x7f]),
+];
+
+// One splat file with a version 2 header, fractional bits 12 and SH degree 0, positioned at
+// (1, -1, 291.27)
+pub(crate) const KNOWN_FILE: [u8; 35] = [
+    0x4e, 0x47, 0x53, 0x50, 0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00,
+    0x00, 0x10, 0x00, 0x00, 0xf0, 0xff, 0x56, 0x34, 0x12,
+    0xfe,
+    0x00, 0x80, 0xff,
+    0x00, 0xa0, 0xff,
+    0x80, 0x80, 0x80,
+];
+
+// Two splat file with a version 1 header and float16 positions, positioned at (1, -2, 0.333)
+// and (65504, -0.5, 2^-24), the second of which catches a wrong stride between splats
+pub(crate) const KNOWN_V1_FILE: [u8; 48] = [
+    0x4e, 0x47, 0x53, 0x50, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x3c, 0x00, 0xc0, 0x55, 0x35, 0xff, 0x7b, 0x00, 0xb8, 0x01, 0x00,
+    0xfe, 0xfe,
+    0x80, 0x80, 0x80, 0x80, 0x80, 0x80,
+    0xa0, 0xa0, 0xa0, 0xa0, 0xa0, 0xa0,
+    0x80, 0x80, 0x80, 0x80, 0x80, 0x80,
+];
+
+// Header claiming the most splats a header can, at SH degree 3, with no data after it. Loaders
+// have to report the truncation without first allocating the hundreds of gigabytes it claims.
+pub(crate) const HOSTILE_HEADER: [u8; 16] = [0x4e, 0x47, 0x53, 0x50, 0x02, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0x03, 0x0c, 0x00, 0x00];
+
+pub(crate) const KNOWN_V1_POSITIONS: [[u32; 3]; 2] = [[0x3f800000, 0xc0000000, 0x3eaaa000], [0x477fe000, 0xbf000000, 0x33800000]];
+
+/// Decodes a set of known vectors and reports any results which differ from the reference
+pub fn self_test() -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+
+    for (bytes, expected) in [
+        ([0x00, 0x10, 0x00], 0x3f800000),
+        ([0x00, 0xf0, 0xff], 0xbf800000),
+        ([0x56, 0x34, 0x12], 0x4391a2b0),
+        ([0xff, 0xff, 0x7f], 0x44fffffe),
+    ] {
+        report.check("decode_fixed24", format!("{:?}", bytes), expected, decode_fixed24(&bytes, 12), 0.0);
+    }
+
+    for (h, expected) in [(0x3c00, 0x3f800000), (0xc000, 0xc0000000), (0x0001, 0x33800000), (0x7bff, 0x477fe000), (0x3555, 0x3eaaa000)] {
+        report.check("half_to_f32", format!("{:#06x}", h), expected, half_to_f32(h), 0.0);
+        report.check("f32_to_half", format!("{:#010x}", expected), (h as f32).to_bits(),
+            f32_to_half(f32::from_bits(expected)) as f32, 0.0);
+    }
+
+    for (bytes, expected) in [
+        ([128, 128, 128], [0x3f7ffe7d, 0x3b808100, 0x3b808100, 0x3b808100]),
+        ([255, 0, 128], [0x00000000, 0x3f800000, 0xbf800000, 0x3b808100]),
+        ([20, 200, 90], [0x00000000, 0xbf57d7d8, 0x3f119192, 0xbe969696]),
+    ] {
+        let q = decode_quat3(&bytes);
+        for j in 0..4 {
+            report.check("decode_quat3", format!("{:?}[{}]", bytes, j), expected[j], q[j], 0.0);
+        }
+    }
+
+    for (x, expected) in [(0, 0xc1200000), (160, 0x00000000), (255, 0x40be0000)] {
+        report.check("unquantize_scale", x.to_string(), expected, unquantize_scale(x), 0.0);
+    }
+    for (x, expected, expected_deterministic) in [(1, 0xc0b131d7, 0xc0b131d8), (128, 0x3c008127, 0x3c0080ac), (254, 0x40b131d8, 0x40b131d8)] {
+        report.check("unquantize_alpha", x.to_string(), expected, unquantize_alpha(x), LIBM_TOLERANCE);
+        report.check("unquantize_alpha_deterministic", x.to_string(), expected_deterministic,
+            unquantize_alpha_deterministic(x), 0.0);
+    }
+    for (x, expected_color, expected_sh) in [(0, 0xc0555555, 0xbf800000), (128, 0x3c562c55, 0x00000000), (255, 0x40555555, 0x3f7e0000)] {
+        report.check("unquantize_color", x.to_string(), expected_color, unquantize_color(x), 0.0);
+        report.check("unquantize_sh", x.to_string(), expected_sh, unquantize_sh(x), 0.0);
+    }
+
+    for (bytes, expected) in KNOWN_HEADERS {
+        let header = PackedGaussiansHeader::from_bytes(&bytes);
+        let fields = [header.magic, header.version, header.num_points, header.sh_degree as u32,
+            header.fractional_bits as u32, header.flags as u32, header.reserved as u32];
+        for (j, (actual, expected)) in fields.into_iter().zip(expected).enumerate() {
+            report.check_u32("header from_bytes", format!("{:02x?}[{}]", bytes, j), expected, actual);
+        }
+        for (j, (actual, expected)) in header.to_bytes().into_iter().zip(bytes).enumerate() {
+            report.check_u32("header to_bytes", format!("{:02x?}[{}]", bytes, j), expected as u32, actual as u32);
+        }
+    }
+
+    // A header written in the wrong byte order has to be rejected rather than misread
+    let rejected = matches!(load_packed_gaussians_from_decompressed_buffer(&KNOWN_HEADERS[2].0[..]), Err(SpzError::InvalidMagic));
+    report.check_u32("header byte order", format!("{:02x?}", KNOWN_HEADERS[2].0), 1, rejected as u32);
+
+    // Decoding a whole file also checks the header is read in the right byte order
+    match load_packed_gaussians_from_decompressed_buffer(&KNOWN_FILE[..]) {
+        Ok(gaussians) => {
+            report.check("header num_points", "known file".to_string(), 1.0f32.to_bits(), gaussians.num_points as f32, 0.0);
+            if gaussians.num_points == 1 {
+                let p = gaussians.unpack_position(0);
+                for (j, expected) in [0x3f800000, 0xbf800000, 0x4391a2b0].into_iter().enumerate() {
+                    report.check("position", format!("known file[{}]", j), expected, p[j], 0.0);
+                }
+            }
+        }
+        Err(_) => report.check("header num_points", "known file".to_string(), 1.0f32.to_bits(), f32::NAN, 0.0),
+    }
+
+    // Float16 positions go through each of the decode paths, which have to agree
+    match load_packed_gaussians_from_decompressed_buffer(&KNOWN_V1_FILE[..]) {
+        Ok(gaussians) => {
+            report.check("header num_points", "known v1 file".to_string(), 2.0f32.to_bits(), gaussians.num_points as f32, 0.0);
+            if gaussians.num_points == 2 {
+                let unpacked = gaussians.unpack_all();
+                for (i, expected) in KNOWN_V1_POSITIONS.into_iter().enumerate() {
+                    let decoded = [gaussians.unpack_position(i), gaussians.unpack(i).position, [0, 1, 2].map(|j| unpacked.positions[i * 3 + j])];
+                    for (path, p) in ["unpack_position", "unpack", "unpack_all"].into_iter().zip(decoded) {
+                        for j in 0..3 {
+                            report.check("float16 position", format!("known v1 file {}[{}][{}]", path, i, j), expected[j], p[j], 0.0);
+                        }
+                    }
+                }
+            }
+        }
+        Err(_) => report.check("header num_points", "known v1 file".to_string(), 2.0f32.to_bits(), f32::NAN, 0.0),
+    }
+
+    let truncated = |result: Result<(), SpzError>| matches!(result, Err(SpzError::TruncatedField { field: "positions", .. })) as u32;
+    let input = format!("{:02x?}", HOSTILE_HEADER);
+    let loaded = load_packed_gaussians_from_decompressed_buffer(&HOSTILE_HEADER[..]).map(|_| ());
+    report.check_u32("hostile header load", input.clone(), 1, truncated(loaded));
+    let viewed = PackedGaussiansView::from_decompressed_bytes(&HOSTILE_HEADER).map(|_| ());
+    report.check_u32("hostile header view", input.clone(), 1, truncated(viewed));
+    let mut decoder = SpzDecoder::new();
+    let pushed = decoder.push(&gzip(&HOSTILE_HEADER)).and_then(|()| decoder.finish()).map(|_| ());
+    report.check_u32("hostile header push", input.clone(), 1, truncated(pushed));
+    let limited = load_packed_gaussians_from_decompressed_buffer_with(&HOSTILE_HEADER[..], &LoadOptions::default().max_decompressed_bytes(1 << 20));
+    report.check_u32("hostile header limit", input, 1, matches!(limited, Err(SpzError::TooLarge { .. })) as u32);
+
+    // Fractional bits past 31 can't be a shift, and past 126 leave positions too small for f32
+    let mut file = KNOWN_FILE;
+    for (bits, expected) in [(40, 0x3591a2b0), (255, 0)] {
+        file[13] = bits;
+        let z = load_packed_gaussians_from_decompressed_buffer(&file[..]).map_or(f32::NAN, |gaussians| gaussians.unpack_position(0)[2]);
+        report.check("fractional bits", format!("known file with {} bits", bits), expected, z, 0.0);
+    }
+
+    report
+}
+
+pub(crate) fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    // Writing to a Vec can't fail
+    let _ = encoder.write_all(bytes);
+    encoder.finish().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn self_test_passes() {
+        let report = self_test();
+        assert!(report.passed(), "{:?}", report.failures);
+    }
+}
@@ -0,0 +1,248 @@
+// Spherical harmonics evaluation using the same real SH basis, coefficient ordering and sign
+// conventions as the original 3D gaussian splatting code, so colors match what splat renderers
+// display.
+
+use crate::math::{self, Mat3};
+use crate::{dim_for_degree, UnpackedGaussian};
+
+pub const SH_C0: f32 = 0.282_094_8;
+pub const SH_C1: f32 = 0.488_602_5;
+pub const SH_C2: [f32; 5] = [1.092_548_4, -1.092_548_4, 0.315_391_57, -1.092_548_4, 0.546_274_2];
+pub const SH_C3: [f32; 7] = [-0.590_043_6, 2.890_611_4, -0.457_045_8, 0.373_176_33, -0.457_045_8, 1.445_305_7, -0.590_043_6];
+pub const SH_C4: [f32; 9] = [
+    2.503_343, -1.770_130_8, 0.946_174_7, -0.669_046_5, 0.105_785_55, -0.669_046_5, 0.473_087_34, -1.770_130_8, 0.625_835_7,
+];
+
+/// Values of the basis functions above degree 0 for a unit direction, in coefficient order
+pub fn sh_basis(sh_degree: usize, direction: [f32; 3]) -> Vec<f32> {
+    let [x, y, z] = direction;
+    let mut basis = Vec::with_capacity(dim_for_degree(sh_degree));
+    if sh_degree >= 1 {
+        basis.extend_from_slice(&[-SH_C1 * y, SH_C1 * z, -SH_C1 * x]);
+    }
+    if sh_degree >= 2 {
+        let (xx, yy, zz) = (x * x, y * y, z * z);
+        basis.extend_from_slice(&[
+            SH_C2[0] * x * y,
+            SH_C2[1] * y * z,
+            SH_C2[2] * (2.0 * zz - xx - yy),
+            SH_C2[3] * x * z,
+            SH_C2[4] * (xx - yy),
+        ]);
+    }
+    if sh_degree >= 3 {
+        let (xx, yy, zz) = (x * x, y * y, z * z);
+        basis.extend_from_slice(&[
+            SH_C3[0] * y * (3.0 * xx - yy),
+            SH_C3[1] * x * y * z,
+            SH_C3[2] * y * (4.0 * zz - xx - yy),
+            SH_C3[3] * z * (2.0 * zz - 3.0 * xx - 3.0 * yy),
+            SH_C3[4] * x * (4.0 * zz - xx - yy),
+            SH_C3[5] * z * (xx - yy),
+            SH_C3[6] * x * (xx - 3.0 * yy),
+        ]);
+    }
+    #[cfg(feature = "experimental")]
+    if sh_degree >= 4 {
+        let (xx, yy, zz) = (x * x, y * y, z * z);
+        basis.extend_from_slice(&[
+            SH_C4[0] * x * y * (xx - yy),
+            SH_C4[1] * y * z * (3.0 * xx - yy),
+            SH_C4[2] * x * y * (7.0 * zz - 1.0),
+            SH_C4[3] * y * z * (7.0 * zz - 3.0),
+            SH_C4[4] * (zz * (35.0 * zz - 30.0) + 3.0),
+            SH_C4[5] * x * z * (7.0 * zz - 3.0),
+            SH_C4[6] * (xx - yy) * (7.0 * zz - 1.0),
+            SH_C4[7] * x * z * (xx - 3.0 * yy),
+            SH_C4[8] * (xx * (xx - 3.0 * yy) - yy * (3.0 * xx - yy)),
+        ]);
+    }
+    basis
+}
+
+/// RGB color of a gaussian seen along `direction`, the unit vector from the viewer towards the
+/// gaussian. Uses the first `sh_degree` bands. The result isn't clamped.
+pub fn evaluate_color(gaussian: &UnpackedGaussian, sh_degree: usize, direction: [f32; 3]) -> [f32; 3] {
+    let mut rgb = gaussian.color.map(|c| 0.5 + SH_C0 * c);
+    for (j, b) in sh_basis(sh_degree, direction).iter().enumerate() {
+        rgb[0] += b * gaussian.sh_r[j];
+        rgb[1] += b * gaussian.sh_g[j];
+        rgb[2] += b * gaussian.sh_b[j];
+    }
+    rgb
+}
+
+// Directions used to fit SH rotations, enough to overdetermine the highest band
+const ROTATION_FIT_DIRECTIONS: usize = 64;
+
+/// Matrices rotating the SH coefficients of each band from 1 up to `sh_degree`, for a rotation
+/// matrix applied to the scene. Band `l` has a (2l + 1) x (2l + 1) row major matrix which maps a
+/// band's coefficients for one color channel to the rotated coefficients, so that the rotated
+/// function seen along `R d` matches the original seen along `d`.
+pub fn rotation_matrices(rotation: &Mat3, sh_degree: usize) -> Vec<Vec<f32>> {
+    // Fit each band by least squares over a spiral of directions. For rotated coefficients c'
+    // the basis at d applied to c' must equal the original basis at R^T d applied to c.
+    let directions: Vec<[f32; 3]> = (0..ROTATION_FIT_DIRECTIONS).map(|k| {
+        let z = 1.0 - (2.0 * k as f32 + 1.0) / ROTATION_FIT_DIRECTIONS as f32;
+        let r = (1.0 - z * z).sqrt();
+        let phi = k as f32 * 2.399_963;
+        [r * phi.cos(), r * phi.sin(), z]
+    }).collect();
+    let original: Vec<Vec<f32>> = directions.iter().map(|&d| sh_basis(sh_degree, d)).collect();
+    let rotated: Vec<Vec<f32>> = directions.iter()
+        .map(|&d| sh_basis(sh_degree, math::mat3_transpose_mul_vec3(rotation, d)))
+        .collect();
+
+    (1..=sh_degree).map(|l| {
+        let offset = l * l - 1;
+        let size = 2 * l + 1;
+        // Normal equations A^T A M = A^T B
+        let mut ata = vec![0.0f64; size * size];
+        let mut atb = vec![0.0f64; size * size];
+        for (a, b) in original.iter().zip(&rotated) {
+            for i in 0..size {
+                for j in 0..size {
+                    ata[i * size + j] += a[offset + i] as f64 * a[offset + j] as f64;
+                    atb[i * size + j] += a[offset + i] as f64 * b[offset + j] as f64;
+                }
+            }
+        }
+        solve(&mut ata, &mut atb, size);
+        atb.iter().map(|&v| v as f32).collect()
+    }).collect()
+}
+
+/// Exact SH matrices for a change of axes given by a signed permutation matrix, such as the change
+/// between two coordinate conventions, in the same layout as [`rotation_matrices`]. They're built
+/// from closed form tables rather than fitted, so conversions give the same bits everywhere.
+pub(crate) fn axis_change_matrices(matrix: &Mat3, sh_degree: usize) -> Vec<Vec<f32>> {
+    // The change is a permutation of the axes applied after flipping some of them. Every
+    // permutation is a product of swaps of x with y and of y with z using at most one of the
+    // latter, so each product has at most one table which mixes coefficients.
+    let flips = [0, 1, 2].map(|j| matrix.iter().any(|row| row[j] < 0.0));
+    let permutation = matrix.map(|row| row.map(f32::abs));
+    let xy = [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+    let yz = [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]];
+    let words: [&[bool]; 6] = [&[], &[false], &[true], &[false, true], &[true, false], &[false, true, false]];
+    let word = words.iter().find(|word| {
+        let product = word.iter().fold([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]], |m, &is_yz| {
+            let swap = if is_yz { &yz } else { &xy };
+            [0, 1, 2].map(|i| [0, 1, 2].map(|j| (0..3).map(|k| m[i][k] * swap[k][j]).sum()))
+        });
+        product == permutation
+    }).expect("axis change should be a signed permutation");
+
+    (1..=sh_degree).map(|l| {
+        let size = 2 * l + 1;
+        let mut band: Vec<f64> = (0..size * size).map(|k| if k % (size + 1) == 0 { 1.0 } else { 0.0 }).collect();
+        for &is_yz in word.iter() {
+            let swap = if is_yz { swap_yz(l) } else { swap_xy(l) };
+            band = (0..size * size).map(|k| {
+                let (i, j) = (k / size, k % size);
+                (0..size).map(|n| band[i * size + n] * swap[n * size + j]).sum()
+            }).collect();
+        }
+        // Flips scale the columns, as they're applied first
+        for (k, v) in band.iter_mut().enumerate() {
+            let m = (k % size) as i64 - l as i64;
+            *v *= (0..3).filter(|&axis| flips[axis]).map(|axis| flip_sign(l as i64, m, axis)).product::<f64>();
+        }
+        band.iter().map(|&v| v as f32).collect()
+    }).collect()
+}
+
+// Sign the coefficient of order `m` in band `l` takes when one axis is negated
+fn flip_sign(l: i64, m: i64, axis: usize) -> f64 {
+    let odd = match axis {
+        0 => if m >= 0 { m % 2 != 0 } else { m % 2 == 0 },
+        1 => m < 0,
+        _ => (l + m) % 2 != 0,
+    };
+    if odd { -1.0 } else { 1.0 }
+}
+
+// Matrix of band `l` for swapping the x and y axes, which maps order m to order -m when m is odd
+// and keeps it otherwise, with a sign depending on m
+fn swap_xy(l: usize) -> Vec<f64> {
+    let size = 2 * l + 1;
+    let mut band = vec![0.0; size * size];
+    for row in 0..size {
+        let m = row as i64 - l as i64;
+        let half = m.abs() / 2;
+        let (column, negative) = match m {
+            0 => (row, false),
+            _ if m % 2 != 0 => ((l as i64 - m) as usize, half % 2 != 0),
+            _ if m > 0 => (row, half % 2 != 0),
+            _ => (row, half % 2 == 0),
+        };
+        band[row * size + column] = if negative { -1.0 } else { 1.0 };
+    }
+    band
+}
+
+// Matrix of band `l` for swapping the y and z axes, which mixes coefficients, written out from the
+// closed form of the rotated basis
+fn swap_yz(l: usize) -> Vec<f64> {
+    let s = f64::sqrt;
+    match l {
+        1 => vec![
+            0.0, -1.0, 0.0,
+            -1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0,
+        ],
+        2 => vec![
+            0.0, 0.0, 0.0, -1.0, 0.0,
+            0.0, 1.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, -0.5, 0.0, -s(3.0) / 2.0,
+            -1.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, -s(3.0) / 2.0, 0.0, 0.5,
+        ],
+        3 => vec![
+            0.0, 0.0, 0.0, s(10.0) / 4.0, 0.0, -s(6.0) / 4.0, 0.0,
+            0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, s(6.0) / 4.0, 0.0, s(10.0) / 4.0, 0.0,
+            s(10.0) / 4.0, 0.0, s(6.0) / 4.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0, -0.25, 0.0, -s(15.0) / 4.0,
+            -s(6.0) / 4.0, 0.0, s(10.0) / 4.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0, -s(15.0) / 4.0, 0.0, 0.25,
+        ],
+        4 => vec![
+            0.0, 0.0, 0.0, 0.0, 0.0, s(14.0) / 4.0, 0.0, -s(2.0) / 4.0, 0.0,
+            0.0, 0.75, 0.0, -s(7.0) / 4.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0, 0.0, s(2.0) / 4.0, 0.0, s(14.0) / 4.0, 0.0,
+            0.0, -s(7.0) / 4.0, 0.0, -0.75, 0.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0, 0.375, 0.0, s(5.0) / 4.0, 0.0, s(35.0) / 8.0,
+            s(14.0) / 4.0, 0.0, s(2.0) / 4.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0, s(5.0) / 4.0, 0.0, 0.5, 0.0, -s(7.0) / 4.0,
+            -s(2.0) / 4.0, 0.0, s(14.0) / 4.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0, s(35.0) / 8.0, 0.0, -s(7.0) / 4.0, 0.0, 0.125,
+        ],
+        _ => unreachable!("SH bands only go up to 4"),
+    }
+}
+
+// Solves A X = B in place for square A using Gauss-Jordan elimination with partial pivoting,
+// leaving X in `b`
+fn solve(a: &mut [f64], b: &mut [f64], n: usize) {
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i * n + col].abs().total_cmp(&a[j * n + col].abs())).unwrap();
+        for k in 0..n {
+            a.swap(col * n + k, pivot * n + k);
+            b.swap(col * n + k, pivot * n + k);
+        }
+        let p = a[col * n + col];
+        for k in 0..n {
+            a[col * n + k] /= p;
+            b[col * n + k] /= p;
+        }
+        for row in 0..n {
+            if row != col {
+                let factor = a[row * n + col];
+                for k in 0..n {
+                    a[row * n + k] -= factor * a[col * n + k];
+                    b[row * n + k] -= factor * b[col * n + k];
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+// k-d tree over points for nearest neighbour lookups
+
+use crate::math::Vec3;
+
+pub(crate) struct KdTree {
+    points: Vec<Vec3>,
+    // Permutation of point indices, arranged so that each subrange is a subtree with its
+    // splitting point in the middle
+    order: Vec<usize>,
+}
+
+impl KdTree {
+    pub(crate) fn new(points: Vec<Vec3>) -> KdTree {
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        build(&points, &mut order, 0);
+        KdTree { points, order }
+    }
+
+    /// Index of the point closest to `query`, or None if the tree is empty
+    pub(crate) fn nearest(&self, query: Vec3) -> Option<usize> {
+        let mut best = None;
+        let mut best_distance2 = f32::INFINITY;
+        self.search(&self.order, 0, query, &mut best, &mut best_distance2);
+        best
+    }
+
+    fn search(&self, order: &[usize], depth: usize, query: Vec3, best: &mut Option<usize>, best_distance2: &mut f32) {
+        if order.is_empty() {
+            return;
+        }
+        let mid = order.len() / 2;
+        let index = order[mid];
+        let p = self.points[index];
+        let d = [query[0] - p[0], query[1] - p[1], query[2] - p[2]];
+        let distance2 = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+        if distance2 < *best_distance2 {
+            *best_distance2 = distance2;
+            *best = Some(index);
+        }
+
+        let axis = depth % 3;
+        let (near, far) = if d[axis] < 0.0 {
+            (&order[..mid], &order[mid + 1..])
+        } else {
+            (&order[mid + 1..], &order[..mid])
+        };
+        self.search(near, depth + 1, query, best, best_distance2);
+        if d[axis] * d[axis] < *best_distance2 {
+            self.search(far, depth + 1, query, best, best_distance2);
+        }
+    }
+}
+
+fn build(points: &[Vec3], order: &mut [usize], depth: usize) {
+    if order.len() <= 1 {
+        return;
+    }
+    let axis = depth % 3;
+    let mid = order.len() / 2;
+    order.select_nth_unstable_by(mid, |&a, &b| points[a][axis].total_cmp(&points[b][axis]));
+    let (left, right) = order.split_at_mut(mid);
+    build(points, left, depth + 1);
+    build(points, &mut right[1..], depth + 1);
+}
@@ -0,0 +1,194 @@
+// Streaming decoding of .spz files, for consumers which can't hold a whole decoded cloud in
+// memory.
+//
+// Each attribute is stored as its own section of the file, one after the other, so decoding a
+// single splat needs bytes from every section. The reader decompresses the file once, in order.
+// It keeps the packed bytes of every section before the SH coefficients, which take 19 or 20
+// bytes per splat, and reads the coefficients, usually most of the file, as splats are decoded.
+// Nothing needs to seek, so the source can be a socket or a pipe.
+
+use std::fs;
+use std::io::{BufReader, Read};
+
+use flate2::read::GzDecoder;
+
+use crate::coordinates::CoordinateSystem;
+use crate::error::{read_field, read_field_vec, SpzError};
+use crate::{
+    convert_smallest_three_rotations, dim_for_degree, read_header, LoadOptions, PackedGaussian, PositionEncoding,
+    UnpackOptions, UnpackedGaussian, FIELD_NAMES, FLAG_ANTIALIASED, MAX_SH_DIM,
+};
+
+/// Decodes the splats of a .spz file one at a time, in order
+pub struct SpzReader<R: Read> {
+    num_points: usize,
+    sh_degree: usize,
+    fractional_bits: usize,
+    antialiased: bool,
+    position_encoding: PositionEncoding,
+    options: UnpackOptions,
+    // Packed sections before the SH coefficients, with rotations in the three byte encoding
+    positions: Vec<u8>,
+    alphas: Vec<u8>,
+    colors: Vec<u8>,
+    scales: Vec<u8>,
+    rotations: Vec<u8>,
+    // Positioned at the coefficients of the next splat
+    sh: BufReader<GzDecoder<R>>,
+    next: usize,
+}
+
+impl<R: Read> SpzReader<R> {
+    pub fn new(reader: R) -> Result<SpzReader<R>, SpzError> {
+        SpzReader::new_with(reader, &UnpackOptions::default())
+    }
+
+    pub fn new_with(reader: R, options: &UnpackOptions) -> Result<SpzReader<R>, SpzError> {
+        let mut decoder = BufReader::new(GzDecoder::new(reader));
+        let header = read_header(&mut decoder).map_err(SpzError::through_gzip)?;
+        let sizes = LoadOptions::default().check_header(&header)?;
+
+        let mut section = |field: usize| read_field_vec(&mut decoder, FIELD_NAMES[field], sizes[field]).map_err(SpzError::through_gzip);
+        let positions = section(0)?;
+        let alphas = section(1)?;
+        let colors = section(2)?;
+        let scales = section(3)?;
+        let rotations = section(4)?;
+        let rotations = if header.version == 3 { convert_smallest_three_rotations(&rotations) } else { rotations };
+
+        Ok(SpzReader {
+            num_points: header.num_points as usize,
+            sh_degree: header.sh_degree as usize,
+            fractional_bits: header.fractional_bits as usize,
+            antialiased: (header.flags & FLAG_ANTIALIASED) != 0,
+            position_encoding: header.position_encoding(),
+            options: options.for_stored(CoordinateSystem::RUB),
+            positions,
+            alphas,
+            colors,
+            scales,
+            rotations,
+            sh: decoder,
+            next: 0,
+        })
+    }
+
+    pub fn num_points(&self) -> usize {
+        self.num_points
+    }
+
+    pub fn sh_degree(&self) -> usize {
+        self.sh_degree
+    }
+
+    pub fn fractional_bits(&self) -> usize {
+        self.fractional_bits
+    }
+
+    pub fn antialiased(&self) -> bool {
+        self.antialiased
+    }
+
+    /// Axis convention of the stored splats, which is always RUB for .spz files
+    pub fn coordinate_system(&self) -> CoordinateSystem {
+        CoordinateSystem::RUB
+    }
+
+    fn read_packed(&mut self) -> Result<PackedGaussian, SpzError> {
+        let mut result = PackedGaussian::default();
+        let position_bits = self.position_encoding.size();
+        let sh_stride = dim_for_degree(self.sh_degree) * 3;
+        let i = self.next;
+
+        result.position[..position_bits].copy_from_slice(&self.positions[i * position_bits..(i + 1) * position_bits]);
+        result.alpha = self.alphas[i];
+        result.color.copy_from_slice(&self.colors[i * 3..i * 3 + 3]);
+        result.scale.copy_from_slice(&self.scales[i * 3..i * 3 + 3]);
+        result.rotation.copy_from_slice(&self.rotations[i * 3..i * 3 + 3]);
+
+        // Coefficients above the file's degree decode to zero
+        let mut coefficients = [128u8; 3 * MAX_SH_DIM];
+        read_field(&mut self.sh, &mut coefficients[..sh_stride], "sh", i * sh_stride, self.num_points * sh_stride)?;
+        for (j, rgb) in coefficients.chunks_exact(3).enumerate() {
+            [result.sh_r[j], result.sh_g[j], result.sh_b[j]] = [rgb[0], rgb[1], rgb[2]];
+        }
+        Ok(result)
+    }
+}
+
+impl SpzReader<fs::File> {
+    pub fn open(filename: &str) -> Result<SpzReader<fs::File>, SpzError> {
+        SpzReader::new(fs::File::open(filename)?)
+    }
+}
+
+impl<R: Read> Iterator for SpzReader<R> {
+    type Item = Result<UnpackedGaussian, SpzError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.num_points {
+            return None;
+        }
+
+        match self.read_packed() {
+            Ok(packed) => {
+                self.next += 1;
+                let uses_float16 = self.position_encoding == PositionEncoding::Float16;
+                Some(Ok(packed.unpack_with(uses_float16, self.fractional_bits as u32, &self.options)))
+            }
+            Err(error) => {
+                // Nothing after a decoding error can be trusted
+                self.next = self.num_points;
+                Some(Err(error.through_gzip()))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.num_points - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+    use crate::write_packed_gaussians_to_spz_buffer;
+
+    #[test]
+    fn streamed_splats_match_the_loaded_cloud() {
+        let cloud = random_cloud(300, 2, 9);
+        let mut bytes = Vec::new();
+        write_packed_gaussians_to_spz_buffer(&cloud, &mut bytes).unwrap();
+
+        // A byte slice can't seek
+        let reader = SpzReader::new(&bytes[..]).unwrap();
+        assert_eq!((reader.num_points(), reader.sh_degree()), (300, 2));
+        let streamed: Vec<UnpackedGaussian> = reader.map(Result::unwrap).collect();
+        assert_eq!(streamed.len(), 300);
+        for (i, gaussian) in streamed.iter().enumerate() {
+            let expected = cloud.unpack(i);
+            assert_eq!((gaussian.position, gaussian.rotation, gaussian.scale), (expected.position, expected.rotation, expected.scale));
+            assert_eq!((gaussian.color, gaussian.alpha.to_bits()), (expected.color, expected.alpha.to_bits()));
+            assert_eq!((gaussian.sh_r, gaussian.sh_g, gaussian.sh_b), (expected.sh_r, expected.sh_g, expected.sh_b));
+        }
+    }
+
+    #[test]
+    fn truncated_coefficients_end_the_stream_with_an_error() {
+        let cloud = random_cloud(100, 1, 10);
+        let mut decompressed = Vec::new();
+        crate::save_packed_gaussians_to_decompressed_buffer(&cloud, &mut decompressed).unwrap();
+        decompressed.truncate(decompressed.len() - 9 * 10 - 1);
+        let mut bytes = Vec::new();
+        let mut encoder = flate2::write::GzEncoder::new(&mut bytes, flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &decompressed).unwrap();
+        encoder.finish().unwrap();
+
+        let results: Vec<_> = SpzReader::new(&bytes[..]).unwrap().collect();
+        assert_eq!(results.len(), 90);
+        assert!(results[..89].iter().all(Result::is_ok));
+        assert!(matches!(results[89], Err(SpzError::TruncatedField { field: "sh", expected: 900, got: 809 })));
+    }
+}
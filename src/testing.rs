@@ -0,0 +1,25 @@
+// Helpers shared by the unit tests
+
+use crate::rng::{RandomSource, SplitMix64};
+use crate::{dim_for_degree, PackedGaussians};
+
+/// Cloud of splats with random bytes in every field and fixed point positions
+pub(crate) fn random_cloud(num_points: usize, sh_degree: usize, seed: u64) -> PackedGaussians {
+    let mut rng = SplitMix64::new(seed);
+    let mut bytes = |count: usize| -> Vec<u8> { (0..count).map(|_| rng.next_u64() as u8).collect() };
+    PackedGaussians {
+        num_points,
+        sh_degree,
+        fractional_bits: 12,
+        antialiased: false,
+        positions: bytes(num_points * 9),
+        scales: bytes(num_points * 3),
+        rotations: bytes(num_points * 3),
+        alphas: bytes(num_points),
+        colors: bytes(num_points * 3),
+        sh: bytes(num_points * dim_for_degree(sh_degree) * 3),
+        attributes: Default::default(),
+        position_encoding: Default::default(),
+        coordinate_system: Default::default(),
+    }
+}
@@ -0,0 +1,244 @@
+// Tiled container for streaming large scenes from a single file. The scene is split into
+// spatially compact tiles, each stored as a complete .spz file, after an index giving the byte
+// range, splat count and bounds of every tile. A viewer fetches the index first and then only
+// the byte ranges of the tiles it can see, for example with HTTP range requests.
+//
+// Layout, little endian:
+//   header: magic "NGST", version, number of tiles, reserved (u32 each)
+//   index:  per tile offset from the start of the file (u64), size in bytes (u64),
+//           number of splats (u32), min and max bounds of the splat centers (3 x f32 each)
+//   tiles:  gzipped .spz files, one after the other
+//
+// Tiles are consecutive runs of the cloud in Z-order, so every tile has about the same number of
+// splats however unevenly the scene is populated.
+
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+
+use crate::coordinates::CoordinateSystem;
+use crate::error::{read_field, SpzError};
+use crate::{load_packed_gaussians_from_bytes, save_packed_gaussians_to_spz_buffer, PackedGaussians};
+
+pub const TILED_MAGIC: u32 = 0x5453474e; // NGST = tiled NGSP
+pub const TILED_VERSION: u32 = 1;
+pub const TILED_HEADER_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TiledOptions {
+    /// Most splats stored in one tile
+    pub points_per_tile: usize,
+}
+
+impl Default for TiledOptions {
+    fn default() -> TiledOptions {
+        TiledOptions { points_per_tile: 1 << 16 }
+    }
+}
+
+impl TiledOptions {
+    pub fn points_per_tile(mut self, points_per_tile: usize) -> TiledOptions {
+        self.points_per_tile = points_per_tile;
+        self
+    }
+}
+
+/// Index entry for one tile
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileInfo {
+    /// Byte offset of the tile's .spz file from the start of the container
+    pub offset: u64,
+    pub size: u64,
+    pub num_points: usize,
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl TileInfo {
+    pub const SIZE: usize = 44;
+
+    fn to_bytes(self) -> [u8; TileInfo::SIZE] {
+        let mut bytes = [0; TileInfo::SIZE];
+        bytes[0..8].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.size.to_le_bytes());
+        bytes[16..20].copy_from_slice(&(self.num_points as u32).to_le_bytes());
+        for (j, x) in self.min.iter().chain(&self.max).enumerate() {
+            bytes[20 + j * 4..24 + j * 4].copy_from_slice(&x.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; TileInfo::SIZE]) -> TileInfo {
+        let f32_at = |j: usize| f32::from_le_bytes([bytes[j], bytes[j + 1], bytes[j + 2], bytes[j + 3]]);
+        TileInfo {
+            offset: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            size: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            num_points: u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize,
+            min: [f32_at(20), f32_at(24), f32_at(28)],
+            max: [f32_at(32), f32_at(36), f32_at(40)],
+        }
+    }
+
+    /// Whether the tile's bounds overlap the box from `min` to `max`
+    pub fn intersects(&self, min: [f32; 3], max: [f32; 3]) -> bool {
+        (0..3).all(|j| self.min[j] <= max[j] && self.max[j] >= min[j])
+    }
+}
+
+/// Splits a cloud into tiles and writes them with their index. Tiles are saved in RUB like any
+/// .spz file, and their bounds are given in RUB too.
+pub fn write_tiled<W: Write>(gaussians: &PackedGaussians, mut writer: W, options: &TiledOptions) -> Result<(), io::Error> {
+    if options.points_per_tile == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Tiles need room for at least one splat"));
+    }
+    let gaussians = if matches!(gaussians.coordinate_system, CoordinateSystem::Unspecified | CoordinateSystem::RUB) {
+        Cow::Borrowed(gaussians)
+    } else {
+        let mut converted = gaussians.clone();
+        converted.convert_coordinates(CoordinateSystem::RUB);
+        Cow::Owned(converted)
+    };
+
+    let order = gaussians.morton_order();
+    let chunks: Vec<&[usize]> = order.chunks(options.points_per_tile).collect();
+    let mut offset = (TILED_HEADER_SIZE + chunks.len() * TileInfo::SIZE) as u64;
+    let mut index = Vec::with_capacity(chunks.len());
+    let mut payloads = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        let tile = gaussians.select(chunk);
+        let mut payload = Vec::new();
+        save_packed_gaussians_to_spz_buffer(&tile, &mut payload)?;
+        // Every tile has at least one splat, so has bounds
+        let (min, max) = tile.compute_bounds().unwrap_or_default();
+        index.push(TileInfo { offset, size: payload.len() as u64, num_points: tile.num_points, min, max });
+        offset += payload.len() as u64;
+        payloads.push(payload);
+    }
+
+    for value in [TILED_MAGIC, TILED_VERSION, index.len() as u32, 0] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    for tile in &index {
+        writer.write_all(&tile.to_bytes())?;
+    }
+    for payload in &payloads {
+        writer.write_all(payload)?;
+    }
+    Ok(())
+}
+
+pub fn save_tiled_to_file(gaussians: &PackedGaussians, filename: &str, options: &TiledOptions) -> Result<(), io::Error> {
+    let file = fs::File::create(filename)?;
+    let mut writer = io::BufWriter::new(file);
+    write_tiled(gaussians, &mut writer, options)?;
+    writer.flush()
+}
+
+/// Reads the header and index from the start of a tiled container. A viewer fetching the file
+/// over the network can read the first [`TILED_HEADER_SIZE`] bytes to learn the number of tiles,
+/// and so the size of the index, before fetching the rest of it.
+pub fn read_tile_index<R: Read>(reader: &mut R) -> Result<Vec<TileInfo>, SpzError> {
+    let mut header = [0u8; TILED_HEADER_SIZE];
+    read_field(reader, &mut header, "header", 0, TILED_HEADER_SIZE)?;
+    let value = |j: usize| u32::from_le_bytes(header[j * 4..j * 4 + 4].try_into().unwrap());
+    if value(0) != TILED_MAGIC {
+        return Err(SpzError::InvalidMagic);
+    }
+    if value(1) != TILED_VERSION {
+        return Err(SpzError::UnsupportedVersion(value(1)));
+    }
+
+    let num_tiles = value(2) as usize;
+    let index_size = num_tiles * TileInfo::SIZE;
+    let mut tiles = Vec::new();
+    for i in 0..num_tiles {
+        let mut bytes = [0u8; TileInfo::SIZE];
+        read_field(reader, &mut bytes, "tile index", i * TileInfo::SIZE, index_size)?;
+        tiles.push(TileInfo::from_bytes(&bytes));
+    }
+    Ok(tiles)
+}
+
+/// Reads individual tiles of a tiled container on demand
+pub struct TiledSpzReader<R: Read + Seek> {
+    reader: R,
+    // Position of the start of the container in the reader, which tile offsets are relative to
+    start: u64,
+    tiles: Vec<TileInfo>,
+}
+
+impl<R: Read + Seek> TiledSpzReader<R> {
+    pub fn new(mut reader: R) -> Result<TiledSpzReader<R>, SpzError> {
+        let start = reader.stream_position()?;
+        let tiles = read_tile_index(&mut reader)?;
+        Ok(TiledSpzReader { reader, start, tiles })
+    }
+
+    pub fn tiles(&self) -> &[TileInfo] {
+        &self.tiles
+    }
+
+    pub fn num_points(&self) -> usize {
+        self.tiles.iter().map(|tile| tile.num_points).sum()
+    }
+
+    /// Indices of the tiles whose bounds overlap the box from `min` to `max`
+    pub fn tiles_intersecting(&self, min: [f32; 3], max: [f32; 3]) -> Vec<usize> {
+        (0..self.tiles.len()).filter(|&i| self.tiles[i].intersects(min, max)).collect()
+    }
+
+    /// Fetches and decodes one tile
+    pub fn read_tile(&mut self, i: usize) -> Result<PackedGaussians, SpzError> {
+        let tile = *self.tiles.get(i).ok_or(SpzError::TileOutOfRange { index: i, num_tiles: self.tiles.len() })?;
+        // An offset past the end of any stream reads as a missing tile
+        let offset = self.start.checked_add(tile.offset)
+            .ok_or(SpzError::TruncatedField { field: "tile", expected: tile.size as usize, got: 0 })?;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut bytes = Vec::new();
+        (&mut self.reader).take(tile.size).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != tile.size {
+            return Err(SpzError::TruncatedField { field: "tile", expected: tile.size as usize, got: bytes.len() });
+        }
+        load_packed_gaussians_from_bytes(&bytes)
+    }
+}
+
+impl TiledSpzReader<BufReader<fs::File>> {
+    pub fn open(filename: &str) -> Result<TiledSpzReader<BufReader<fs::File>>, SpzError> {
+        TiledSpzReader::new(BufReader::new(fs::File::open(filename)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+
+    #[test]
+    fn tiles_past_the_index_are_an_error() {
+        let cloud = random_cloud(100, 0, 12);
+        let mut bytes = Vec::new();
+        write_tiled(&cloud, &mut bytes, &TiledOptions::default().points_per_tile(40)).unwrap();
+
+        let mut reader = TiledSpzReader::new(io::Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.tiles().len(), 3);
+        let total: usize = (0..3).map(|i| reader.read_tile(i).unwrap().num_points).sum();
+        assert_eq!(total, 100);
+        assert!(matches!(reader.read_tile(3), Err(SpzError::TileOutOfRange { index: 3, num_tiles: 3 })));
+    }
+
+    #[test]
+    fn bounds_match_the_saved_tiles() {
+        let mut cloud = random_cloud(100, 1, 16);
+        cloud.coordinate_system = CoordinateSystem::RDF;
+        let mut bytes = Vec::new();
+        write_tiled(&cloud, &mut bytes, &TiledOptions::default().points_per_tile(30)).unwrap();
+
+        let mut reader = TiledSpzReader::new(io::Cursor::new(bytes)).unwrap();
+        for (i, info) in reader.tiles().to_vec().into_iter().enumerate() {
+            let tile = reader.read_tile(i).unwrap();
+            assert_eq!(tile.coordinate_system, CoordinateSystem::RUB);
+            assert_eq!(tile.compute_bounds(), Some((info.min, info.max)));
+        }
+    }
+}
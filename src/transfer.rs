@@ -0,0 +1,74 @@
+// Transfer of appearance between clouds covering the same scene, for example to apply the look
+// of a color graded copy back to the original geometry
+
+use std::borrow::Cow;
+
+use crate::coordinates::CoordinateSystem;
+use crate::spatial::KdTree;
+use crate::{dim_for_degree, PackedGaussians};
+
+/// Recolors `to` using the color of the nearest splat in `from`, returning the number of splats
+/// changed. `to` keeps its SH degree: coefficients for bands that `from` also has are copied,
+/// and any higher bands are left as they were. `from` is converted to the coordinate system of
+/// `to` first, as both the matching and the SH coefficients depend on it.
+pub fn transfer_colors(from: &PackedGaussians, to: &mut PackedGaussians) -> usize {
+    if from.num_points == 0 {
+        return 0;
+    }
+
+    // Unspecified clouds are in RUB, the convention of the format
+    let frame = |system| if system == CoordinateSystem::Unspecified { CoordinateSystem::RUB } else { system };
+    let from = if frame(from.coordinate_system) == frame(to.coordinate_system) {
+        Cow::Borrowed(from)
+    } else {
+        let mut converted = from.clone();
+        converted.convert_coordinates(frame(to.coordinate_system));
+        Cow::Owned(converted)
+    };
+
+    let tree = KdTree::new((0..from.num_points).map(|i| from.unpack_position(i)).collect());
+    let from_sh_dim = dim_for_degree(from.sh_degree);
+    let to_sh_dim = dim_for_degree(to.sh_degree);
+    let shared = from_sh_dim.min(to_sh_dim) * 3;
+
+    let mut changed = 0;
+    for i in 0..to.num_points {
+        let source = tree.nearest(to.unpack_position(i)).unwrap();
+        let color = &from.colors[3 * source..3 * source + 3];
+        let sh = &from.sh[source * from_sh_dim * 3..source * from_sh_dim * 3 + shared];
+        let target_sh = &mut to.sh[i * to_sh_dim * 3..i * to_sh_dim * 3 + shared];
+        if to.colors[3 * i..3 * i + 3] != *color || *target_sh != *sh {
+            to.colors[3 * i..3 * i + 3].copy_from_slice(color);
+            target_sh.copy_from_slice(sh);
+            changed += 1;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+
+    #[test]
+    fn colors_are_matched_in_the_target_frame_and_extra_bands_kept() {
+        let mut to = random_cloud(300, 2, 21);
+        to.coordinate_system = CoordinateSystem::RUB;
+        let mut from = random_cloud(300, 1, 22);
+        from.positions = to.positions.clone();
+        from.coordinate_system = CoordinateSystem::RUB;
+        from.convert_coordinates(CoordinateSystem::RDF);
+        let mut expected = from.clone();
+        expected.convert_coordinates(CoordinateSystem::RUB);
+        assert_eq!(expected.positions, to.positions);
+
+        let original_sh = to.sh.clone();
+        assert_eq!(transfer_colors(&from, &mut to), 300);
+        for i in 0..300 {
+            assert_eq!(to.colors[i * 3..i * 3 + 3], expected.colors[i * 3..i * 3 + 3]);
+            assert_eq!(to.sh[i * 24..i * 24 + 9], expected.sh[i * 9..i * 9 + 9]);
+            assert_eq!(to.sh[i * 24 + 9..i * 24 + 24], original_sh[i * 24 + 9..i * 24 + 24]);
+        }
+    }
+}
@@ -0,0 +1,319 @@
+// Changes of the frame a cloud is expressed in. Rotating a cloud rotates splat positions and
+// orientations and also the view dependent color, so each splat keeps its appearance from
+// every direction.
+//
+// General affine transforms move each splat's covariance to A * C * A^T, which is decomposed
+// back into scales and a rotation. View dependent color follows the rotation part of A, the
+// orthogonal factor of its polar decomposition, since SH can't represent a stretched function.
+
+use crate::codec::{encode_quat3, quantize_scale, quantize_sh, unquantize_scale, unquantize_sh};
+use crate::coordinates::{AxisConversion, CoordinateSystem};
+use crate::math::{self, Mat3};
+use crate::{dim_for_degree, sh, PackedGaussians};
+
+/// Rotation taking a Z up scene to the Y up convention of the .spz format, -90 degrees about x
+pub const Z_UP_TO_Y_UP: Mat3 = [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]];
+
+/// Row major affine transform, with the translation in the last column. The last row is assumed
+/// to be 0, 0, 0, 1.
+pub type Mat4 = [[f32; 4]; 4];
+
+// Transforms whose squared singular values are this close to each other are treated as a
+// rotation and a uniform scale, which keeps splat scales exact apart from the scale factor
+const UNIFORM_SCALE_TOLERANCE: f32 = 1e-5;
+
+// Eigenvalues of transformed covariances are clamped to this so flattening transforms still give
+// finite scales
+const MIN_VARIANCE: f32 = 1e-20;
+
+/// Rotates the SH coefficients of one splat with the matrices for each band
+fn rotate_sh(coefficients: &mut [u8], sh_rotations: &[Vec<f32>]) {
+    for (l, m) in sh_rotations.iter().enumerate() {
+        let offset = (l + 1) * (l + 1) - 1;
+        let size = 2 * l + 3;
+        for channel in 0..3 {
+            let original: Vec<f32> = (0..size).map(|j| unquantize_sh(coefficients[(offset + j) * 3 + channel])).collect();
+            for row in 0..size {
+                let value: f32 = (0..size).map(|j| m[row * size + j] * original[j]).sum();
+                coefficients[(offset + row) * 3 + channel] = quantize_sh(value, 8);
+            }
+        }
+    }
+}
+
+/// Splits a matrix into an orthogonal matrix and the symmetric positive definite stretch
+/// applied before it, returning the orthogonal factor and the eigenvalues of the stretch squared
+fn polar_decomposition(m: &Mat3) -> (Mat3, [f32; 3]) {
+    let mut mtm = [[0.0; 3]; 3];
+    for (i, row) in mtm.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| m[k][i] * m[k][j]).sum();
+        }
+    }
+    let (values, vectors) = math::symmetric_eigen(&mtm);
+    let inverse_stretch = values.map(|v| 1.0 / v.max(MIN_VARIANCE).sqrt());
+
+    let mut orthogonal = [[0.0; 3]; 3];
+    for (i, row) in orthogonal.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            // M * V * D^-1 * V^T
+            *v = (0..3).map(|k| {
+                let mv = (0..3).map(|l| m[i][l] * vectors[l][k]).sum::<f32>();
+                mv * inverse_stretch[k] * vectors[j][k]
+            }).sum();
+        }
+    }
+    (orthogonal, values)
+}
+
+impl PackedGaussians {
+    /// Rotates the cloud about the origin by a rotation matrix
+    pub fn rotate(&mut self, rotation: &Mat3) {
+        let q = math::mat3_to_quat(rotation);
+        let sh_dim = dim_for_degree(self.sh_degree);
+        let sh_rotations = sh::rotation_matrices(rotation, self.sh_degree);
+
+        for i in 0..self.num_points {
+            let p = self.unpack_position(i);
+            self.set_position(i, math::mat3_mul_vec3(rotation, p));
+
+            let r = math::quat_normalize(math::quat_mul(q, self.unpack_rotation(i)));
+            self.rotations[3 * i..3 * i + 3].copy_from_slice(&encode_quat3(r));
+
+            rotate_sh(&mut self.sh[i * sh_dim * 3..(i + 1) * sh_dim * 3], &sh_rotations);
+        }
+    }
+
+    /// Applies an affine transform to the cloud. Mirroring transforms are supported, the splat
+    /// orientations stay proper rotations.
+    pub fn transform(&mut self, matrix: &Mat4) {
+        let linear: Mat3 = [0, 1, 2].map(|i| [matrix[i][0], matrix[i][1], matrix[i][2]]);
+        let translation = [matrix[0][3], matrix[1][3], matrix[2][3]];
+        let (orthogonal, stretch) = polar_decomposition(&linear);
+
+        let sign = math::mat3_determinant(&linear).signum();
+        let q = math::mat3_to_quat(&orthogonal.map(|row| row.map(|v| v * sign)));
+        let max_stretch = stretch.iter().fold(0.0f32, |a, &b| a.max(b));
+        let min_stretch = stretch.iter().fold(f32::INFINITY, |a, &b| a.min(b));
+        let uniform = max_stretch - min_stretch <= UNIFORM_SCALE_TOLERANCE * max_stretch;
+        let log_scale = 0.25 * (max_stretch * min_stretch).max(MIN_VARIANCE).ln();
+
+        let sh_dim = dim_for_degree(self.sh_degree);
+        let sh_rotations = sh::rotation_matrices(&orthogonal, self.sh_degree);
+
+        for i in 0..self.num_points {
+            let p = math::mat3_mul_vec3(&linear, self.unpack_position(i));
+            self.set_position(i, [p[0] + translation[0], p[1] + translation[1], p[2] + translation[2]]);
+
+            let rotation = self.unpack_rotation(i);
+            let scales = &mut self.scales[3 * i..3 * i + 3];
+            let r = if uniform {
+                for x in scales.iter_mut() {
+                    *x = quantize_scale(unquantize_scale(*x) + log_scale);
+                }
+                math::quat_normalize(math::quat_mul(q, rotation))
+            } else {
+                let covariance = math::covariance_from_scale_rotation([0, 1, 2].map(|j| unquantize_scale(scales[j])), rotation);
+                let mut transformed = [[0.0; 3]; 3];
+                for (j, row) in transformed.iter_mut().enumerate() {
+                    for (k, v) in row.iter_mut().enumerate() {
+                        *v = (0..3).map(|a| (0..3).map(|b| linear[j][a] * covariance[a][b] * linear[k][b]).sum::<f32>()).sum();
+                    }
+                }
+                let (scale, rotation) = math::scale_rotation_from_covariance(&transformed, MIN_VARIANCE);
+                scales.copy_from_slice(&scale.map(quantize_scale));
+                rotation
+            };
+            self.rotations[3 * i..3 * i + 3].copy_from_slice(&encode_quat3(r));
+
+            rotate_sh(&mut self.sh[i * sh_dim * 3..(i + 1) * sh_dim * 3], &sh_rotations);
+        }
+    }
+
+    pub fn translate(&mut self, offset: [f32; 3]) {
+        for i in 0..self.num_points {
+            let p = self.unpack_position(i);
+            self.set_position(i, [p[0] + offset[0], p[1] + offset[1], p[2] + offset[2]]);
+        }
+    }
+
+    /// Scales the cloud uniformly about the origin
+    pub fn scale(&mut self, factor: f32) {
+        let f = factor;
+        self.transform(&[[f, 0.0, 0.0, 0.0], [0.0, f, 0.0, 0.0], [0.0, 0.0, f, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+    }
+
+    /// Converts the packed splats into another axis convention. Clouds in an unspecified
+    /// convention are taken to be in RUB, the convention of the format.
+    pub fn convert_coordinates(&mut self, to: CoordinateSystem) {
+        let from = match self.coordinate_system {
+            CoordinateSystem::Unspecified => CoordinateSystem::RUB,
+            system => system,
+        };
+        if let Some(conversion) = AxisConversion::new(from, to) {
+            let sh_dim = dim_for_degree(self.sh_degree);
+            let sh_matrices = &conversion.sh_matrices()[..self.sh_degree];
+            for i in 0..self.num_points {
+                let mut p = self.unpack_position(i);
+                conversion.convert_position(&mut p);
+                self.set_position(i, p);
+
+                let mut r = self.unpack_rotation(i);
+                conversion.convert_rotation(&mut r);
+                self.rotations[3 * i..3 * i + 3].copy_from_slice(&encode_quat3(r));
+
+                conversion.convert_scale(&mut self.scales[3 * i..3 * i + 3]);
+                rotate_sh(&mut self.sh[i * sh_dim * 3..(i + 1) * sh_dim * 3], sh_matrices);
+            }
+        }
+        if to != CoordinateSystem::Unspecified {
+            self.coordinate_system = to;
+        }
+    }
+
+    /// Converts a cloud exported with Z up to Y up
+    pub fn convert_z_up_to_y_up(&mut self) {
+        self.rotate(&Z_UP_TO_Y_UP);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::random_cloud;
+
+    const IDENTITY: Mat3 = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    // Random cloud with SH well inside the quantized range and rotation components of 0.4 to 0.5
+    // in magnitude, so that no component is near zero after a small rotation or an axis flip and
+    // re-encoding only moves bytes by rounding
+    fn canonical_cloud(seed: u64) -> PackedGaussians {
+        let mut cloud = random_cloud(200, 2, seed);
+        cloud.coordinate_system = CoordinateSystem::RUB;
+        for x in cloud.sh.iter_mut().chain(cloud.scales.iter_mut()) {
+            *x = 64 + *x / 2;
+        }
+        for x in cloud.rotations.iter_mut() {
+            let magnitude = 51 + *x % 13;
+            *x = if *x & 0x80 != 0 { 128 + magnitude } else { 128 - magnitude };
+        }
+        // Keep positions within 16 units so a rotation cannot leave the fixed-point range
+        for p in cloud.positions.chunks_mut(3) {
+            p[2] = 0;
+        }
+        cloud.rotate(&IDENTITY);
+        cloud
+    }
+
+    fn assert_bytes_within(what: &str, actual: &[u8], expected: &[u8], steps: u8) {
+        assert_eq!(actual.len(), expected.len());
+        for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+            assert!(a.abs_diff(*e) <= steps, "{} byte {}: {} vs {}", what, i, a, e);
+        }
+    }
+
+    // Different quaternions can give the same splat when axes are flipped, so the shapes are
+    // compared rather than the rotation bytes
+    fn assert_covariances_match(actual: &PackedGaussians, expected: &PackedGaussians) {
+        for i in 0..actual.num_points {
+            let a = math::covariance_from_scale_rotation(actual.unpack_scale(i), actual.unpack_rotation(i));
+            let e = math::covariance_from_scale_rotation(expected.unpack_scale(i), expected.unpack_rotation(i));
+            let largest = (0..3).map(|j| e[j][j]).fold(0.0f32, f32::max);
+            for j in 0..3 {
+                for k in 0..3 {
+                    assert!((a[j][k] - e[j][k]).abs() <= 0.05 * largest, "splat {}: {:?} vs {:?}", i, a, e);
+                }
+            }
+        }
+    }
+
+    fn matmul(a: &Mat3, b: &Mat3) -> Mat3 {
+        [0, 1, 2].map(|i| [0, 1, 2].map(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+    }
+
+    fn transpose(m: &Mat3) -> Mat3 {
+        [0, 1, 2].map(|i| [0, 1, 2].map(|j| m[j][i]))
+    }
+
+    fn rotation_about_axis(axis: [f32; 3], angle: f32) -> Mat3 {
+        let length = (axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2]).sqrt();
+        let [x, y, z] = axis.map(|v| v / length);
+        let (s, c) = angle.sin_cos();
+        let t = 1.0 - c;
+        [
+            [t * x * x + c, t * x * y - s * z, t * x * z + s * y],
+            [t * x * y + s * z, t * y * y + c, t * y * z - s * x],
+            [t * x * z - s * y, t * y * z + s * x, t * z * z + c],
+        ]
+    }
+
+    #[test]
+    fn polar_decomposition_splits_rotation_and_stretch() {
+        let rotation = rotation_about_axis([1.0, 2.0, -0.5], 0.7);
+        let basis = rotation_about_axis([0.3, -1.0, 0.2], 1.9);
+        let stretch = matmul(&matmul(&basis, &[[2.0, 0.0, 0.0], [0.0, 0.5, 0.0], [0.0, 0.0, 3.0]]), &transpose(&basis));
+        for mirror in [IDENTITY, [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]]] {
+            let expected = matmul(&mirror, &rotation);
+            let (orthogonal, squared) = polar_decomposition(&matmul(&expected, &stretch));
+            for i in 0..3 {
+                for j in 0..3 {
+                    assert!((orthogonal[i][j] - expected[i][j]).abs() < 1e-4, "{:?} vs {:?}", orthogonal, expected);
+                }
+            }
+            let mut squared = squared;
+            squared.sort_by(f32::total_cmp);
+            for (value, expected) in squared.iter().zip([0.25, 4.0, 9.0]) {
+                assert!((value - expected).abs() < 1e-3, "{:?}", squared);
+            }
+        }
+    }
+
+    #[test]
+    fn rotating_back_restores_the_bytes() {
+        let original = canonical_cloud(30);
+        let rotation = rotation_about_axis([0.2, 1.0, 0.4], 0.3);
+        let mut cloud = original.clone();
+        cloud.rotate(&rotation);
+        cloud.rotate(&transpose(&rotation));
+
+        for i in 0..cloud.num_points {
+            let (p, q) = (cloud.unpack_position(i), original.unpack_position(i));
+            for j in 0..3 {
+                assert!((p[j] - q[j]).abs() <= 1.0 / 4096.0, "{:?} vs {:?}", p, q);
+            }
+        }
+        assert_bytes_within("rotation", &cloud.rotations, &original.rotations, 1);
+        assert_eq!(cloud.scales, original.scales);
+        assert_bytes_within("sh", &cloud.sh, &original.sh, 1);
+    }
+
+    #[test]
+    fn scaling_adds_to_the_log_scales() {
+        let original = canonical_cloud(31);
+        let mut cloud = original.clone();
+        cloud.scale(2.0);
+        for (a, b) in cloud.scales.iter().zip(&original.scales) {
+            let added = unquantize_scale(*a) - unquantize_scale(*b);
+            assert!((added - std::f32::consts::LN_2).abs() <= 0.5 / 16.0, "{} to {}", b, a);
+        }
+        assert_bytes_within("rotation", &cloud.rotations, &original.rotations, 1);
+        assert_eq!(cloud.sh, original.sh);
+    }
+
+    #[test]
+    fn axis_flips_match_the_coordinate_conversions() {
+        // A rotation by half a turn about x, and a mirror in z
+        for (to, diagonal) in [(CoordinateSystem::RDF, [1.0, -1.0, -1.0]), (CoordinateSystem::RUF, [1.0, 1.0, -1.0])] {
+            let original = canonical_cloud(32);
+            let mut converted = original.clone();
+            converted.convert_coordinates(to);
+            let mut transformed = original.clone();
+            let [x, y, z] = diagonal;
+            transformed.transform(&[[x, 0.0, 0.0, 0.0], [0.0, y, 0.0, 0.0], [0.0, 0.0, z, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+
+            assert_eq!(transformed.positions, converted.positions, "{:?}", to);
+            assert_covariances_match(&transformed, &converted);
+            assert_bytes_within("sh", &transformed.sh, &converted.sh, 1);
+        }
+    }
+}
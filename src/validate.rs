@@ -0,0 +1,61 @@
+// Structural validation of packed clouds, for data from untrusted sources. Unlike the report from
+// `diagnose`, validation stops at the first problem and only looks for ones which would make
+// decoding panic or produce garbage, so it's cheap enough to run on every load.
+
+use crate::error::SpzError;
+use crate::{dim_for_degree, PackedGaussians, MAX_SH_DEGREE};
+
+/// Largest number of fractional bits which still leaves room for the integer part of 24 bit
+/// fixed point positions
+pub const MAX_FRACTIONAL_BITS: usize = 23;
+
+// Exponent bits of a float16, all set for infinities and NaNs
+const HALF_EXPONENT_MASK: u16 = 0x7c00;
+
+impl PackedGaussians {
+    /// Checks that the cloud can be decoded safely: the buffers match the number of splats, the
+    /// SH degree and fractional bits are supported and no float16 position is infinite or NaN
+    pub fn validate(&self) -> Result<(), SpzError> {
+        let n = self.num_points;
+        if n > u32::MAX as usize {
+            return Err(SpzError::TooManyPoints { num_points: n, limit: u32::MAX as usize });
+        }
+        if self.sh_degree > MAX_SH_DEGREE {
+            return Err(SpzError::UnsupportedShDegree(self.sh_degree as u8));
+        }
+        if self.fractional_bits > MAX_FRACTIONAL_BITS {
+            return Err(SpzError::InvalidFractionalBits(self.fractional_bits));
+        }
+
+        self.check_field_lengths()?;
+
+        if self.uses_float16() {
+            let non_finite = self.positions.chunks_exact(2)
+                .position(|half| u16::from_le_bytes([half[0], half[1]]) & HALF_EXPONENT_MASK == HALF_EXPONENT_MASK);
+            if let Some(j) = non_finite {
+                return Err(SpzError::NonFinitePosition { index: j / 3 });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that each field holds as many bytes as the number of splats needs
+    pub(crate) fn check_field_lengths(&self) -> Result<(), SpzError> {
+        let n = self.num_points;
+        // Float16 positions are told apart by their length, so either size is valid
+        let position_bytes = self.position_encoding.size();
+        for (field, expected, got) in [
+            ("positions", n * position_bytes, self.positions.len()),
+            ("alphas", n, self.alphas.len()),
+            ("colors", n * 3, self.colors.len()),
+            ("scales", n * 3, self.scales.len()),
+            ("rotations", n * 3, self.rotations.len()),
+            ("sh", n * dim_for_degree(self.sh_degree) * 3, self.sh.len()),
+        ] {
+            if expected != got {
+                return Err(SpzError::InvalidFieldLength { field, expected, got });
+            }
+        }
+        Ok(())
+    }
+}
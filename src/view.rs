@@ -0,0 +1,337 @@
+// Borrowed, read only view of packed splats. Views can be created directly over a decompressed
+// .spz payload, such as a memory mapped cache file, without copying the field data, which
+// keeps load time and peak memory down for large scenes. Owned clouds decode through a view of
+// themselves, so both decode the same way.
+
+use crate::attributes::CustomAttributes;
+use crate::codec::{
+    decode_fixed24_batch, decode_quat3, half_to_f32_batch, unquantize_alpha, unquantize_color, unquantize_scale,
+    unquantize_scale_batch, unquantize_sh, unquantize_sh_batch,
+};
+use crate::color::{srgb8_table, ColorMode};
+use crate::coordinates::CoordinateSystem;
+use crate::error::SpzError;
+#[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+use crate::MIN_PARALLEL_RUN;
+use crate::{
+    dim_for_degree, read_header, unquantize_position, FormatVersion, LoadOptions, PackedGaussian, PackedGaussians, PositionEncoding, UnpackOptions,
+    UnpackedGaussian, UnpackedGaussians, UnpackedRun, FIELD_NAMES, FLAG_ANTIALIASED, MAX_SH_DIM,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct PackedGaussiansView<'a> {
+    pub num_points: usize,
+    pub sh_degree: usize,
+    pub fractional_bits: usize,
+    pub antialiased: bool,
+    pub position_encoding: PositionEncoding,
+    pub coordinate_system: CoordinateSystem,
+    pub positions: &'a [u8],
+    pub scales: &'a [u8],
+    pub rotations: &'a [u8],
+    pub alphas: &'a [u8],
+    pub colors: &'a [u8],
+    pub sh: &'a [u8],
+}
+
+impl<'a> PackedGaussiansView<'a> {
+    /// Borrows the fields of a decompressed .spz payload, after checking the header and that
+    /// every field is present. Bytes after the last field are ignored. Version 3 payloads are
+    /// rejected, since their rotations can't be read in place.
+    pub fn from_decompressed_bytes(bytes: &'a [u8]) -> Result<PackedGaussiansView<'a>, SpzError> {
+        let mut rest = bytes;
+        let header = read_header(&mut rest)?;
+        if header.format_version() == Some(FormatVersion::V3) {
+            return Err(SpzError::UnsupportedVersion(header.version));
+        }
+        let num_points = header.num_points as usize;
+        let sh_degree = header.sh_degree as usize;
+        let position_encoding = header.position_encoding();
+        let sizes = LoadOptions::default().check_header(&header)?;
+
+        let mut take = |field: usize| -> Result<&'a [u8], SpzError> {
+            let size = sizes[field];
+            if rest.len() < size {
+                return Err(SpzError::TruncatedField { field: FIELD_NAMES[field], expected: size, got: rest.len() });
+            }
+            let (data, remaining) = rest.split_at(size);
+            rest = remaining;
+            Ok(data)
+        };
+        let positions = take(0)?;
+        let alphas = take(1)?;
+        let colors = take(2)?;
+        let scales = take(3)?;
+        let rotations = take(4)?;
+        let sh = take(5)?;
+
+        Ok(PackedGaussiansView {
+            num_points,
+            sh_degree,
+            fractional_bits: header.fractional_bits as usize,
+            antialiased: (header.flags & FLAG_ANTIALIASED) != 0,
+            position_encoding,
+            coordinate_system: CoordinateSystem::RUB,
+            positions,
+            scales,
+            rotations,
+            alphas,
+            colors,
+            sh,
+        })
+    }
+
+    /// True for float16 positions, as in version 1 files
+    pub fn uses_float16(&self) -> bool {
+        self.position_encoding == PositionEncoding::Float16
+    }
+
+    /// Copies the view into an owned cloud
+    pub fn to_packed_gaussians(&self) -> PackedGaussians {
+        PackedGaussians {
+            num_points: self.num_points,
+            sh_degree: self.sh_degree,
+            fractional_bits: self.fractional_bits,
+            antialiased: self.antialiased,
+            positions: self.positions.to_vec(),
+            scales: self.scales.to_vec(),
+            rotations: self.rotations.to_vec(),
+            alphas: self.alphas.to_vec(),
+            colors: self.colors.to_vec(),
+            sh: self.sh.to_vec(),
+            attributes: CustomAttributes::default(),
+            position_encoding: self.position_encoding,
+            coordinate_system: self.coordinate_system,
+        }
+    }
+
+    pub fn at(&self, i: usize) -> PackedGaussian {
+        let mut result = PackedGaussian::default();
+        let position_bits = self.position_encoding.size();
+
+        let start3 = i * 3;
+        let p_start = i * position_bits;
+        result.position[..position_bits].copy_from_slice(&self.positions[p_start..p_start + position_bits]);
+        result.scale.copy_from_slice(&self.scales[start3..start3 + 3]);
+        result.rotation.copy_from_slice(&self.rotations[start3..start3 + 3]);
+        result.color.copy_from_slice(&self.colors[start3..start3 + 3]);
+        result.alpha = self.alphas[i];
+
+        let sh_dim = dim_for_degree(self.sh_degree);
+        let sh_start = i * sh_dim * 3;
+        for j in 0..sh_dim {
+            result.sh_r[j] = self.sh[sh_start + j * 3];
+            result.sh_g[j] = self.sh[sh_start + j * 3 + 1];
+            result.sh_b[j] = self.sh[sh_start + j * 3 + 2];
+        }
+        for j in sh_dim..MAX_SH_DIM {
+            result.sh_r[j] = 128;
+            result.sh_g[j] = 128;
+            result.sh_b[j] = 128;
+        }
+
+        result
+    }
+
+    // Single field decoders, for tools which only need some attributes of some splats. These
+    // read straight from the packed fields and decode the same as `unpack` with default options,
+    // except that they leave splats in the convention the cloud is stored in.
+
+    pub fn position(&self, i: usize) -> [f32; 3] {
+        let position_bits = self.position_encoding.size();
+        let p_start = i * position_bits;
+        unquantize_position(&self.positions[p_start..p_start + position_bits], self.uses_float16(), self.fractional_bits as u32)
+    }
+
+    /// w, x, y, z
+    pub fn rotation(&self, i: usize) -> [f32; 4] {
+        decode_quat3(&self.rotations[i * 3..i * 3 + 3])
+    }
+
+    /// Log scales along each axis
+    pub fn scale(&self, i: usize) -> [f32; 3] {
+        [0, 1, 2].map(|j| unquantize_scale(self.scales[i * 3 + j]))
+    }
+
+    /// DC color coefficients r, g, b
+    pub fn color(&self, i: usize) -> [f32; 3] {
+        [0, 1, 2].map(|j| unquantize_color(self.colors[i * 3 + j]))
+    }
+
+    /// Alpha before the sigmoid
+    pub fn alpha(&self, i: usize) -> f32 {
+        unquantize_alpha(self.alphas[i])
+    }
+
+    /// SH coefficients above degree 0 in order, each as r, g and b
+    pub fn sh(&self, i: usize) -> impl ExactSizeIterator<Item = [f32; 3]> + 'a {
+        let sh_stride = dim_for_degree(self.sh_degree) * 3;
+        self.sh[i * sh_stride..(i + 1) * sh_stride]
+            .chunks_exact(3)
+            .map(|c| [unquantize_sh(c[0]), unquantize_sh(c[1]), unquantize_sh(c[2])])
+    }
+
+    pub fn unpack(&self, i: usize) -> UnpackedGaussian {
+        self.unpack_with(i, &UnpackOptions::default())
+    }
+
+    pub fn unpack_with(&self, i: usize, options: &UnpackOptions) -> UnpackedGaussian {
+        let options = options.for_stored(self.coordinate_system);
+        self.at(i).unpack_with(self.uses_float16(), self.fractional_bits as u32, &options)
+    }
+
+    pub fn unpack_all(&self) -> UnpackedGaussians {
+        self.unpack_all_with(&UnpackOptions::default())
+    }
+
+    pub fn unpack_all_with(&self, options: &UnpackOptions) -> UnpackedGaussians {
+        let options = &options.for_stored(self.coordinate_system);
+        let mut result = UnpackedGaussians::zeroed(self.num_points, options.sh_degree(self.sh_degree), self.antialiased);
+
+        // Threads can't be spawned in the browser, so wasm builds always decode on the caller's
+        #[cfg(all(feature = "parallel", not(target_family = "wasm")))]
+        {
+            let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+            let run_length = self.num_points.div_ceil(threads).max(MIN_PARALLEL_RUN);
+            std::thread::scope(|scope| {
+                for (start, run) in result.runs(run_length) {
+                    scope.spawn(move || self.unpack_run(start, options, run));
+                }
+            });
+        }
+        #[cfg(not(all(feature = "parallel", not(target_family = "wasm"))))]
+        for (start, run) in result.runs(self.num_points.max(1)) {
+            self.unpack_run(start, options, run);
+        }
+
+        // Alpha bytes are already the opacity scaled to 0 to 255
+        if options.color_mode == ColorMode::Rgba8 {
+            let table = srgb8_table(options.deterministic);
+            result.rgba8 = self.colors.chunks_exact(3)
+                .zip(self.alphas)
+                .flat_map(|(color, &alpha)| [table[color[0] as usize], table[color[1] as usize], table[color[2] as usize], alpha])
+                .collect();
+        }
+
+        result
+    }
+
+    fn unpack_run(&self, start: usize, options: &UnpackOptions, run: UnpackedRun) {
+        let uses_float16 = self.uses_float16();
+        let position_bits = self.position_encoding.size();
+        let sh_stride = dim_for_degree(self.sh_degree) * 3;
+        let sh_degree = options.sh_degree(self.sh_degree);
+        let unpacked_sh_stride = dim_for_degree(sh_degree) * 3;
+        let end = start + run.alphas.len();
+
+        let packed_positions = &self.positions[start * position_bits..end * position_bits];
+        if uses_float16 {
+            half_to_f32_batch(packed_positions, run.positions);
+        } else {
+            decode_fixed24_batch(packed_positions, self.fractional_bits as u32, run.positions);
+        }
+        options.decode_rotation_batch(&self.rotations[start * 3..end * 3], run.rotations);
+        unquantize_scale_batch(&self.scales[start * 3..end * 3], run.scales);
+        options.decode_color_batch(&self.colors[start * 3..end * 3], run.colors);
+        options.unquantize_alpha_batch(&self.alphas[start..end], run.alphas);
+        if unpacked_sh_stride > 0 {
+            let packed_sh = self.sh[start * sh_stride..end * sh_stride].chunks_exact(sh_stride);
+            for (coefficients, packed) in run.sh.chunks_exact_mut(unpacked_sh_stride).zip(packed_sh) {
+                unquantize_sh_batch(packed, coefficients);
+            }
+        }
+
+        if let Some(conversion) = options.conversion() {
+            run.positions.chunks_exact_mut(3).for_each(|p| conversion.convert_position(p));
+            run.rotations.chunks_exact_mut(4).for_each(|r| conversion.convert_rotation(r));
+            run.scales.chunks_exact_mut(3).for_each(|s| conversion.convert_scale(s));
+            if unpacked_sh_stride > 0 {
+                for coefficients in run.sh.chunks_exact_mut(unpacked_sh_stride) {
+                    for channel in 0..3 {
+                        conversion.convert_sh(&mut coefficients[channel..], 3, sh_degree);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::ColorMode;
+    use crate::coordinates::CoordinateSystem;
+    use crate::testing::random_cloud;
+    use crate::{UnpackOptions, UnpackedGaussians};
+
+    use super::PackedGaussiansView;
+
+    fn assert_bits_eq(what: &str, i: usize, actual: &[f32], expected: &[f32]) {
+        let bits = |x: &[f32]| x.iter().map(|v| v.to_bits()).collect::<Vec<u32>>();
+        assert_eq!(bits(actual), bits(expected), "{} of splat {}", what, i);
+    }
+
+    fn assert_matches_single_splats(unpacked: &UnpackedGaussians, cloud: &crate::PackedGaussians, options: &UnpackOptions) {
+        let sh_stride = crate::dim_for_degree(unpacked.sh_degree) * 3;
+        for i in 0..cloud.num_points {
+            let single = cloud.unpack_with(i, options);
+            let batch = unpacked.at(i);
+            assert_bits_eq("position", i, &batch.position, &single.position);
+            assert_bits_eq("rotation", i, &batch.rotation, &single.rotation);
+            assert_bits_eq("scale", i, &batch.scale, &single.scale);
+            assert_bits_eq("color", i, &batch.color, &single.color);
+            assert_bits_eq("alpha", i, &[batch.alpha], &[single.alpha]);
+            let sh: Vec<f32> = (0..sh_stride / 3).flat_map(|j| [single.sh_r[j], single.sh_g[j], single.sh_b[j]]).collect();
+            assert_bits_eq("sh", i, &unpacked.sh[i * sh_stride..(i + 1) * sh_stride], &sh);
+        }
+    }
+
+    #[test]
+    fn unpack_all_matches_unpack() {
+        let cloud = random_cloud(2000, 3, 7);
+        let options = [
+            UnpackOptions::default(),
+            UnpackOptions::default().deterministic(true),
+            UnpackOptions::default().deterministic(true).color_mode(ColorMode::Srgb),
+            UnpackOptions::default().color_mode(ColorMode::Linear).identity_for_zero_rotation(true),
+            UnpackOptions::default().max_sh_degree(1),
+            UnpackOptions::default().deterministic(true).from(CoordinateSystem::RUB).to(CoordinateSystem::RDF),
+        ];
+        for options in options {
+            assert_matches_single_splats(&cloud.unpack_all_with(&options), &cloud, &options);
+        }
+    }
+
+    #[test]
+    fn deterministic_unpack_is_pinned() {
+        // Hash of every decoded bit of a fixed cloud, which has to be the same on every target
+        let cloud = random_cloud(500, 3, 11);
+        let unpacked = cloud.unpack_all_with(&UnpackOptions::default().deterministic(true));
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        for v in [&unpacked.positions, &unpacked.rotations, &unpacked.scales, &unpacked.colors, &unpacked.alphas, &unpacked.sh] {
+            for x in v.iter() {
+                hash = (hash ^ x.to_bits() as u64).wrapping_mul(0x100_0000_01b3);
+            }
+        }
+        assert_eq!(hash, 0x9def_acda_edd6_269d);
+    }
+
+    #[test]
+    fn version_1_float16_positions_match_the_loaded_cloud() {
+        use crate::selftest::{KNOWN_V1_FILE, KNOWN_V1_POSITIONS};
+        let view = PackedGaussiansView::from_decompressed_bytes(&KNOWN_V1_FILE).unwrap();
+        assert!(view.uses_float16());
+        let unpacked = view.unpack_all();
+        for (i, expected) in KNOWN_V1_POSITIONS.into_iter().enumerate() {
+            let expected = expected.map(f32::from_bits);
+            assert_eq!(view.position(i), expected);
+            assert_eq!(view.unpack(i).position, expected);
+            assert_eq!(unpacked.positions[i * 3..i * 3 + 3], expected);
+        }
+    }
+
+    #[test]
+    fn hostile_headers_are_truncated() {
+        let viewed = PackedGaussiansView::from_decompressed_bytes(&crate::selftest::HOSTILE_HEADER);
+        assert!(matches!(viewed, Err(crate::SpzError::TruncatedField { field: "positions", .. })));
+    }
+}